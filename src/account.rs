@@ -0,0 +1,363 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::api::{self, State};
+use crate::ban;
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::guard;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::time;
+
+const MIN_PASSWORD: usize = 8;
+const RESERVED: [&str; 4] = ["admin", "registered", "oidc", "onboarding"];
+
+pub fn secret(entity: &Entity) -> bool {
+    matches!(entity.tags.get("kind").map(String::as_str), Some("credential" | "token" | "session" | "hook" | "peer" | "contact" | "push" | "notify" | "marker" | "invite"))
+}
+
+pub async fn registered(store: &dyn Store, id: Uuid) -> bool {
+    matches!(
+        store.read(id).await,
+        Ok(Some(user)) if user.tags.get("registered").map(String::as_str) == Some("true")
+    )
+}
+
+pub async fn protect(store: &dyn Store, tags: &BTreeMap<String, String>, actor: Option<Uuid>, existing: Option<&Entity>) -> actix_web::Result<()> {
+    for key in RESERVED {
+        if tags.get(key) != existing.and_then(|e| e.tags.get(key)) {
+            return Err(actix_web::error::ErrorForbidden(format!("{} is set by the server", key)));
+        }
+    }
+    let kind = tags.get("kind").map(String::as_str);
+    if matches!(kind, Some("credential" | "token" | "contact" | "push" | "notify" | "feed")) {
+        return Err(actix_web::error::ErrorForbidden("use /register, /token, /email, /push, /notify, or /feed"));
+    }
+    if kind == Some("invite") {
+        return Err(actix_web::error::ErrorForbidden("use POST /invites or morce invite"));
+    }
+    if kind == Some("marker") {
+        return Err(actix_web::error::ErrorForbidden("use PUT /rooms/<room>/read"));
+    }
+    if kind == Some("session") {
+        return Err(actix_web::error::ErrorForbidden("sessions come from /login"));
+    }
+    if kind == Some("waiting") {
+        return Err(actix_web::error::ErrorForbidden("use /join to wait for a full room"));
+    }
+    if kind == Some("ban") {
+        return Err(actix_web::error::ErrorForbidden("use /ban"));
+    }
+    if matches!(kind, Some("poll" | "vote")) {
+        return Err(actix_web::error::ErrorForbidden("use /poll and /vote"));
+    }
+    if kind == Some("preview") {
+        return Err(actix_web::error::ErrorForbidden("link previews come from the server"));
+    }
+    if kind == Some("perm") && !grants(store, tags, actor).await {
+        return Err(actix_web::error::ErrorForbidden("only admins and the room's owner can grant permissions"));
+    }
+    if kind == Some("channel") {
+        let admin = is_admin(store, actor).await;
+        match existing {
+            None if !admin => return Err(actix_web::error::ErrorForbidden("use /join to create rooms")),
+            Some(channel) if !admin && tags.get("owner") != channel.tags.get("owner") => {
+                return Err(actix_web::error::ErrorForbidden("only admins can change a room's owner"));
+            }
+            _ => {}
+        }
+    }
+    if kind == Some("peer") && !is_admin(store, actor).await {
+        return Err(actix_web::error::ErrorForbidden("only admins can add peers"));
+    }
+    if let Some(from) = tags.get("from").and_then(|v| v.parse::<Uuid>().ok()) {
+        if actor != Some(from) && registered(store, from).await {
+            return Err(actix_web::error::ErrorForbidden("cannot post as a registered account"));
+        }
+    }
+    if kind == Some("hook") {
+        if let Some(user) = tags.get("as").and_then(|v| v.parse::<Uuid>().ok()) {
+            if actor != Some(user) && registered(store, user).await {
+                return Err(actix_web::error::ErrorForbidden("cannot add hooks for a registered account"));
+            }
+        }
+    }
+    if kind == Some("key") {
+        if let Some(user) = tags.get("user").and_then(|v| v.parse::<Uuid>().ok()) {
+            if actor != Some(user) && registered(store, user).await {
+                return Err(actix_web::error::ErrorForbidden("cannot publish keys for a registered account"));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn room_owner(store: &dyn Store, room: Option<&String>) -> Option<String> {
+    let room = room?.parse::<Uuid>().ok()?;
+    store.read(room).await.ok()??.tags.get("owner").cloned()
+}
+
+async fn grants(store: &dyn Store, tags: &BTreeMap<String, String>, actor: Option<Uuid>) -> bool {
+    let Some(actor) = actor else {
+        return false;
+    };
+    guard::is_admin(store, actor).await || room_owner(store, tags.get("what")).await == Some(actor.to_string())
+}
+
+pub async fn owner_only(store: &dyn Store, entity: &Entity, actor: Option<Uuid>) -> actix_web::Result<()> {
+    let owners = match entity.tags.get("kind").map(String::as_str) {
+        Some("user") => vec![Some(entity.id.to_string())],
+        Some("credential" | "token") => vec![entity.tags.get("user").cloned()],
+        Some("session") => vec![entity.tags.get("actor").cloned()],
+        Some("channel") => vec![entity.tags.get("owner").cloned()],
+        Some("perm") => vec![entity.tags.get("who").cloned(), room_owner(store, entity.tags.get("what")).await],
+        Some("waiting") => vec![entity.tags.get("user").cloned(), room_owner(store, entity.tags.get("room")).await],
+        Some("ban") => Vec::new(),
+        _ => return Ok(()),
+    };
+    let actor = actor.ok_or(actix_web::error::ErrorForbidden("sign in to change this entity"))?;
+    if owners.contains(&Some(actor.to_string())) || guard::is_admin(store, actor).await {
+        Ok(())
+    } else {
+        Err(actix_web::error::ErrorForbidden("only its owner or an admin can change this entity"))
+    }
+}
+
+async fn is_admin(store: &dyn Store, actor: Option<Uuid>) -> bool {
+    match actor {
+        Some(actor) => guard::is_admin(store, actor).await,
+        None => false,
+    }
+}
+
+async fn find(store: &dyn Store, name: &str) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "user".into());
+    pred.insert("name".into(), name.to_string());
+    pred.insert("registered".into(), "true".into());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+fn hash(password: &str) -> Result<String, String> {
+    let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes()).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn verify(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash)
+        .is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+async fn credential(store: &dyn Store, user: Uuid) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "credential".into());
+    pred.insert("user".into(), user.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+pub struct Register;
+
+#[async_trait]
+impl Command for Register {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "<password>", about: "help.register" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("register.signin"))?;
+        if args.is_empty() {
+            return Err(tr("register.usage").into());
+        }
+        if args.chars().count() < MIN_PASSWORD {
+            return Err(tr("register.short").into());
+        }
+        let store = &*ctx.state.store;
+        let user = store.read(actor).await.map_err(|e| e.to_string())?
+            .ok_or(tr("status.nouser"))?;
+        if user.tags.get("registered").map(String::as_str) == Some("true") {
+            return Err(tr("register.taken").into());
+        }
+        let name = user.tags.get("name").cloned().unwrap_or_else(|| user.id.to_string());
+        if find(store, &name).await.is_some() {
+            return Err(tr("register.taken").into());
+        }
+
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "credential".into());
+        tags.insert("user".into(), actor.to_string());
+        store.create(hash(args)?.into(), tags).await.map_err(|e| e.to_string())?;
+
+        let mut tags = user.tags.clone();
+        tags.insert("registered".into(), "true".into());
+        let user = store.update(user.id, user.load.clone(), tags).await.map_err(|e| e.to_string())?;
+        ctx.state.hub.publish(&user);
+        Ok(trf("register.done", &[&name]))
+    }
+}
+
+pub struct Token;
+
+#[async_trait]
+impl Command for Token {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "", about: "help.token" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("token.signin"))?;
+        if !registered(&*ctx.state.store, actor).await {
+            return Err(tr("token.signin").into());
+        }
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "token".into());
+        tags.insert("user".into(), actor.to_string());
+        let token = ctx.state.store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+        Ok(trf("token.issued", &[&token.id.to_string()]))
+    }
+}
+
+pub async fn login(state: web::Data<State>, req: HttpRequest, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let payload: Value = serde_json::from_slice(&body)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid json"))?;
+    let field = |key: &str| payload.get(key).and_then(Value::as_str);
+    let store = &*state.store;
+
+    let (user, via) = if let Some(id_token) = field("id_token") {
+        let oidc = state.oidc.as_ref().ok_or(actix_web::error::ErrorNotFound("oidc is not configured"))?;
+        let user = oidc.sign_in(store, id_token).await
+            .map_err(actix_web::error::ErrorUnauthorized)?;
+        (Some(user), "oidc")
+    } else if let Some(token) = field("token") {
+        let token = token.parse::<Uuid>().ok();
+        let user = match token {
+            Some(id) => store.read(id).await?
+                .filter(|t| t.tags.get("kind").map(String::as_str) == Some("token"))
+                .and_then(|t| t.tags.get("user")?.parse::<Uuid>().ok()),
+            None => None,
+        };
+        (user, "token")
+    } else if let Some(guest) = field("guest") {
+        let user = match guest.parse::<Uuid>() {
+            Ok(id) => store.read(id).await?
+                .filter(|u| u.tags.get("kind").map(String::as_str) == Some("user"))
+                .filter(|u| u.tags.get("registered").map(String::as_str) != Some("true"))
+                .map(|u| u.id),
+            Err(_) => None,
+        };
+        (user, "guest")
+    } else {
+        let (Some(name), Some(password)) = (field("name"), field("password")) else {
+            return Err(actix_web::error::ErrorBadRequest("expected name and password, token, or guest"));
+        };
+        let mut user = None;
+        if let Some(found) = find(store, name).await {
+            if credential(store, found.id).await.is_some_and(|c| verify(&String::from_utf8_lossy(&c.load), password)) {
+                user = Some(found.id);
+            }
+        }
+        (user, "password")
+    };
+    let user = user.ok_or(actix_web::error::ErrorUnauthorized("invalid credentials"))?;
+    if ban::user_banned(store, user).await {
+        return Err(actix_web::error::ErrorForbidden("banned"));
+    }
+    promote(&state, user).await?;
+
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "session".into());
+    tags.insert("actor".into(), user.to_string());
+    tags.insert("via".into(), via.into());
+    if let Some(agent) = req.headers().get("User-Agent").and_then(|v| v.to_str().ok()) {
+        tags.insert("device".into(), layout::truncate(&agent.replace([',', '='], " "), 48));
+    }
+    let session = store.create(bytes::Bytes::new(), tags).await?;
+    Ok(HttpResponse::Created()
+        .insert_header(("x-entity-id", session.id.to_string()))
+        .insert_header(("x-entity-tags", api::tags_header(&session.tags)))
+        .body(session.id.to_string()))
+}
+
+async fn promote(state: &State, user: Uuid) -> actix_web::Result<()> {
+    let Some(entity) = state.store.read(user).await? else {
+        return Ok(());
+    };
+    let listed = entity.tags.get("name").is_some_and(|name| state.admins.contains(name));
+    let registered = entity.tags.get("registered").map(String::as_str) == Some("true");
+    if listed && registered && entity.tags.get("admin").map(String::as_str) != Some("true") {
+        let mut tags = entity.tags.clone();
+        tags.insert("admin".into(), "true".into());
+        state.store.update(entity.id, entity.load.clone(), tags).await?;
+    }
+    Ok(())
+}
+
+pub struct Sessions;
+
+#[async_trait]
+impl Command for Sessions {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "[revoke <id>|revoke all]", about: "help.sessions" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("sessions.signin"))?;
+        let store = &*ctx.state.store;
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "session".into());
+        pred.insert("actor".into(), actor.to_string());
+        let mut sessions = store.query(&pred).await.map_err(|e| e.to_string())?;
+        sessions.sort_by_key(|s| s.at);
+
+        let mut parts = args.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (None, _) => {
+                let rows: Vec<Vec<String>> = sessions.iter()
+                    .map(|s| vec![
+                        s.id.to_string()[..8].to_string(),
+                        s.tags.get("via").cloned().unwrap_or_else(|| "-".into()),
+                        s.tags.get("device").cloned().unwrap_or_else(|| "-".into()),
+                        time::iso_millis(s.at),
+                    ])
+                    .collect();
+                Ok(layout::table(&[tr("sessions.id"), tr("sessions.via"), tr("sessions.device"), tr("sessions.since")], &rows))
+            }
+            (Some("revoke"), Some(target)) => {
+                let doomed: Vec<&Entity> = sessions.iter()
+                    .filter(|s| target == "all" || s.id.to_string().starts_with(target))
+                    .collect();
+                if doomed.is_empty() {
+                    return Err(trf("sessions.none", &[target]));
+                }
+                for session in &doomed {
+                    store.delete(session.id).await.map_err(|e| e.to_string())?;
+                    ctx.state.replay.forget(session.id);
+                }
+                Ok(trf("sessions.revoked", &[&doomed.len().to_string()]))
+            }
+            _ => Err(tr("sessions.usage").into()),
+        }
+    }
+}
@@ -1,18 +1,70 @@
 use actix_web::{web, HttpRequest, HttpResponse};
 use std::collections::BTreeMap;
+use std::net::IpAddr;
 use uuid::Uuid;
 
+use crate::account;
+use crate::ban::Bans;
+use crate::command::Commands;
+use crate::entity::Entity;
+use crate::federation::Federation;
+use crate::feed::Feeds;
+use crate::dedup::{Claim, Dedup};
 use crate::hub::Hub;
 use crate::memory::Memory;
+use crate::oidc::Oidc;
 use crate::predicate::Predicate;
+use crate::pow::Pow;
+use crate::quota::{self, Quota};
+use crate::record::Recorder;
+use crate::replay::Replay;
+use crate::retention;
+use crate::room::{self, Rooms};
+use crate::stats::Stats;
 use crate::store::Store;
 use crate::format;
 use crate::parse;
 use crate::guard;
+use crate::hooks::Hooks;
+use crate::log::Level;
+use crate::mail::Mail;
+use crate::names::{self, Policy, Resolution};
+use crate::presence::Online;
+use crate::push::Relay;
+use crate::time::{self, Clock};
+use crate::translate::Translator;
+use crate::unfurl::Unfurler;
+use crate::welcome::{self, Welcome};
+use crate::ws::Deadlines;
+use crate::xlog;
 
 pub struct State {
     pub store: std::sync::Arc<Memory>,
     pub hub: std::sync::Arc<Hub>,
+    pub commands: std::sync::Arc<Commands>,
+    pub dedup: std::sync::Arc<Dedup>,
+    pub stats: std::sync::Arc<Stats>,
+    pub pow: std::sync::Arc<Pow>,
+    pub oidc: Option<std::sync::Arc<Oidc>>,
+    pub replay: std::sync::Arc<Replay>,
+    pub federation: std::sync::Arc<Federation>,
+    pub deadlines: Deadlines,
+    pub names: Policy,
+    pub draining: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    pub quota: std::sync::Arc<Quota>,
+    pub welcome: Option<std::sync::Arc<Welcome>>,
+    pub rooms: std::sync::Arc<Rooms>,
+    pub hooks: std::sync::Arc<Hooks>,
+    pub online: std::sync::Arc<Online>,
+    pub mail: Option<std::sync::Arc<Mail>>,
+    pub push: Option<std::sync::Arc<Relay>>,
+    pub feeds: std::sync::Arc<Feeds>,
+    pub translator: Option<std::sync::Arc<Translator>>,
+    pub unfurl: Option<std::sync::Arc<Unfurler>>,
+    pub recorder: Option<std::sync::Arc<Recorder>>,
+    pub bans: std::sync::Arc<Bans>,
+    pub admins: Vec<String>,
+    pub clock: std::sync::Arc<dyn Clock>,
 }
 
 fn extract_tags(req: &HttpRequest) -> BTreeMap<String, String> {
@@ -24,7 +76,7 @@ fn extract_tags(req: &HttpRequest) -> BTreeMap<String, String> {
     BTreeMap::new()
 }
 
-fn tags_header(tags: &BTreeMap<String, String>) -> String {
+pub fn tags_header(tags: &BTreeMap<String, String>) -> String {
     let mut parts = Vec::new();
     for (k, v) in tags {
         parts.push(format!("{}={}", k, v));
@@ -38,6 +90,96 @@ fn extract_filename(req: &HttpRequest) -> Option<String> {
         .map(|s| s.to_string())
 }
 
+pub struct Post {
+    pub actor: Option<Uuid>,
+    pub session: Option<Uuid>,
+    pub request_id: Option<String>,
+    pub seq: Option<u64>,
+    pub ip: Option<IpAddr>,
+    pub tags: BTreeMap<String, String>,
+    pub body: web::Bytes,
+}
+
+pub enum Posted {
+    Created(Entity, Option<Resolution>),
+    Duplicate(Entity),
+    Private(Entity),
+}
+
+pub async fn post(state: &State, post: Post) -> actix_web::Result<Posted> {
+    check_post(state, post.actor, &post.tags, &post.body).await?;
+
+    let sender = post.session.map(|session| session.to_string()).or_else(|| post.ip.map(|ip| ip.to_string()));
+    let request_key = sender.zip(post.request_id.as_deref()).map(|(sender, rid)| format!("{}:{}", sender, rid));
+    if let Some(key) = &request_key {
+        match state.dedup.claim(key) {
+            Claim::Fresh => {}
+            Claim::Full => {
+                xlog!(Level::Warn, target: "dedup", "x-request-id cache is full");
+                return Err(actix_web::error::ErrorServiceUnavailable("too many requests in flight, retry later"));
+            }
+            Claim::Pending => return Err(actix_web::error::ErrorConflict("a request with this x-request-id is in progress")),
+            Claim::Done(id) => {
+                return match state.store.read(id).await? {
+                    Some(entity) => Ok(Posted::Duplicate(entity)),
+                    None => Err(actix_web::error::ErrorGone("the entity created for this x-request-id was deleted")),
+                };
+            }
+        }
+    }
+    let result = admit(state, post).await;
+    if let Some(key) = request_key {
+        match &result {
+            Ok(Posted::Created(entity, _)) => state.dedup.remember(key, entity.id),
+            _ => state.dedup.release(&key),
+        }
+    }
+    result
+}
+
+async fn admit(state: &State, post: Post) -> actix_web::Result<Posted> {
+    let claimed = match post.seq {
+        Some(seq) => {
+            let session = post.session.ok_or(actix_web::error::ErrorBadRequest("x-seq needs a session"))?;
+            if !state.replay.accept_seq(session, seq) {
+                state.stats.record_replay();
+                return Err(actix_web::error::ErrorConflict("replayed sequence number"));
+            }
+            Some((session, seq))
+        }
+        None => None,
+    };
+    let result = deliver(state, post).await;
+    if let (Some((session, seq)), Err(_)) = (claimed, &result) {
+        state.replay.release_seq(session, seq);
+    }
+    result
+}
+
+async fn deliver(state: &State, post: Post) -> actix_web::Result<Posted> {
+    let Post { actor, session, ip, mut tags, body, .. } = post;
+    if let Some(nonce) = tags.get("nonce") {
+        if !state.replay.accept_nonce(nonce) {
+            state.stats.record_replay();
+            return Err(actix_web::error::ErrorConflict("replayed nonce"));
+        }
+    }
+
+    if tags.get("kind").map(String::as_str) == Some("msg") && state.commands.is_private(&body) {
+        let actor = actor.ok_or(guard::Denied::NoSession)?;
+        let mut direct = BTreeMap::new();
+        direct.insert("kind".into(), "msg".into());
+        direct.insert("from".into(), actor.to_string());
+        let reply = state.commands.dispatch(state, &Entity::new(body, direct, state.clock.millis()), Some(actor), session).await
+            .ok_or(actix_web::error::ErrorInternalServerError("command failed"))?;
+        return Ok(Posted::Private(reply));
+    }
+
+    let resolution = names::resolve(state, ip, &mut tags).await?;
+    let entity = accept(state, actor, session, ip.map(|ip| ip.to_string()), tags, body).await?;
+    Ok(Posted::Created(entity, resolution))
+}
+
 pub async fn create(
     state: web::Data<State>,
     req: HttpRequest,
@@ -46,18 +188,85 @@ pub async fn create(
     let mut tags = extract_tags(&req);
     let resource = tags.get("in").and_then(|v| v.parse::<Uuid>().ok());
     let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
-    guard::check(&*state.store, auth_header, "create", resource).await?;
-
+    let actor = guard::check(&*state.store, auth_header, "create", resource).await?;
+    let seq = match req.headers().get("x-seq").and_then(|v| v.to_str().ok()) {
+        Some(raw) => Some(raw.parse::<u64>().map_err(|_| actix_web::error::ErrorBadRequest("x-seq must be a number"))?),
+        None => None,
+    };
     if let Some(filename) = extract_filename(&req) {
         tags.entry("filename".into()).or_insert(filename);
     }
 
+    let posted = post(&state, Post {
+        actor,
+        session: guard::session_id(auth_header).and_then(Result::ok),
+        request_id: req.headers().get("x-request-id").and_then(|v| v.to_str().ok()).map(str::to_string),
+        seq,
+        ip: req.peer_addr().map(|addr| addr.ip()),
+        tags,
+        body,
+    }).await?;
+    let (entity, resolution) = match posted {
+        Posted::Created(entity, resolution) => (entity, resolution),
+        Posted::Duplicate(entity) => {
+            return Ok(HttpResponse::Ok()
+                .insert_header(("x-entity-id", entity.id.to_string()))
+                .insert_header(("x-entity-at", time::iso_millis(entity.at)))
+                .insert_header(("x-entity-tags", tags_header(&entity.tags)))
+                .insert_header(("x-duplicate", "true"))
+                .body(entity.load));
+        }
+        Posted::Private(reply) => {
+            return Ok(HttpResponse::Accepted()
+                .insert_header(("x-entity-id", reply.id.to_string()))
+                .finish());
+        }
+    };
+    let mut response = HttpResponse::Created();
+    response
+        .insert_header(("x-entity-id", entity.id.to_string()))
+        .insert_header(("x-entity-at", time::iso_millis(entity.at)))
+        .insert_header(("x-entity-tags", tags_header(&entity.tags)));
+    if let Some(resolution) = resolution {
+        response.insert_header(("x-name-resolution", resolution.to_string()));
+    }
+    Ok(response.body(entity.load.clone()))
+}
+
+pub async fn check_post(
+    state: &State,
+    actor: Option<Uuid>,
+    tags: &BTreeMap<String, String>,
+    body: &[u8],
+) -> actix_web::Result<()> {
+    account::protect(&*state.store, tags, actor, None).await?;
+    room::check_post(state, actor, tags).await?;
+    welcome::check_post(state, actor, tags, body).await
+}
+
+pub async fn accept(
+    state: &State,
+    actor: Option<Uuid>,
+    session: Option<Uuid>,
+    peer: Option<String>,
+    tags: BTreeMap<String, String>,
+    body: web::Bytes,
+) -> actix_web::Result<Entity> {
+    quota::enforce(state, actor, peer, &tags, body.len()).await?;
+    let (mut tags, body) = state.hooks.filter(state, actor, tags, body).await?;
+    let greet = welcome::prepare(state, &mut tags).await;
     let entity = state.store.create(body, tags).await?;
+    if greet {
+        welcome::greet(state, &entity).await;
+    }
+    room::arrive(state, &entity).await;
+    state.stats.record_in(&entity);
     state.hub.publish(&entity);
-    Ok(HttpResponse::Created()
-        .insert_header(("x-entity-id", entity.id.to_string()))
-        .insert_header(("x-entity-tags", tags_header(&entity.tags)))
-        .body(entity.load.clone()))
+    state.commands.dispatch(state, &entity, actor, session).await;
+    if retention::ephemeral(&*state.store, &entity).await {
+        state.store.delete(entity.id).await?;
+    }
+    Ok(entity)
 }
 
 pub async fn read(
@@ -65,9 +274,11 @@ pub async fn read(
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let id = path.into_inner();
-    if let Some(entity) = state.store.read(id).await? {
+    if let Some(entity) = state.store.read(id).await?.filter(|e| !account::secret(e)) {
+        state.stats.record_out(entity.load.len());
         Ok(HttpResponse::Ok()
             .insert_header(("x-entity-id", entity.id.to_string()))
+            .insert_header(("x-entity-at", time::iso_millis(entity.at)))
             .insert_header(("x-entity-tags", tags_header(&entity.tags)))
             .body(entity.load))
     } else {
@@ -84,16 +295,24 @@ pub async fn update(
     let id = path.into_inner();
     let mut tags = extract_tags(&req);
     let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
-    guard::check(&*state.store, auth_header, "update", Some(id)).await?;
+    let actor = guard::check(&*state.store, auth_header, "update", Some(id)).await?;
+    let existing = state.store.read(id).await?.ok_or(actix_web::error::ErrorNotFound("not found"))?;
+    account::owner_only(&*state.store, &existing, actor).await?;
+    account::protect(&*state.store, &tags, actor, Some(&existing)).await?;
+    room::check_post(&state, actor, &tags).await?;
 
     if let Some(filename) = extract_filename(&req) {
         tags.entry("filename".into()).or_insert(filename);
     }
+    let peer = req.peer_addr().map(|addr| addr.ip().to_string());
+    quota::enforce(&state, actor, peer, &tags, body.len()).await?;
 
     let entity = state.store.update(id, body, tags).await?;
+    state.stats.record_in(&entity);
     state.hub.publish(&entity);
     Ok(HttpResponse::Ok()
         .insert_header(("x-entity-id", entity.id.to_string()))
+        .insert_header(("x-entity-at", time::iso_millis(entity.at)))
         .insert_header(("x-entity-tags", tags_header(&entity.tags)))
         .body(entity.load))
 }
@@ -105,9 +324,19 @@ pub async fn delete(
 ) -> actix_web::Result<HttpResponse> {
     let id = path.into_inner();
     let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
-    guard::check(&*state.store, auth_header, "delete", Some(id)).await?;
+    let actor = guard::check(&*state.store, auth_header, "delete", Some(id)).await?;
 
+    let entity = state.store.read(id).await?;
+    if let Some(entity) = &entity {
+        account::owner_only(&*state.store, entity, actor).await?;
+    }
     state.store.delete(id).await?;
+    if let Some(entity) = entity {
+        if entity.tags.get("kind").map(String::as_str) == Some("ban") {
+            state.bans.refresh(&*state.store).await;
+        }
+        room::released(&state, &entity).await;
+    }
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -122,10 +351,13 @@ pub async fn query(
     }) {
         predicate.insert(key, val);
     }
-    let entities = state.store.query(&predicate).await?;
+    let mut entities = state.store.query(&predicate).await?;
+    entities.retain(|e| !account::secret(e));
+    let body = format::entity_list(&entities);
+    state.stats.record_out(body.len());
     Ok(HttpResponse::Ok()
         .content_type("text/plain; charset=utf-8")
-        .body(format::entity_list(&entities)))
+        .body(body))
 }
 
 pub async fn eval(
@@ -140,12 +372,43 @@ pub async fn eval(
     let test = query_entity.tags.get("test").ok_or(actix_web::error::ErrorBadRequest("missing test"))?;
     let value = query_entity.tags.get("value").ok_or(actix_web::error::ErrorBadRequest("missing value"))?;
 
-    let entities = match test.as_str() {
+    let mut entities = match test.as_str() {
         "prefix" => state.store.query_prefix(key, value).await?,
         _ => return Err(actix_web::error::ErrorBadRequest("unknown test")),
     };
+    entities.retain(|e| !account::secret(e));
 
     Ok(HttpResponse::Ok()
         .content_type("text/plain; charset=utf-8")
         .body(format::entity_list(&entities)))
 }
+
+pub async fn help(state: web::Data<State>, req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+    let admin = match guard::check(&*state.store, auth_header, "read", None).await? {
+        Some(actor) => guard::is_admin(&*state.store, actor).await,
+        None => false,
+    };
+    let body = state.commands.render(req.match_info().get("name"), admin)
+        .map_err(actix_web::error::ErrorNotFound)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(body))
+}
+
+pub async fn commands(state: web::Data<State>) -> HttpResponse {
+    let mut out = String::new();
+    for name in state.commands.names() {
+        out.push_str(name);
+        out.push('\n');
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(out)
+}
+
+pub async fn stats(state: web::Data<State>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(state.stats.render())
+}
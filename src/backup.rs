@@ -0,0 +1,174 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::entity::Entity;
+use crate::guard;
+use crate::log::Level;
+use crate::store::Store;
+use crate::xlog;
+
+pub const USAGE: &str = "usage: morce backup|restore [--target ADDR] --session ID (--out FILE | --in FILE)";
+pub const MAX_SNAPSHOT: usize = 256 << 20;
+const MAX_EXPANDED: u64 = MAX_SNAPSHOT as u64 * 4;
+
+const LEVEL: i32 = 3;
+
+pub fn encode(entities: &[Entity]) -> std::io::Result<Vec<u8>> {
+    let mut lines = String::new();
+    for entity in entities {
+        let line = json!({
+            "id": entity.id,
+            "at": entity.at,
+            "tags": entity.tags,
+            "load": STANDARD.encode(&entity.load),
+        });
+        lines.push_str(&line.to_string());
+        lines.push('\n');
+    }
+    zstd::encode_all(lines.as_bytes(), LEVEL)
+}
+
+pub fn decode(raw: &[u8]) -> Result<Vec<Entity>, String> {
+    let decoder = zstd::Decoder::new(raw).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(decoder.take(MAX_EXPANDED + 1));
+    let mut entities = Vec::new();
+    let mut line = String::new();
+    for n in 1.. {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| format!("snapshot is not zstd json lines: {}", e))? == 0 {
+            break;
+        }
+        if reader.get_ref().limit() == 0 {
+            return Err(format!("snapshot expands to more than {} bytes", MAX_EXPANDED));
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        entities.push(entity(&line).ok_or(format!("bad entity on line {}", n))?);
+    }
+    Ok(entities)
+}
+
+fn entity(line: &str) -> Option<Entity> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let tags: BTreeMap<String, String> = value.get("tags")?.as_object()?.iter()
+        .map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+        .collect::<Option<_>>()?;
+    Some(Entity {
+        id: value.get("id")?.as_str()?.parse::<Uuid>().ok()?,
+        at: value.get("at")?.as_u64()?,
+        load: STANDARD.decode(value.get("load")?.as_str()?).ok()?.into(),
+        tags,
+    })
+}
+
+async fn require_admin(state: &State, req: &HttpRequest, action: &str) -> actix_web::Result<Uuid> {
+    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+    let actor = guard::check(&*state.store, auth_header, action, None).await?
+        .ok_or(guard::Denied::NoSession)?;
+    if !guard::is_admin(&*state.store, actor).await {
+        return Err(actix_web::error::ErrorForbidden("only admins can back up or restore"));
+    }
+    Ok(actor)
+}
+
+pub async fn export(state: web::Data<State>, req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let actor = require_admin(&state, &req, "backup").await?;
+    let entities = state.store.snapshot().await?;
+    let body = web::block(move || encode(&entities)).await??;
+    xlog!(Level::Info, target: "backup", "{} took a snapshot ({} bytes)", actor, body.len());
+    Ok(HttpResponse::Ok().content_type("application/zstd").body(body))
+}
+
+pub async fn import(state: web::Data<State>, req: HttpRequest, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let actor = require_admin(&state, &req, "restore").await?;
+    let entities = web::block(move || decode(&body)).await?
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let count = entities.len();
+    state.store.restore(entities).await?;
+    state.bans.refresh(&*state.store).await;
+    xlog!(Level::Warn, target: "backup", "{} restored a snapshot of {} entities", actor, count);
+    Ok(HttpResponse::Ok().body(count.to_string()))
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub target: String,
+    pub session: String,
+    pub file: String,
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I, file_flag: &str) -> Result<Options, String> {
+        let mut target = "127.0.0.1:8080".to_string();
+        let (mut session, mut file) = (None, None);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--target" => target = value,
+                "--session" => session = Some(value),
+                f if f == file_flag => file = Some(value),
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        Ok(Options {
+            target,
+            session: session.ok_or(format!("--session is required\n{}", USAGE))?,
+            file: file.ok_or(format!("{} is required\n{}", file_flag, USAGE))?,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        let base = self.target.trim_end_matches('/');
+        if base.contains("://") {
+            format!("{}{}", base, path)
+        } else {
+            format!("http://{}{}", base, path)
+        }
+    }
+}
+
+async fn check(response: reqwest::Response) -> Result<bytes::Bytes, String> {
+    let status = response.status();
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, String::from_utf8_lossy(&body)));
+    }
+    Ok(body)
+}
+
+pub async fn backup(options: Options) -> Result<String, String> {
+    let response = reqwest::Client::new()
+        .get(options.url("/backup"))
+        .bearer_auth(&options.session)
+        .send().await.map_err(|e| e.to_string())?;
+    let body = check(response).await?;
+    let count = decode(&body)?.len();
+    let partial = format!("{}.partial", options.file);
+    tokio::fs::write(&partial, &body).await.map_err(|e| e.to_string())?;
+    tokio::fs::rename(&partial, &options.file).await.map_err(|e| e.to_string())?;
+    Ok(format!("wrote {} entities ({} bytes) to {}", count, body.len(), options.file))
+}
+
+pub async fn restore(options: Options) -> Result<String, String> {
+    let body = tokio::fs::read(&options.file).await.map_err(|e| format!("{}: {}", options.file, e))?;
+    let count = decode(&body)?.len();
+    let response = reqwest::Client::new()
+        .post(options.url("/restore"))
+        .bearer_auth(&options.session)
+        .body(body)
+        .send().await.map_err(|e| e.to_string())?;
+    check(response).await?;
+    Ok(format!("restored {} entities from {}", count, options.file))
+}
@@ -0,0 +1,296 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::guard;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::task;
+use crate::time::{self, Clock};
+use crate::xlog;
+
+const SWEEP_EVERY: Duration = Duration::from_secs(10);
+
+pub struct Net {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Net {
+    pub fn parse(raw: &str) -> Option<Net> {
+        let (addr, prefix) = match raw.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+            None => (raw.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Net { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn parse_duration(raw: &str) -> Option<Duration> {
+    let split = raw.find(|c: char| !c.is_ascii_digit())?;
+    let count: u64 = raw[..split].parse().ok()?;
+    let unit = match &raw[split..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+}
+
+fn until(ban: &Entity) -> Option<u64> {
+    ban.tags.get("until").and_then(|v| v.parse::<u64>().ok())
+}
+
+fn expired(until: Option<u64>, now: u64) -> bool {
+    until.is_some_and(|until| until <= now)
+}
+
+async fn active(store: &dyn Store, mut pred: Predicate) -> Vec<Entity> {
+    pred.insert("kind".into(), "ban".into());
+    let Ok(mut bans) = store.query(&pred).await else {
+        return Vec::new();
+    };
+    let now = store.now();
+    bans.retain(|ban| !expired(until(ban), now));
+    bans
+}
+
+pub async fn user_banned(store: &dyn Store, actor: Uuid) -> bool {
+    let mut pred = Predicate::new();
+    pred.insert("user".into(), actor.to_string());
+    !active(store, pred).await.is_empty()
+}
+
+pub struct Bans {
+    nets: RwLock<Vec<(Net, Option<u64>)>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Bans {
+    fn default() -> Self {
+        Self::new(time::system())
+    }
+}
+
+impl Bans {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { nets: RwLock::new(Vec::new()), clock }
+    }
+
+    pub async fn refresh(&self, store: &dyn Store) {
+        let nets = active(store, Predicate::new()).await
+            .iter()
+            .filter_map(|ban| Some((Net::parse(ban.tags.get("net")?)?, until(ban))))
+            .collect();
+        *self.nets.write().unwrap() = nets;
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let now = self.clock.millis();
+        self.nets.read().unwrap()
+            .iter()
+            .any(|(net, until)| !expired(*until, now) && net.contains(ip))
+    }
+}
+
+async fn sweep(state: &State) -> usize {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "ban".into());
+    let now = state.clock.millis();
+    let mut removed = 0;
+    for ban in state.store.query(&pred).await.unwrap_or_default() {
+        if expired(until(&ban), now) && state.store.delete(ban.id).await.is_ok() {
+            removed += 1;
+        }
+    }
+    state.bans.refresh(&*state.store).await;
+    removed
+}
+
+pub fn start(state: web::Data<State>) {
+    task::spawn("bans", async move {
+        let mut ticker = tokio::time::interval(SWEEP_EVERY);
+        loop {
+            ticker.tick().await;
+            let removed = sweep(&state).await;
+            if removed > 0 {
+                xlog!(Level::Info, target: "ban", "{} bans expired", removed);
+            }
+        }
+    });
+}
+
+pub async fn reject(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    if let (Some(state), Some(ip)) = (req.app_data::<web::Data<State>>(), ip) {
+        if state.bans.contains(ip) {
+            return Err(actix_web::error::ErrorForbidden("banned"));
+        }
+    }
+    next.call(req).await
+}
+
+async fn find_user(store: &dyn Store, who: &str) -> Option<Uuid> {
+    if let Ok(id) = who.parse::<Uuid>() {
+        return Some(id);
+    }
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "user".into());
+    pred.insert("name".into(), who.to_string());
+    store.query(&pred).await.ok()?.first().map(|user| user.id)
+}
+
+async fn require_admin(ctx: &Context<'_>) -> Result<Uuid, String> {
+    let actor = ctx.actor.ok_or(tr("ban.admin"))?;
+    if guard::is_admin(&*ctx.state.store, actor).await {
+        Ok(actor)
+    } else {
+        Err(tr("ban.admin").into())
+    }
+}
+
+pub struct Ban;
+
+#[async_trait]
+impl Command for Ban {
+    fn help(&self) -> Help {
+        Help { category: Category::Admin, role: Role::Admin, usage: "<user|ip|cidr> [duration]", about: "help.ban" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = require_admin(ctx).await?;
+        let mut parts = args.split_whitespace();
+        let target = parts.next().ok_or(tr("ban.usage"))?;
+        let until = match parts.next() {
+            Some(raw) => {
+                let span = parse_duration(raw).and_then(|span| u64::try_from(span.as_millis()).ok());
+                Some(span.and_then(|span| ctx.state.clock.millis().checked_add(span)).ok_or(tr("ban.duration"))?)
+            }
+            None => None,
+        };
+
+        let store = &*ctx.state.store;
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "ban".into());
+        tags.insert("target".into(), target.to_string());
+        tags.insert("by".into(), actor.to_string());
+        if Net::parse(target).is_some() {
+            tags.insert("net".into(), target.to_string());
+        } else {
+            let user = find_user(store, target).await.ok_or_else(|| trf("ban.nouser", &[target]))?;
+            tags.insert("user".into(), user.to_string());
+        }
+        if let Some(until) = until {
+            tags.insert("until".into(), until.to_string());
+        }
+        let ban = store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+        ctx.state.bans.refresh(store).await;
+        ctx.state.hub.publish(&ban);
+
+        Ok(match until {
+            Some(until) => trf("ban.until", &[target, &time::iso_millis(until)]),
+            None => trf("ban.added", &[target]),
+        })
+    }
+}
+
+pub struct Unban;
+
+#[async_trait]
+impl Command for Unban {
+    fn help(&self) -> Help {
+        Help { category: Category::Admin, role: Role::Admin, usage: "<user|ip|cidr>", about: "help.unban" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        require_admin(ctx).await?;
+        if args.is_empty() {
+            return Err(tr("unban.usage").into());
+        }
+        let mut pred = Predicate::new();
+        pred.insert("target".into(), args.to_string());
+        let bans = active(&*ctx.state.store, pred).await;
+        if bans.is_empty() {
+            return Err(trf("unban.none", &[args]));
+        }
+        for ban in bans {
+            ctx.state.store.delete(ban.id).await.map_err(|e| e.to_string())?;
+        }
+        ctx.state.bans.refresh(&*ctx.state.store).await;
+        Ok(trf("unban.removed", &[args]))
+    }
+}
+
+pub struct List;
+
+#[async_trait]
+impl Command for List {
+    fn help(&self) -> Help {
+        Help { category: Category::Admin, role: Role::Admin, usage: "", about: "help.bans" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        require_admin(ctx).await?;
+        let bans = active(&*ctx.state.store, Predicate::new()).await;
+        if bans.is_empty() {
+            return Ok(tr("bans.empty").into());
+        }
+        let rows: Vec<Vec<String>> = bans.iter()
+            .map(|ban| vec![
+                layout::truncate(ban.tags.get("target").map(String::as_str).unwrap_or("-"), 40),
+                ban.tags.get("until")
+                    .and_then(|v| v.parse().ok())
+                    .map(time::iso_millis)
+                    .unwrap_or_else(|| tr("bans.forever").into()),
+            ])
+            .collect();
+        Ok(layout::table(&[tr("bans.target"), tr("bans.until")], &rows))
+    }
+}
@@ -0,0 +1,120 @@
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::{self, Post, Posted, State};
+use crate::entity::Entity;
+use crate::guard;
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::task;
+
+pub struct BotClient {
+    pub actor: Uuid,
+    pub session: Uuid,
+}
+
+impl BotClient {
+    pub async fn connect(state: &State, name: &str) -> actix_web::Result<Self> {
+        let store = &state.store;
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "user".into());
+        pred.insert("name".into(), name.into());
+        let actor = match store.query(&pred).await?.into_iter().next() {
+            Some(user) if user.tags.get("bot").map(String::as_str) == Some("true") => user.id,
+            Some(_) => return Err(actix_web::error::ErrorConflict("name belongs to someone who is not a bot")),
+            None => {
+                let mut tags = pred.clone();
+                tags.insert("bot".into(), "true".into());
+                store.create(Bytes::new(), tags).await?.id
+            }
+        };
+
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "session".into());
+        tags.insert("actor".into(), actor.to_string());
+        let session = store.create(Bytes::new(), tags).await?.id;
+
+        Ok(Self { actor, session })
+    }
+
+    pub fn run<F, Fut>(self: Arc<Self>, state: &State, predicate: Predicate, callback: F) -> Uuid
+    where
+        F: Fn(Arc<Self>, Entity) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let (id, mut rx) = state.hub.subscribe(predicate);
+        task::spawn_local(&format!("bot {}", self.actor), async move {
+            let me = self.actor.to_string();
+            while let Some(entity) = rx.recv().await {
+                if entity.tags.get("from") == Some(&me) {
+                    continue;
+                }
+                callback(self.clone(), entity).await;
+            }
+        });
+        id
+    }
+
+    pub fn stop(&self, state: &State, id: Uuid) {
+        state.hub.unsubscribe(id);
+    }
+
+    pub async fn reply(&self, state: &State, to: &Entity, load: impl Into<Bytes>) -> actix_web::Result<Entity> {
+        let mut tags = self.message_tags();
+        tags.insert("reply".into(), to.id.to_string());
+        if let Some(room) = to.tags.get("in") {
+            tags.insert("in".into(), room.clone());
+        } else if let Some(from) = to.tags.get("from") {
+            tags.insert("to".into(), from.clone());
+        }
+        self.send(state, load.into(), tags).await
+    }
+
+    pub async fn say(&self, state: &State, room: Uuid, load: impl Into<Bytes>) -> actix_web::Result<Entity> {
+        let mut tags = self.message_tags();
+        tags.insert("in".into(), room.to_string());
+        self.send(state, load.into(), tags).await
+    }
+
+    pub async fn dm(&self, state: &State, user: Uuid, load: impl Into<Bytes>) -> actix_web::Result<Entity> {
+        let mut tags = self.message_tags();
+        tags.insert("to".into(), user.to_string());
+        self.send(state, load.into(), tags).await
+    }
+
+    pub async fn send_file(&self, state: &State, room: Uuid, filename: &str, load: impl Into<Bytes>) -> actix_web::Result<Entity> {
+        let mut tags = self.message_tags();
+        tags.insert("in".into(), room.to_string());
+        tags.insert("filename".into(), filename.into());
+        self.send(state, load.into(), tags).await
+    }
+
+    pub async fn send(&self, state: &State, load: Bytes, tags: BTreeMap<String, String>) -> actix_web::Result<Entity> {
+        let resource = tags.get("in").and_then(|v| v.parse::<Uuid>().ok());
+        let auth = format!("Bearer {}", self.session);
+        let actor = guard::check(&*state.store, Some(&auth), "create", resource).await?;
+
+        let posted = api::post(state, Post {
+            actor,
+            session: Some(self.session),
+            request_id: None,
+            seq: None,
+            ip: None,
+            tags,
+            body: load,
+        }).await?;
+        match posted {
+            Posted::Created(entity, _) | Posted::Duplicate(entity) | Posted::Private(entity) => Ok(entity),
+        }
+    }
+
+    fn message_tags(&self) -> BTreeMap<String, String> {
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "msg".into());
+        tags.insert("from".into(), self.actor.to_string());
+        tags
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+
+use crate::backup;
+use crate::client;
+use crate::doctor;
+use crate::health;
+use crate::init;
+use crate::invite;
+use crate::p2p;
+use crate::profile;
+use crate::record;
+use crate::server;
+use crate::stress;
+
+pub const USAGE: &str = "usage: morce completions bash|zsh|fish|powershell";
+pub const MAN_USAGE: &str = "usage: morce man";
+
+const COMMANDS: &[(&str, &str, &str)] = &[
+    ("server", "run the server (the default when no command is given)", server::USAGE),
+    ("stress", "load-test a running server, optionally over a simulated bad network", stress::USAGE),
+    ("init", "set up the terminal client interactively", init::USAGE),
+    ("client", "chat in a room from the terminal", client::USAGE),
+    ("profile", "add, list, or remove saved client profiles", profile::USAGE),
+    ("p2p", "chat with peers on the local network without a server", p2p::USAGE),
+    ("backup", "download a snapshot of a server's store", backup::USAGE),
+    ("restore", "load a snapshot into a server's store", backup::USAGE),
+    ("invite", "print a morce:// link that lets someone into a room", invite::USAGE),
+    ("healthcheck", "exit 0 when a server answers /healthz, 1 otherwise", health::USAGE),
+    ("playback", "replay a capture made with server --record against a server", record::USAGE),
+    ("doctor", "diagnose connectivity to a server: connect, handshake, echo, throughput, mtu", doctor::USAGE),
+    ("completions", "print a shell completion script", USAGE),
+    ("man", "print this manual page in roff", MAN_USAGE),
+];
+
+#[derive(Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl Shell {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "powershell" | "pwsh" => Some(Shell::PowerShell),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::PowerShell => "powershell",
+        })
+    }
+}
+
+struct Flag {
+    name: &'static str,
+    value: Option<&'static str>,
+}
+
+impl Flag {
+    fn takes_path(&self) -> bool {
+        matches!(self.value, Some("PATH" | "FILE"))
+    }
+}
+
+fn flags(command: &str, usage: &'static str) -> Vec<Flag> {
+    let words: Vec<&'static str> = usage.split_whitespace()
+        .map(|w| w.trim_matches(|c| "[]()|".contains(c)))
+        .collect();
+    let mut flags: Vec<Flag> = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let skip = matches!((command, *word), ("backup", "--in") | ("restore", "--out"));
+        if !word.starts_with("--") || skip || flags.iter().any(|f| f.name == *word) {
+            continue;
+        }
+        let value = words.get(i + 1).copied()
+            .filter(|w| !w.is_empty() && w.chars().all(|c| c.is_ascii_uppercase()));
+        flags.push(Flag { name: word, value });
+    }
+    flags
+}
+
+fn names() -> Vec<&'static str> {
+    COMMANDS.iter().map(|(name, _, _)| *name).collect()
+}
+
+pub fn completions(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+        Shell::PowerShell => powershell(),
+    }
+}
+
+fn bash() -> String {
+    let mut out = Vec::new();
+    let paths: Vec<&str> = COMMANDS.iter()
+        .flat_map(|(name, _, usage)| flags(name, usage))
+        .filter(Flag::takes_path)
+        .map(|f| f.name)
+        .collect();
+    out.push("_morce() {".into());
+    out.push("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\" cmd=server opts".into());
+    out.push("    case \"${COMP_WORDS[1]}\" in".into());
+    out.push(format!("        {}) [[ $COMP_CWORD -gt 1 ]] && cmd=\"${{COMP_WORDS[1]}}\" ;;", names().join("|")));
+    out.push("    esac".into());
+    out.push("    case \"$prev\" in".into());
+    out.push(format!("        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;", paths.join("|")));
+    out.push("    esac".into());
+    out.push("    case \"$cmd\" in".into());
+    for (name, _, usage) in COMMANDS {
+        let words: Vec<&str> = match *name {
+            "completions" => vec!["bash", "zsh", "fish", "powershell"],
+            "profile" => vec!["add", "list", "remove"].into_iter().chain(flags(name, usage).iter().map(|f| f.name)).collect(),
+            _ => flags(name, usage).iter().map(|f| f.name).collect(),
+        };
+        out.push(format!("        {}) opts=\"{}\" ;;", name, words.join(" ")));
+    }
+    out.push("    esac".into());
+    out.push(format!("    [[ $COMP_CWORD -eq 1 ]] && opts=\"{} $opts\"", names().join(" ")));
+    out.push("    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))".into());
+    out.push("}".into());
+    out.push("complete -F _morce morce".into());
+    out.join("\n") + "\n"
+}
+
+fn zsh() -> String {
+    let mut out: Vec<String> = vec![
+        "#compdef morce".into(),
+        String::new(),
+        "_morce() {".into(),
+        "    local -a commands".into(),
+        "    commands=(".into(),
+    ];
+    for (name, about, _) in COMMANDS {
+        out.push(format!("        '{}:{}'", name, about.replace('\'', "'\\''")));
+    }
+    out.push("    )".into());
+    out.push("    if (( CURRENT == 2 )) && [[ $words[2] != -* ]]; then".into());
+    out.push("        _describe -t commands 'morce command' commands".into());
+    out.push("        return".into());
+    out.push("    fi".into());
+    out.push("    local cmd=server".into());
+    out.push("    case $words[2] in".into());
+    out.push(format!("        ({})", names().join("|")));
+    out.push("            cmd=$words[2]".into());
+    out.push("            shift words".into());
+    out.push("            (( CURRENT-- ))".into());
+    out.push("            ;;".into());
+    out.push("    esac".into());
+    out.push("    case $cmd in".into());
+    for (name, _, usage) in COMMANDS {
+        let specs: Vec<String> = match *name {
+            "completions" => vec!["'1:shell:(bash zsh fish powershell)'".into()],
+            _ => flags(name, usage).iter().map(|f| match f.value {
+                Some(value) if f.takes_path() => format!("'{}[{}]:{}:_files'", f.name, value, value),
+                Some(value) => format!("'{}[{}]:{}: '", f.name, value, value),
+                None => format!("'{}'", f.name),
+            }).collect(),
+        };
+        if specs.is_empty() {
+            out.push(format!("        ({}) ;;", name));
+        } else {
+            out.push(format!("        ({}) _arguments {} ;;", name, specs.join(" ")));
+        }
+    }
+    out.push("    esac".into());
+    out.push("}".into());
+    out.push(String::new());
+    out.push("_morce \"$@\"".into());
+    out.join("\n") + "\n"
+}
+
+fn fish() -> String {
+    let mut out = Vec::new();
+    let all = names().join(" ");
+    out.push("complete -c morce -f".into());
+    for (name, about, _) in COMMANDS {
+        out.push(format!("complete -c morce -n __fish_use_subcommand -a {} -d '{}'", name, about.replace('\'', "\\'")));
+    }
+    for (name, _, usage) in COMMANDS {
+        let condition = match *name {
+            "server" => format!("'not __fish_seen_subcommand_from {}; or __fish_seen_subcommand_from server'", all),
+            name => format!("'__fish_seen_subcommand_from {}'", name),
+        };
+        if *name == "completions" {
+            out.push(format!("complete -c morce -n {} -a 'bash zsh fish powershell'", condition));
+            continue;
+        }
+        for flag in flags(name, usage) {
+            let mut line = format!("complete -c morce -n {} -l {}", condition, flag.name.trim_start_matches("--"));
+            if let Some(value) = flag.value {
+                line.push_str(&format!(" -r -d {}", value));
+            }
+            if flag.takes_path() {
+                line.push_str(" -F");
+            }
+            out.push(line);
+        }
+    }
+    out.join("\n") + "\n"
+}
+
+fn powershell() -> String {
+    let mut out = Vec::new();
+    let quote = |words: Vec<&str>| words.iter().map(|w| format!("'{}'", w)).collect::<Vec<_>>().join(", ");
+    out.push("Register-ArgumentCompleter -Native -CommandName morce -ScriptBlock {".into());
+    out.push("    param($wordToComplete, $commandAst, $cursorPosition)".into());
+    out.push(format!("    $commands = @({})", quote(names())));
+    out.push("    $flags = @{".into());
+    for (name, _, usage) in COMMANDS {
+        let words: Vec<&str> = match *name {
+            "completions" => vec!["bash", "zsh", "fish", "powershell"],
+            _ => flags(name, usage).iter().map(|f| f.name).collect(),
+        };
+        out.push(format!("        '{}' = @({})", name, quote(words)));
+    }
+    out.push("    }".into());
+    out.push("    $words = @($commandAst.CommandElements | ForEach-Object { $_.ToString() })".into());
+    out.push("    $cmd = 'server'".into());
+    out.push("    if ($words.Count -gt 1 -and $commands -contains $words[1] -and $words[1] -ne $wordToComplete) { $cmd = $words[1] }".into());
+    out.push("    $candidates = $flags[$cmd]".into());
+    out.push("    if ($words.Count -eq 1 -or ($words.Count -eq 2 -and $wordToComplete)) { $candidates = $commands + $candidates }".into());
+    out.push("    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {".into());
+    out.push("        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)".into());
+    out.push("    }".into());
+    out.push("}".into());
+    out.join("\n") + "\n"
+}
+
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('-', "\\-")
+}
+
+pub fn man() -> String {
+    let mut out: Vec<String> = vec![
+        format!(".TH MORCE 1 \"\" \"morce {}\" \"User Commands\"", env!("CARGO_PKG_VERSION")),
+        ".SH NAME".into(),
+        "morce \\- real-time entity store and chat server with WebSocket pub/sub".into(),
+        ".SH SYNOPSIS".into(),
+    ];
+    for (_, _, usage) in COMMANDS.iter().filter(|(name, _, _)| *name != "restore") {
+        out.push(".B morce".into());
+        out.push(roff(usage.trim_start_matches("usage: morce").trim()));
+        out.push(".br".into());
+    }
+    out.push(".SH DESCRIPTION".into());
+    out.push("morce stores entities with tag-based metadata behind an HTTP API, pushes matching changes to WebSocket subscribers, and serves a web terminal at /console. Without a command it runs the server.".into());
+    out.push(".SH COMMANDS".into());
+    for (name, about, _) in COMMANDS {
+        out.push(format!(".TP\n.B {}\n{}", name, roff(about)));
+    }
+    out.push(".SH OPTIONS".into());
+    for (name, _, usage) in COMMANDS {
+        let flags = flags(name, usage);
+        if flags.is_empty() {
+            continue;
+        }
+        out.push(format!(".SS {}", name));
+        for flag in flags {
+            out.push(".TP".into());
+            match flag.value {
+                Some(value) => out.push(format!(".BI {} \" {}\"", roff(flag.name), value)),
+                None => out.push(format!(".B {}", roff(flag.name))),
+            }
+        }
+    }
+    out.push(".SH ENVIRONMENT".into());
+    out.push(".TP\n.B MORCE_LOG\nlog level: trace, debug, info, warn, or error (default info)".into());
+    out.push(".TP\n.B MORCE_LANG\nmessage language, en or fa; LC_ALL, LC_MESSAGES, and LANG are checked after it".into());
+    out.push(".TP\n.B MORCE_HOME\nkeep the client's config, cache, logs, and downloads under this directory instead of the XDG locations".into());
+    out.push(".SH FILES".into());
+    out.push(".TP\n.I ~/.config/morce/config.toml\nclient defaults, overridden by profiles and flags".into());
+    out.push(".TP\n.I ~/.config/morce/profiles\nsaved client profiles".into());
+    out.push(".TP\n.I ~/.local/state/morce/logs/client.log\nclient connection errors".into());
+    out.push(".TP\n.I morce.pid\npid file written by \\-\\-daemon".into());
+    out.push(".TP\n.I morce.log\nlog file appended to by \\-\\-daemon".into());
+    out.push(".TP\n.I morce.onion.key\nonion service key saved by \\-\\-onion".into());
+    out.push(".SH EXIT STATUS".into());
+    out.push("0 on success, 1 when healthcheck finds the server unhealthy, a doctor check fails, or playback hits an error, 2 on bad arguments or a failed command.".into());
+    out.push(".SH SEE ALSO".into());
+    out.push("The README shipped with morce documents the HTTP API, slash commands, and deployment.".into());
+    out.join("\n") + "\n"
+}
@@ -0,0 +1,763 @@
+use reqwest::header::HeaderMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::invite::Link;
+use crate::layout;
+use crate::names::Resolution;
+use crate::oidc;
+use crate::parse;
+use crate::paths::Dirs;
+use crate::pow;
+use crate::profile::{self, Profile};
+use crate::task;
+use crate::time;
+use crate::transport::{Tcp, Transport, Wire};
+
+const MAX_FRAME: u64 = 64 << 20;
+
+pub const USAGE: &str = "usage: morce client [morce://HOST:PORT/ROOM?token=INVITE] [--profile NAME]... [--target ADDR] [--name NAME] [--token ID] [--oidc-issuer URL --oidc-client ID] [--room ROOM] [--theme THEME] [--downloads DIR]";
+
+#[derive(Clone, Copy, Debug)]
+pub enum Theme {
+    Green,
+    Amber,
+    Mono,
+    Light,
+}
+
+#[derive(Clone, Copy)]
+enum Role {
+    Name,
+    System,
+    Error,
+    Info,
+    Success,
+}
+
+impl Theme {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "green" => Some(Theme::Green),
+            "amber" => Some(Theme::Amber),
+            "mono" => Some(Theme::Mono),
+            "light" => Some(Theme::Light),
+            _ => None,
+        }
+    }
+
+    fn paint(&self, role: Role, text: &str) -> String {
+        if !colored() {
+            return text.to_string();
+        }
+        let code = match (self, role) {
+            (Theme::Mono, Role::Name) => "1",
+            (Theme::Mono, _) => return text.to_string(),
+            (Theme::Green, Role::Name) => "1;32",
+            (Theme::Green, Role::System) => "36",
+            (Theme::Amber, Role::Name) => "1;38;5;214",
+            (Theme::Amber, Role::System) => "38;5;220",
+            (Theme::Amber, Role::Error) => "38;5;208",
+            (Theme::Amber, Role::Info) => "38;5;136",
+            (Theme::Amber, Role::Success) => "38;5;142",
+            (Theme::Light, Role::Name) => "1;34",
+            (Theme::Light, Role::System) => "35",
+            (_, Role::Error) => "31",
+            (_, Role::Info) => "2",
+            (_, Role::Success) => "32",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+}
+
+fn colored() -> bool {
+    static COLORED: OnceLock<bool> = OnceLock::new();
+    *COLORED.get_or_init(|| std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()))
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub target: String,
+    pub name: String,
+    pub token: Option<String>,
+    pub oidc: Option<(String, String)>,
+    pub room: Option<String>,
+    pub invite: Option<String>,
+    pub theme: Theme,
+    pub downloads: PathBuf,
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Vec<(String, Options)>, String> {
+        let (links, args): (Vec<String>, Vec<String>) = args.into_iter().partition(|a| a.starts_with("morce://"));
+        let (chosen, flags) = Profile::from_flags(args).map_err(|err| format!("{}\n{}", err, USAGE))?;
+        if let Some(raw) = links.first() {
+            let link = Link::parse(raw).ok_or(format!("{} is not a morce://host:port/room?token=... link", raw))?;
+            if links.len() > 1 || chosen.len() > 1 {
+                return Err("an invite link connects to one server".into());
+            }
+            let mut options = Self::from_profile(&profile::resolve(chosen.first().cloned(), flags)?)?;
+            options.target = link.target;
+            options.room = Some(link.room);
+            options.invite = Some(link.token);
+            return Ok(vec![(options.target.clone(), options)]);
+        }
+        if chosen.is_empty() {
+            let options = Self::from_profile(&profile::resolve(None, flags)?)?;
+            return Ok(vec![(options.target.clone(), options)]);
+        }
+        let mut servers: Vec<(String, Options)> = Vec::new();
+        for name in chosen {
+            if servers.iter().any(|(label, _)| *label == name) {
+                return Err(format!("profile {} given twice", name));
+            }
+            let options = Self::from_profile(&profile::resolve(Some(name.clone()), flags.clone())?)?;
+            servers.push((name, options));
+        }
+        Ok(servers)
+    }
+
+    pub fn from_profile(profile: &Profile) -> Result<Options, String> {
+        let theme = profile.get("theme").unwrap_or("green");
+        Ok(Options {
+            target: profile.get("target").unwrap_or("127.0.0.1:8080").to_string(),
+            name: profile.get("name").map(str::to_string)
+                .unwrap_or_else(|| std::env::var("USER").unwrap_or_else(|_| "guest".into())),
+            token: profile.get("token").map(str::to_string),
+            oidc: match (profile.get("oidc-issuer"), profile.get("oidc-client")) {
+                (Some(issuer), Some(client)) => Some((issuer.to_string(), client.to_string())),
+                (None, None) => None,
+                _ => return Err("--oidc-issuer and --oidc-client go together".into()),
+            },
+            room: profile.get("room").map(str::to_string),
+            invite: None,
+            theme: Theme::parse(theme).ok_or(format!("unknown theme {}, try green, amber, mono, or light", theme))?,
+            downloads: profile.get("downloads").map(PathBuf::from).unwrap_or_else(|| Dirs::resolve().downloads()),
+        })
+    }
+}
+
+type Events = mpsc::UnboundedSender<(usize, Result<String, String>)>;
+
+#[derive(Debug)]
+pub enum Error {
+    Unreachable(String),
+    Rejected(reqwest::StatusCode, String),
+    NoRoom(String),
+    File(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unreachable(err) => write!(f, "{}", err),
+            Error::Rejected(status, reason) => write!(f, "{}: {}", status, reason),
+            Error::NoRoom(room) => write!(f, "no room named {}, and this server does not let you create it", room),
+            Error::File(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for String {
+    fn from(err: Error) -> String {
+        err.to_string()
+    }
+}
+
+struct Pushed {
+    id: String,
+    at: String,
+    tags: BTreeMap<String, String>,
+    load: String,
+}
+
+fn parse_entity(text: &str) -> Option<Pushed> {
+    let mut lines = text.splitn(4, '\n');
+    let id = lines.next()?.strip_prefix("id:")?.to_string();
+    let at = lines.next()?.strip_prefix("at:")?.to_string();
+    let tags = parse::tags(lines.next()?.strip_prefix("tags:")?);
+    let load = lines.next()?.strip_prefix("load:")?.to_string();
+    Some(Pushed { id, at, tags, load })
+}
+
+struct Session {
+    http: reqwest::Client,
+    base: String,
+    id: String,
+    me: String,
+    username: String,
+    resolution: Option<Resolution>,
+}
+
+impl Session {
+    async fn open(options: &Options) -> Result<Session, Error> {
+        let http = reqwest::Client::new();
+        let base = format!("http://{}", options.target);
+        let (mut username, mut resolution) = (options.name.clone(), None);
+        let (id, me) = match (&options.token, &options.oidc) {
+            (Some(token), _) => login(&http, &base, serde_json::json!({"token": token})).await?,
+            (None, Some((issuer, client))) => {
+                let id_token = oidc::device_flow(issuer, client, |uri, code| {
+                    println!("To sign in, open {} and enter the code {}", uri, code);
+                }).await.map_err(Error::Unreachable)?;
+                login(&http, &base, serde_json::json!({"id_token": id_token})).await?
+            }
+            (None, None) => {
+                let (me, headers) = create(&http, &base, None, &format!("kind=user,name={}", options.name), None, Vec::new()).await?;
+                resolution = headers.get("x-name-resolution").and_then(|v| v.to_str().ok()).and_then(Resolution::parse);
+                if let Some(granted) = headers.get("x-entity-tags").and_then(|v| v.to_str().ok()).map(parse::tags).and_then(|mut t| t.remove("name")) {
+                    username = granted;
+                }
+                login(&http, &base, serde_json::json!({"guest": me})).await?
+            }
+        };
+        Ok(Session { http, base, id, me, username, resolution })
+    }
+
+    async fn post(&self, tags: &str, filename: Option<&str>, body: Vec<u8>) -> Result<String, Error> {
+        post(&self.http, &self.base, Some(&self.id), tags, filename, body).await
+    }
+
+    async fn default_room(&self) -> Option<String> {
+        let response = self.http.get(format!("{}/rooms/default", self.base)).send().await.ok()?;
+        match response.status().is_success() {
+            true => response.text().await.ok(),
+            false => None,
+        }
+    }
+
+    async fn redeem(&self, invite: &str) -> Result<(), Error> {
+        let response = self.http.post(format!("{}/invites/{}", self.base, invite))
+            .bearer_auth(&self.id)
+            .send().await.map_err(|e| Error::Unreachable(e.to_string()))?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(Error::Rejected(response.status(), format!("invite refused: {}", response.text().await.unwrap_or_default()))),
+        }
+    }
+
+    async fn command(&self, line: String) -> Result<String, Error> {
+        self.post(&format!("kind=msg,from={}", self.me), None, line.into_bytes()).await
+    }
+
+    async fn join(&self, name: &str) -> Result<String, Error> {
+        self.command(format!("/join {}", name)).await?;
+        let url = format!("{}/entities?kind=channel&name={}", self.base, name);
+        let listing = self.http.get(url).send().await.map_err(|e| Error::Unreachable(e.to_string()))?
+            .text().await.map_err(|e| Error::Unreachable(e.to_string()))?;
+        listing.lines().find_map(|line| line.strip_prefix("id:")).map(str::to_string)
+            .ok_or(Error::NoRoom(name.to_string()))
+    }
+
+    async fn marker(&self, room: &str, read: Option<&str>) -> Result<serde_json::Value, Error> {
+        let url = format!("{}/rooms/{}/read", self.base, room);
+        let request = match read {
+            Some(read) => self.http.put(url).body(read.to_string()),
+            None => self.http.get(url),
+        };
+        let response = request.bearer_auth(&self.id).send().await.map_err(|e| Error::Unreachable(e.to_string()))?;
+        match response.status() {
+            status if status.is_success() => response.json().await.map_err(|e| Error::Unreachable(e.to_string())),
+            status => Err(Error::Rejected(status, response.text().await.unwrap_or_default())),
+        }
+    }
+
+    async fn name(&self, names: &mut HashMap<String, String>, id: &str) -> String {
+        if let Some(name) = names.get(id) {
+            return name.clone();
+        }
+        let fetched = match self.http.get(format!("{}/entities/{}", self.base, id)).send().await {
+            Ok(response) => header_tags(&response).remove("name"),
+            Err(_) => None,
+        };
+        let name = fetched.unwrap_or_else(|| id.chars().take(8).collect());
+        names.insert(id.to_string(), name.clone());
+        name
+    }
+
+    async fn download(&self, dir: &Path, pushed: &Pushed, filename: &str) -> Result<PathBuf, String> {
+        let name = Path::new(filename).file_name().ok_or(format!("bad file name {}", filename))?;
+        let response = self.http.get(format!("{}/entities/{}", self.base, pushed.id)).send().await
+            .map_err(|e| e.to_string())?;
+        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let mut path = dir.join(name);
+        if path.exists() {
+            path = dir.join(format!("{}-{}", pushed.id.chars().take(8).collect::<String>(), name.to_string_lossy()));
+        }
+        std::fs::write(&path, &bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+fn header_tags(response: &reqwest::Response) -> BTreeMap<String, String> {
+    response.headers().get("x-entity-tags")
+        .and_then(|v| v.to_str().ok())
+        .map(parse::tags)
+        .unwrap_or_default()
+}
+
+async fn login(http: &reqwest::Client, base: &str, body: serde_json::Value) -> Result<(String, String), Error> {
+    let response = http.post(format!("{}/login", base))
+        .body(body.to_string())
+        .send().await.map_err(|e| Error::Unreachable(format!("{}: {}", base, e)))?;
+    if !response.status().is_success() {
+        return Err(Error::Rejected(response.status(), format!("login failed: {}", response.text().await.unwrap_or_default())));
+    }
+    let me = header_tags(&response).remove("actor")
+        .ok_or(Error::Rejected(response.status(), "session has no actor".into()))?;
+    let id = response.text().await.map_err(|e| Error::Unreachable(e.to_string()))?.trim().to_string();
+    Ok((id, me))
+}
+
+async fn post(http: &reqwest::Client, base: &str, session: Option<&str>, tags: &str, filename: Option<&str>, body: Vec<u8>) -> Result<String, Error> {
+    create(http, base, session, tags, filename, body).await.map(|(id, _)| id)
+}
+
+async fn create(http: &reqwest::Client, base: &str, session: Option<&str>, tags: &str, filename: Option<&str>, body: Vec<u8>) -> Result<(String, HeaderMap), Error> {
+    let mut request = http.post(format!("{}/entities", base)).header("x-tags", tags).body(body);
+    if let Some(session) = session {
+        request = request.header("Authorization", format!("Bearer {}", session));
+    }
+    if let Some(filename) = filename {
+        request = request.header("x-filename", filename);
+    }
+    let response = request.send().await.map_err(|e| Error::Unreachable(format!("{}: {}", base, e)))?;
+    let status = response.status();
+    let id = response.headers().get("x-entity-id").and_then(|v| v.to_str().ok()).map(str::to_string);
+    match id {
+        Some(id) if status.is_success() => Ok((id, response.headers().clone())),
+        _ => Err(Error::Rejected(status, response.text().await.unwrap_or_default())),
+    }
+}
+
+pub struct ClientHandle {
+    session: Session,
+    room: String,
+    room_name: String,
+}
+
+impl ClientHandle {
+    pub async fn connect(options: &Options) -> Result<ClientHandle, Error> {
+        let session = Session::open(options).await?;
+        let room_name = match &options.room {
+            Some(room) => room.clone(),
+            None => session.default_room().await.unwrap_or_else(|| "general".into()),
+        };
+        if let Some(invite) = &options.invite {
+            session.redeem(invite).await?;
+        }
+        let room = session.join(&room_name).await?;
+        Ok(ClientHandle { session, room, room_name })
+    }
+
+    pub fn me(&self) -> &str {
+        &self.session.me
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.session.username
+    }
+
+    pub fn resolution(&self) -> Option<Resolution> {
+        self.session.resolution
+    }
+
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    pub fn room_name(&self) -> &str {
+        &self.room_name
+    }
+
+    pub async fn send_text(&self, text: &str) -> Result<String, Error> {
+        let tags = format!("kind=msg,from={},in={}", self.session.me, self.room);
+        self.session.post(&tags, None, text.as_bytes().to_vec()).await
+    }
+
+    pub async fn send_file(&self, path: &Path) -> Result<String, Error> {
+        let name = path.file_name().and_then(|n| n.to_str()).ok_or(Error::File(format!("bad file name {}", path.display())))?;
+        let body = tokio::fs::read(path).await.map_err(|e| Error::File(format!("{}: {}", path.display(), e)))?;
+        let tags = format!("kind=msg,from={},in={}", self.session.me, self.room);
+        self.session.post(&tags, Some(name), body).await
+    }
+
+    pub async fn join_room(&mut self, room: &str) -> Result<String, Error> {
+        self.room = self.session.join(room).await?;
+        self.room_name = room.to_string();
+        Ok(self.room.clone())
+    }
+
+    pub async fn unread(&self) -> Result<usize, Error> {
+        let report = self.session.marker(&self.room, None).await?;
+        Ok(report["unread"].as_u64().unwrap_or_default() as usize)
+    }
+
+    pub async fn mark_read(&self, message: Option<&str>) -> Result<usize, Error> {
+        let report = self.session.marker(&self.room, Some(message.unwrap_or_default())).await?;
+        Ok(report["unread"].as_u64().unwrap_or_default() as usize)
+    }
+
+    pub async fn set_status(&self, status: &str) -> Result<(), Error> {
+        self.session.command(format!("/status {}", status)).await.map(|_| ())
+    }
+}
+
+pub async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mask = *Uuid::new_v4().as_bytes();
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(0x80 | n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask[..4]);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).await
+}
+
+pub async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> std::io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await?;
+    let len = match head[1] & 0x7f {
+        126 => stream.read_u16().await? as u64,
+        127 => stream.read_u64().await?,
+        n => n as u64,
+    };
+    if len > MAX_FRAME {
+        return Err(std::io::Error::other(format!("frame of {} bytes is too large", len)));
+    }
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload).await?;
+    if head[1] & 0x80 != 0 {
+        payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok((head[0] & 0x80 != 0, head[0] & 0x0f, payload))
+}
+
+pub async fn challenge(transport: &dyn Transport, target: &str) -> std::io::Result<Option<String>> {
+    let mut stream = transport.connect(target).await?;
+    let head = format!("GET /challenge HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", target);
+    stream.write_all(head.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    let Some((_, body)) = response.split_once("\r\n\r\n") else {
+        return Ok(None);
+    };
+    let mut parts = body.split_whitespace();
+    Ok(match (parts.next(), parts.next().and_then(|b| b.parse().ok())) {
+        (Some(challenge), Some(bits)) => Some(pow::solve(challenge, bits)),
+        _ => None,
+    })
+}
+
+pub async fn upgrade(transport: &dyn Transport, target: &str, session: Option<&str>) -> Result<Box<dyn Wire>, String> {
+    let path = match challenge(transport, target).await.map_err(|e| format!("{}: {}", target, e))? {
+        Some(solution) => format!("/ws?pow={}", solution),
+        None => "/ws".into(),
+    };
+    let mut stream = transport.connect(target).await.map_err(|e| format!("{}: {}", target, e))?;
+    let key = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, Uuid::new_v4().as_bytes());
+    let auth = session.map(|s| format!("Authorization: Bearer {}\r\n", s)).unwrap_or_default();
+    let head = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n{}\r\n",
+        path, target, key, auth,
+    );
+    stream.write_all(head.as_bytes()).await.map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        let byte = stream.read_u8().await.map_err(|e| e.to_string())?;
+        response.push(byte);
+        if response.len() > 8192 {
+            return Err("oversized upgrade response".into());
+        }
+    }
+    let status = String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string();
+    if !status.contains(" 101 ") {
+        return Err(format!("upgrade refused: {}", status));
+    }
+    Ok(stream)
+}
+
+async fn subscribe(transport: &dyn Transport, target: &str, session: &str, filter: &str, index: usize, tx: &Events) -> Result<(), String> {
+    let mut stream = upgrade(transport, target, Some(session)).await?;
+    write_frame(&mut stream, 0x1, filter.as_bytes()).await.map_err(|e| e.to_string())?;
+    let mut message = Vec::new();
+    loop {
+        let (fin, opcode, payload) = read_frame(&mut stream).await.map_err(|e| e.to_string())?;
+        match opcode {
+            0x0 | 0x1 => {
+                message.extend(payload);
+                if fin && tx.send((index, Ok(String::from_utf8_lossy(&std::mem::take(&mut message)).into_owned()))).is_err() {
+                    return Ok(());
+                }
+            }
+            0x8 => return Err("server closed the connection".into()),
+            0x9 => write_frame(&mut stream, 0xa, &payload).await.map_err(|e| e.to_string())?,
+            _ => {}
+        }
+    }
+}
+
+struct Connection {
+    label: String,
+    options: Options,
+    handle: ClientHandle,
+    names: HashMap<String, String>,
+    lost: bool,
+    unread: usize,
+}
+
+impl Connection {
+    async fn open(index: usize, label: String, options: Options, tx: &Events) -> Result<Connection, String> {
+        let handle = ClientHandle::connect(&options).await?;
+        let unread = handle.unread().await.unwrap_or_default();
+        let _ = handle.mark_read(None).await;
+        let transport: Arc<dyn Transport> = Arc::new(Tcp::direct());
+        for filter in [format!("kind=msg&in={}", handle.room), format!("kind=msg&to={}", handle.me()), format!("kind=preview&in={}", handle.room)] {
+            let (transport, target, id, tx) = (transport.clone(), options.target.clone(), handle.session().to_string(), tx.clone());
+            task::spawn("client.ws", async move {
+                if let Err(err) = subscribe(&*transport, &target, &id, &filter, index, &tx).await {
+                    let _ = tx.send((index, Err(err)));
+                }
+            });
+        }
+        Ok(Connection { label, options, handle, names: HashMap::new(), lost: false, unread })
+    }
+
+    fn prefix(&self, labelled: bool) -> String {
+        match labelled {
+            true => format!("{} ", self.options.theme.paint(Role::Info, &format!("[{}]", self.label))),
+            false => String::new(),
+        }
+    }
+
+    async fn show(&mut self, labelled: bool, text: &str) {
+        let Some(pushed) = parse_entity(text) else {
+            return;
+        };
+        let (theme, prefix) = (self.options.theme, self.prefix(labelled));
+        let time = theme.paint(Role::Info, pushed.at.get(11..16).unwrap_or(""));
+        let load = match pushed.tags.get("tone").map(String::as_str) {
+            Some("ok") => theme.paint(Role::Success, &pushed.load),
+            Some("fail") => theme.paint(Role::Error, &pushed.load),
+            _ => pushed.load.clone(),
+        };
+        if pushed.tags.get("kind").map(String::as_str) == Some("preview") {
+            let preview: serde_json::Value = serde_json::from_str(&pushed.load).unwrap_or_default();
+            let field = |key: &str| preview.get(key).and_then(serde_json::Value::as_str).unwrap_or_default().to_string();
+            let line = match field("description").as_str() {
+                "" => format!("↳ {}: {}", field("site"), field("title")),
+                description => format!("↳ {}: {} — {}", field("site"), field("title"), layout::truncate(description, 120)),
+            };
+            println!("{}{} {}", prefix, time, theme.paint(Role::Info, &line));
+            return;
+        }
+        if pushed.tags.get("in") == Some(&self.handle.room) {
+            let _ = self.handle.mark_read(Some(&pushed.id)).await;
+        }
+        match pushed.tags.get("from").map(String::as_str) {
+            Some("system") => println!("{}{} {}", prefix, time, theme.paint(Role::System, &format!("* {}", pushed.load))),
+            Some(from) => {
+                let name = self.handle.session.name(&mut self.names, from).await;
+                println!("{}{} {} {}", prefix, time, theme.paint(Role::Name, &format!("<{}>", name)), load);
+            }
+            None => println!("{}{} {}", prefix, time, load),
+        }
+        if let Some(filename) = pushed.tags.get("filename") {
+            match self.handle.session.download(&self.options.downloads, &pushed, filename).await {
+                Ok(path) => println!("{}{}", prefix, theme.paint(Role::Info, &format!("saved {}", path.display()))),
+                Err(err) => println!("{}{}", prefix, theme.paint(Role::Error, &err)),
+            }
+        }
+    }
+}
+
+fn record(label: &str, line: &str) {
+    let path = Dirs::resolve().logs.join("client.log");
+    let entry = format!("{} [{}] {}\n", time::timestamp(), label, line);
+    let _ = std::fs::OpenOptions::new().append(true).create(true).open(path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, entry.as_bytes()));
+}
+
+fn switch(connections: &[Connection], current: &mut usize, args: &str) -> String {
+    if args.is_empty() {
+        let labels: Vec<String> = connections.iter().enumerate().map(|(i, c)| {
+            let mark = if i == *current { "*" } else { " " };
+            let state = if c.lost { " (lost)" } else { "" };
+            format!("{} {} {} #{}{}", mark, c.label, c.options.target, c.handle.room_name, state)
+        }).collect();
+        return labels.join("\n");
+    }
+    match connections.iter().position(|c| c.label == args) {
+        Some(i) => {
+            *current = i;
+            format!("sending to {}", args)
+        }
+        None => format!("no server named {}, see /server", args),
+    }
+}
+
+pub async fn run(servers: Vec<(String, Options)>) -> Result<(), String> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let labelled = servers.len() > 1;
+    let mut connections = Vec::new();
+    for (label, options) in servers {
+        match Connection::open(connections.len(), label.clone(), options, &tx).await {
+            Ok(connection) => connections.push(connection),
+            Err(err) => {
+                record(&label, &err);
+                return Err(if labelled { format!("{}: {}", label, err) } else { err });
+            }
+        }
+    }
+    drop(tx);
+    let theme = connections[0].options.theme;
+    for connection in &connections {
+        let options = &connection.options;
+        let handle = &connection.handle;
+        match handle.resolution() {
+            Some(Resolution::Suffixed) => println!("{}{}", connection.prefix(labelled), options.theme.paint(Role::Info, &format!(
+                "{} is taken, using {}", options.name, handle.name(),
+            ))),
+            Some(Resolution::Kicked) => println!("{}{}", connection.prefix(labelled), options.theme.paint(Role::Info, &format!(
+                "signed out the other {} session", handle.name(),
+            ))),
+            None => {}
+        }
+        println!("{}{}", connection.prefix(labelled), options.theme.paint(Role::Info, &format!(
+            "connected to {} as {} in #{}", options.target, handle.name(), handle.room_name,
+        )));
+        if connection.unread > 0 {
+            println!("{}{}", connection.prefix(labelled), options.theme.paint(Role::Info, &format!(
+                "── {} new since you were last here ──", connection.unread,
+            )));
+        }
+    }
+    let hint = if labelled { "type to send to the current server, /server NAME to switch, Ctrl-D to quit" } else { "type to send, Ctrl-D to quit" };
+    println!("{}", theme.paint(Role::Info, hint));
+
+    let mut current = 0;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(text) = line.map_err(|e| e.to_string())? else {
+                    return Ok(());
+                };
+                if text.trim().is_empty() {
+                    continue;
+                }
+                if let Some(args) = text.trim().strip_prefix("/server").filter(|a| a.is_empty() || a.starts_with(' ')) {
+                    println!("{}", theme.paint(Role::Info, &switch(&connections, &mut current, args.trim())));
+                    continue;
+                }
+                let connection = &connections[current];
+                let sent = match connection.lost {
+                    true => Err(format!("{} is disconnected, see /server", connection.label)),
+                    false => connection.handle.send_text(&text).await.map_err(String::from),
+                };
+                if let Err(err) = sent {
+                    println!("{}{}", connection.prefix(labelled), connection.options.theme.paint(Role::Error, &err));
+                }
+            }
+            pushed = rx.recv() => {
+                let Some((index, pushed)) = pushed else {
+                    return Ok(());
+                };
+                let connection = &mut connections[index];
+                match pushed {
+                    Ok(text) => connection.show(labelled, &text).await,
+                    Err(err) if !connection.lost => {
+                        connection.lost = true;
+                        record(&connection.label, &format!("connection lost: {}", err));
+                        println!("{}{}", connection.prefix(labelled), connection.options.theme.paint(Role::Error, &format!("connection lost: {}", err)));
+                        if connections.iter().all(|c| c.lost) {
+                            return Err("all connections lost".into());
+                        }
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(len + 16);
+        while out.len() < len {
+            out.extend_from_slice(Uuid::new_v4().as_bytes());
+        }
+        out.truncate(len);
+        out
+    }
+
+    async fn round_trip(len: usize, opcode: u8) {
+        let sent = payload(len);
+        let (mut near, mut far) = tokio::io::duplex(7);
+        let write = async {
+            write_frame(&mut near, opcode, &sent).await.unwrap();
+        };
+        let (_, read) = tokio::join!(write, read_frame(&mut far));
+        let (fin, got_opcode, got) = read.unwrap();
+        assert!(fin);
+        assert_eq!(got_opcode, opcode);
+        assert_eq!(got.len(), len, "payload of {} bytes", len);
+        assert!(got == sent, "payload of {} bytes came back different", len);
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip_across_length_boundaries() {
+        for len in [0, 1, 125, 126, 127, 65535, 65536, 65537] {
+            round_trip(len, 0x2).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip_at_random_lengths() {
+        for _ in 0..32 {
+            let len = (Uuid::new_v4().as_u128() % 200_000) as usize;
+            round_trip(len, 0x1).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn unmasked_frames_read_back() {
+        let sent = payload(300);
+        let mut frame = vec![0x81, 126];
+        frame.extend_from_slice(&(sent.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&sent);
+        let (mut near, mut far) = tokio::io::duplex(5);
+        let write = async {
+            near.write_all(&frame).await.unwrap();
+        };
+        let (_, read) = tokio::join!(write, read_frame(&mut far));
+        assert_eq!(read.unwrap(), (true, 0x1, sent));
+    }
+}
@@ -0,0 +1,340 @@
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::account;
+use crate::api::State;
+use crate::ban;
+use crate::feed;
+use crate::entity::Entity;
+use crate::guard;
+use crate::keys;
+use crate::layout;
+use crate::locale::{self, tr, trf, Lang};
+use crate::mail;
+use crate::notify;
+use crate::poll;
+use crate::presence;
+use crate::push;
+use crate::quota;
+use crate::retention;
+use crate::room;
+use crate::store::Store;
+use crate::time;
+use crate::translate;
+use crate::unfurl;
+use crate::welcome;
+
+pub struct Context<'a> {
+    pub state: &'a State,
+    pub message: &'a Entity,
+    pub actor: Option<Uuid>,
+    pub session: Option<Uuid>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Chat,
+    Rooms,
+    Account,
+    Server,
+    Admin,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Category::Chat => tr("help.chat"),
+            Category::Rooms => tr("help.rooms"),
+            Category::Account => tr("help.account"),
+            Category::Server => tr("help.server"),
+            Category::Admin => tr("help.admin"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Anyone,
+    User,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Anyone => tr("help.anyone"),
+            Role::User => tr("help.user"),
+            Role::Admin => tr("help.admins"),
+        })
+    }
+}
+
+pub struct Help {
+    pub category: Category,
+    pub role: Role,
+    pub usage: &'static str,
+    pub about: &'static str,
+}
+
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn private(&self) -> bool {
+        false
+    }
+
+    fn help(&self) -> Help;
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String>;
+}
+
+pub struct Commands {
+    items: BTreeMap<String, Arc<dyn Command>>,
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self {
+            items: BTreeMap::new(),
+        }
+    }
+
+    pub fn builtin() -> Self {
+        let mut commands = Self::new();
+        commands.register("roll", Roll);
+        commands.register("date", Today);
+        commands.register("stats", ServerStats);
+        let polls = Arc::new(poll::Polls::new());
+        commands.register("poll", poll::Open(polls.clone()));
+        commands.register("vote", poll::Vote(polls));
+        commands.register("status", presence::Status);
+        commands.register("who", presence::Who);
+        commands.register("away", presence::Away);
+        commands.register("ban", ban::Ban);
+        commands.register("unban", ban::Unban);
+        commands.register("bans", ban::List);
+        commands.register("retention", retention::Retention);
+        commands.register("register", account::Register);
+        commands.register("token", account::Token);
+        commands.register("sessions", account::Sessions);
+        commands.register("email", mail::Email);
+        commands.register("push", push::Push);
+        commands.register("notify", notify::Notify);
+        commands.register("fingerprint", keys::Fingerprint);
+        commands.register("quota", quota::Show);
+        commands.register("join", room::Join);
+        commands.register("leave", room::Leave);
+        commands.register("rooms", room::List);
+        commands.register("feed", feed::Feed);
+        commands.register("translate", translate::Translate);
+        commands.register("unfurl", unfurl::Unfurl);
+        commands.register("agree", welcome::Agree);
+        commands.register("help", HelpCommand);
+        commands
+    }
+
+    pub fn register(&mut self, name: &str, command: impl Command + 'static) {
+        self.items.insert(name.to_string(), Arc::new(command));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.items.keys().map(String::as_str)
+    }
+
+    pub fn render(&self, name: Option<&str>, admin: bool) -> Result<String, String> {
+        if let Some(name) = name {
+            let name = name.trim_start_matches('/');
+            let help = self.items.get(name).map(|c| c.help()).ok_or_else(|| trf("command.unknown", &[name]))?;
+            return Ok(trf("help.one", &[&signature(name, &help), tr(help.about), &help.category.to_string(), &help.role.to_string()]));
+        }
+        let mut groups: BTreeMap<Category, Vec<(String, Help)>> = BTreeMap::new();
+        for (name, command) in &self.items {
+            let help = command.help();
+            if help.role != Role::Admin || admin {
+                groups.entry(help.category).or_default().push((signature(name, &help), help));
+            }
+        }
+        let pad = groups.values().flatten().map(|(sig, _)| layout::width(sig)).max().unwrap_or(0);
+        let mut out = Vec::new();
+        for (category, entries) in groups {
+            out.push(format!("{}:", category));
+            for (sig, help) in entries {
+                let role = match help.role {
+                    Role::Anyone => String::new(),
+                    role => format!(" ({})", role),
+                };
+                out.push(format!("  {}{}  {}{}", sig, " ".repeat(pad - layout::width(&sig)), tr(help.about), role));
+            }
+        }
+        out.push(tr("help.more").into());
+        Ok(out.join("\n"))
+    }
+
+    pub fn is_private(&self, load: &[u8]) -> bool {
+        Self::parse(load)
+            .and_then(|(name, _)| self.items.get(name))
+            .is_some_and(|command| command.private())
+    }
+
+    pub fn parse(load: &[u8]) -> Option<(&str, &str)> {
+        let text = std::str::from_utf8(load).ok()?.trim();
+        let text = text.strip_prefix('/')?;
+        let mut split = text.splitn(2, char::is_whitespace);
+        let name = split.next().filter(|n| !n.is_empty())?;
+        Some((name, split.next().unwrap_or("").trim()))
+    }
+
+    pub async fn dispatch(&self, state: &State, message: &Entity, actor: Option<Uuid>, session: Option<Uuid>) -> Option<Entity> {
+        if message.tags.get("kind").map(String::as_str) != Some("msg") {
+            return None;
+        }
+        let (name, args) = Self::parse(&message.load)?;
+        let ctx = Context { state, message, actor, session };
+
+        let (text, private) = match self.items.get(name) {
+            Some(command) => match command.run(&ctx, args).await {
+                Ok(text) => (text, command.private()),
+                Err(err) => (trf("command.failed", &[name, &err]), true),
+            },
+            None => (trf("command.unknown", &[name]), true),
+        };
+        match actor {
+            Some(actor) if private => ephemeral(state, actor, Some(message), &text).await,
+            _ => system_message(state, message, &text).await,
+        }
+    }
+}
+
+pub async fn system_message(state: &State, to: &Entity, text: &str) -> Option<Entity> {
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "msg".into());
+    tags.insert("from".into(), "system".into());
+    tags.insert("reply".into(), to.id.to_string());
+    if let Some(room) = to.tags.get("in") {
+        tags.insert("in".into(), room.clone());
+    } else if let Some(from) = to.tags.get("from") {
+        tags.insert("to".into(), from.clone());
+    }
+    let entity = state.store.create(text.to_string().into(), tags).await.ok()?;
+    state.hub.publish(&entity);
+    Some(entity)
+}
+
+pub async fn direct_message(state: &State, to: Uuid, text: &str) -> Option<Entity> {
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "msg".into());
+    tags.insert("from".into(), "system".into());
+    tags.insert("to".into(), to.to_string());
+    let entity = state.store.create(text.to_string().into(), tags).await.ok()?;
+    state.hub.publish(&entity);
+    Some(entity)
+}
+
+pub async fn ephemeral(state: &State, to: Uuid, about: Option<&Entity>, text: &str) -> Option<Entity> {
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "msg".into());
+    tags.insert("from".into(), "system".into());
+    tags.insert("to".into(), to.to_string());
+    tags.insert("ephemeral".into(), "true".into());
+    if let Some(about) = about {
+        tags.insert("reply".into(), about.id.to_string());
+        if let Some(room) = about.tags.get("in") {
+            tags.insert("in".into(), room.clone());
+        }
+    }
+    let entity = Entity::new(text.to_string().into(), tags, state.clock.millis());
+    match state.hub.deliver_to(to, entity.clone()) {
+        true => Some(entity),
+        false => direct_message(state, to, text).await,
+    }
+}
+
+fn signature(name: &str, help: &Help) -> String {
+    if help.usage.is_empty() {
+        format!("/{}", name)
+    } else {
+        format!("/{} {}", name, help.usage)
+    }
+}
+
+struct HelpCommand;
+
+#[async_trait]
+impl Command for HelpCommand {
+    fn help(&self) -> Help {
+        Help { category: Category::Server, role: Role::Anyone, usage: "[command]", about: "help.help" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let admin = match ctx.actor {
+            Some(actor) => guard::is_admin(&*ctx.state.store, actor).await,
+            None => false,
+        };
+        ctx.state.commands.render(Some(args).filter(|a| !a.is_empty()), admin)
+    }
+}
+
+const MAX_SIDES: u32 = 1000;
+
+struct Roll;
+
+#[async_trait]
+impl Command for Roll {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::Anyone, usage: "[NdM]", about: "help.roll" }
+    }
+
+    async fn run(&self, _ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let spec = if args.is_empty() { "1d6" } else { args };
+        let (count, sides) = spec.split_once('d').ok_or(tr("roll.usage"))?;
+        let count: u32 = if count.is_empty() { 1 } else { count.parse().map_err(|_| tr("roll.count"))? };
+        let sides: u32 = sides.parse().map_err(|_| tr("roll.sides"))?;
+        if count == 0 || count > 100 || sides == 0 || sides > MAX_SIDES {
+            return Err(tr("roll.range").into());
+        }
+        let rolls: Vec<u32> = (0..count)
+            .map(|_| (Uuid::new_v4().as_u128() % sides as u128) as u32 + 1)
+            .collect();
+        let total: u64 = rolls.iter().map(|&r| u64::from(r)).sum();
+        let parts: Vec<String> = rolls.iter().map(|r| r.to_string()).collect();
+        Ok(format!("{}: {} = {}", spec, parts.join(" + "), total))
+    }
+}
+
+struct Today;
+
+#[async_trait]
+impl Command for Today {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::Anyone, usage: "", about: "help.date" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        let clock = &*ctx.state.clock;
+        Ok(match locale::lang() {
+            Lang::Fa => time::Jalali::today(clock).format_full(),
+            Lang::En => time::Date::today(clock).format_full(),
+        })
+    }
+}
+
+struct ServerStats;
+
+#[async_trait]
+impl Command for ServerStats {
+    fn help(&self) -> Help {
+        Help { category: Category::Server, role: Role::Anyone, usage: "", about: "help.stats" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        Ok(layout::table(&[tr("stats.metric"), tr("stats.value")], &ctx.state.stats.rows()))
+    }
+}
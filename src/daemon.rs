@@ -0,0 +1,52 @@
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+fn running(pid_file: &Path) -> Option<i32> {
+    let pid = std::fs::read_to_string(pid_file).ok()?.trim().parse::<i32>().ok()?;
+    (unsafe { libc::kill(pid, 0) } == 0).then_some(pid)
+}
+
+pub struct Pid(PathBuf);
+
+impl Drop for Pid {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+pub fn detach(pid_file: &str, log_file: &str) -> std::io::Result<Pid> {
+    let pid_file = PathBuf::from(pid_file);
+    if let Some(pid) = running(&pid_file) {
+        return Err(Error::new(ErrorKind::AddrInUse, format!("already running as pid {} ({})", pid, pid_file.display())));
+    }
+    let log = OpenOptions::new().create(true).append(true).open(log_file)?;
+    let null = OpenOptions::new().read(true).open("/dev/null")?;
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(Error::last_os_error()),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+    if unsafe { libc::setsid() } == -1 {
+        return Err(Error::last_os_error());
+    }
+    match unsafe { libc::fork() } {
+        -1 => return Err(Error::last_os_error()),
+        0 => {}
+        child => {
+            println!("morce started as pid {}, logging to {}", child, log_file);
+            std::process::exit(0);
+        }
+    }
+
+    unsafe {
+        libc::umask(0o027);
+        libc::dup2(null.as_raw_fd(), 0);
+        libc::dup2(log.as_raw_fd(), 1);
+        libc::dup2(log.as_raw_fd(), 2);
+    }
+    std::fs::write(&pid_file, format!("{}\n", std::process::id()))?;
+    Ok(Pid(pid_file))
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::time::Clock;
+use crate::window::{Slot, Window};
+
+const CAPACITY: usize = 100_000;
+
+pub enum Claim {
+    Fresh,
+    Full,
+    Pending,
+    Done(Uuid),
+}
+
+pub struct Dedup {
+    seen: Window<String, Option<Uuid>>,
+}
+
+impl Dedup {
+    pub fn new(window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            seen: Window::new(window, CAPACITY, clock),
+        }
+    }
+
+    pub fn claim(&self, key: &str) -> Claim {
+        match self.seen.claim(key.to_string(), None) {
+            Slot::Claimed => Claim::Fresh,
+            Slot::Full => Claim::Full,
+            Slot::Taken(Some(id)) => Claim::Done(id),
+            Slot::Taken(None) => Claim::Pending,
+        }
+    }
+
+    pub fn remember(&self, key: String, id: Uuid) {
+        self.seen.insert(key, Some(id));
+    }
+
+    pub fn release(&self, key: &str) {
+        self.seen.remove_if(&key.to_string(), Option::is_none);
+    }
+
+    pub fn prune(&self) -> usize {
+        self.seen.sweep()
+    }
+}
@@ -0,0 +1,206 @@
+use std::io::IsTerminal;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::client;
+use crate::time::{self, Clock};
+use crate::tor;
+use crate::transport::Tcp;
+
+pub const USAGE: &str = "usage: morce doctor [--addr HOST:PORT] [--timeout SECS]";
+
+const PINGS: usize = 5;
+const PROBE_TIME: Duration = Duration::from_secs(1);
+const PROBE_BYTES: usize = 8 << 20;
+const SLOW_RTT: Duration = Duration::from_millis(250);
+
+pub struct Options {
+    pub addr: String,
+    pub timeout: Duration,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options { addr: "127.0.0.1:8080".into(), timeout: Duration::from_secs(5), clock: time::system() };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--addr" => options.addr = value.trim_start_matches("http://").trim_end_matches('/').to_string(),
+                "--timeout" => options.timeout = value.parse().map(Duration::from_secs)
+                    .map_err(|_| format!("--timeout expects seconds, got {}", value))?,
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Outcome {
+    fn mark(&self, color: bool) -> String {
+        let (mark, code) = match self {
+            Outcome::Pass => ("✓", "32"),
+            Outcome::Warn => ("!", "33"),
+            Outcome::Fail => ("✗", "31"),
+        };
+        if color { format!("\x1b[{}m{}\x1b[0m", code, mark) } else { mark.into() }
+    }
+}
+
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<(Outcome, String), String>) -> Self {
+        match result {
+            Ok((outcome, detail)) => Check { name, outcome, detail },
+            Err(detail) => Check { name, outcome: Outcome::Fail, detail },
+        }
+    }
+}
+
+fn millis(elapsed: Duration) -> String {
+    format!("{:.1} ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+async fn within<T>(timeout: Duration, what: &str, work: impl std::future::Future<Output = Result<T, String>>) -> Result<T, String> {
+    tokio::time::timeout(timeout, work).await.map_err(|_| format!("{} timed out after {}s", what, timeout.as_secs()))?
+}
+
+async fn connect(options: &Options) -> Result<(TcpStream, Duration), String> {
+    let started = options.clock.instant();
+    let stream = within(options.timeout, "connect", async {
+        tor::connect(&options.addr, None).await.map_err(|e| format!("{}: {}", options.addr, e))
+    }).await?;
+    Ok((stream, options.clock.since(started)))
+}
+
+async fn handshake(options: &Options) -> Result<(Outcome, String), String> {
+    let url = format!("http://{}/healthz", options.addr);
+    let http = reqwest::Client::builder().timeout(options.timeout).build().map_err(|e| e.to_string())?;
+    let started = options.clock.instant();
+    let response = http.get(&url).send().await.map_err(|e| format!("{}: {}", url, e))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let health = options.clock.since(started);
+    let started = options.clock.instant();
+    within(options.timeout, "upgrade", client::upgrade(&Tcp::direct(), &options.addr, None)).await?;
+    let detail = format!("/healthz {} in {}, websocket upgrade in {}", status.as_u16(), millis(health), millis(options.clock.since(started)));
+    if status.is_success() {
+        Ok((Outcome::Pass, detail))
+    } else {
+        Ok((Outcome::Warn, format!("{} ({})", detail, body.trim())))
+    }
+}
+
+async fn echo(options: &Options) -> Result<(Outcome, String), String> {
+    let mut stream = within(options.timeout, "upgrade", client::upgrade(&Tcp::direct(), &options.addr, None)).await?;
+    let mut rtts = Vec::new();
+    for i in 0..PINGS {
+        let payload = format!("morce doctor {}", i);
+        let started = options.clock.instant();
+        client::write_frame(&mut stream, 0x9, payload.as_bytes()).await.map_err(|e| e.to_string())?;
+        loop {
+            let (_, opcode, body) = within(options.timeout, "pong", async {
+                client::read_frame(&mut stream).await.map_err(|e| e.to_string())
+            }).await?;
+            match opcode {
+                0xa if body == payload.as_bytes() => break,
+                0xa => return Err(format!("pong {} came back with a different payload", i)),
+                0x8 => return Err("server closed the socket".into()),
+                _ => {}
+            }
+        }
+        rtts.push(options.clock.since(started));
+    }
+    let _ = client::write_frame(&mut stream, 0x8, &[]).await;
+    let (min, max) = (rtts.iter().min().copied().unwrap_or_default(), rtts.iter().max().copied().unwrap_or_default());
+    let avg = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+    let detail = format!("{} pings, rtt min/avg/max {}/{}/{}", PINGS, millis(min), millis(avg), millis(max));
+    Ok((if avg > SLOW_RTT { Outcome::Warn } else { Outcome::Pass }, detail))
+}
+
+async fn throughput(options: &Options) -> Result<(Outcome, String), String> {
+    let url = format!("http://{}/console", options.addr);
+    let http = reqwest::Client::builder().timeout(options.timeout).build().map_err(|e| e.to_string())?;
+    let (started, mut bytes, mut requests) = (options.clock.instant(), 0usize, 0);
+    while options.clock.since(started) < PROBE_TIME && bytes < PROBE_BYTES {
+        let response = http.get(&url).send().await.map_err(|e| format!("{}: {}", url, e))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND && requests == 0 {
+            return Ok((Outcome::Warn, "skipped, the server has no /console (running with --echo?)".into()));
+        }
+        if !response.status().is_success() {
+            return Err(format!("{}: {}", url, response.status()));
+        }
+        bytes += response.bytes().await.map_err(|e| e.to_string())?.len();
+        requests += 1;
+    }
+    let rate = bytes as f64 / 1024.0 / options.clock.since(started).as_secs_f64();
+    Ok((Outcome::Pass, format!("{} KiB in {} requests, {:.0} KiB/s", bytes / 1024, requests, rate)))
+}
+
+fn mtu(stream: &TcpStream) -> Result<(Outcome, String), String> {
+    let mut mss: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_MAXSEG, (&mut mss as *mut libc::c_int).cast(), &mut len)
+    };
+    if rc != 0 {
+        return Err(format!("TCP_MAXSEG: {}", std::io::Error::last_os_error()));
+    }
+    let ipv6 = stream.peer_addr().is_ok_and(|a| a.is_ipv6());
+    let path = mss + if ipv6 { 60 } else { 40 };
+    let detail = format!("mss {} bytes, path mtu about {}", mss, path);
+    if path < 1280 {
+        Ok((Outcome::Warn, format!("{}, smaller than usual; large frames will fragment", detail)))
+    } else {
+        Ok((Outcome::Pass, detail))
+    }
+}
+
+pub async fn run(options: &Options) -> Vec<Check> {
+    let (stream, elapsed) = match connect(options).await {
+        Ok(connected) => connected,
+        Err(err) => return vec![Check::new("tcp connect", Err(err))],
+    };
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_else(|_| options.addr.clone());
+    let mut checks = vec![Check::new("tcp connect", Ok((Outcome::Pass, format!("{} in {}", peer, millis(elapsed)))))];
+    checks.push(Check::new("handshake", handshake(options).await));
+    checks.push(Check::new("echo", echo(options).await));
+    checks.push(Check::new("throughput", throughput(options).await));
+    checks.push(Check::new("mtu", mtu(&stream)));
+    checks
+}
+
+pub fn report(addr: &str, checks: &[Check]) -> String {
+    let color = std::io::stdout().is_terminal();
+    let mut out = format!("morce doctor {}\n", addr);
+    for check in checks {
+        out += &format!("  {} {:<12} {}\n", check.outcome.mark(color), check.name, check.detail);
+    }
+    let failed = checks.iter().filter(|c| c.outcome == Outcome::Fail).count();
+    let warned = checks.iter().filter(|c| c.outcome == Outcome::Warn).count();
+    out += &match (failed, warned) {
+        (0, 0) => "all checks passed".to_string(),
+        (0, w) => format!("passed with {} warning(s)", w),
+        (f, _) => format!("{} check(s) failed", f),
+    };
+    out
+}
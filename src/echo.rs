@@ -0,0 +1,63 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use uuid::Uuid;
+
+use crate::api::{self, State};
+use crate::health;
+use crate::log::Level;
+use crate::pow;
+use crate::task;
+use crate::xlog;
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route("/healthz", web::get().to(health::healthz))
+        .route("/challenge", web::get().to(pow::challenge))
+        .route("/stats", web::get().to(api::stats))
+        .route("/ws", web::get().to(handler));
+}
+
+pub async fn handler(req: HttpRequest, stream: web::Payload, state: web::Data<State>) -> actix_web::Result<HttpResponse> {
+    if state.pow.enabled() && !pow::solution(&req).is_some_and(|s| state.pow.verify(&s)) {
+        return Ok(HttpResponse::Forbidden().body("proof of work required, see /challenge"));
+    }
+    let peer = req.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|| "-".into());
+    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let id = Uuid::new_v4();
+    let recorder = state.recorder.clone();
+    if let Some(recorder) = &recorder {
+        recorder.open_socket(id, req.headers());
+    }
+    let stats = state.stats.clone();
+    stats.connect();
+    task::spawn_local(&format!("echo {}", peer), async move {
+        xlog!(Level::Debug, target: "echo", "{} connected", peer);
+        while let Some(Ok(msg)) = msg_stream.next().await {
+            if let Some(recorder) = &recorder {
+                recorder.frame(id, &msg);
+            }
+            let sent = match msg {
+                actix_ws::Message::Text(text) => {
+                    stats.record_push(text.len());
+                    session.text(text).await
+                }
+                actix_ws::Message::Binary(bytes) => {
+                    stats.record_push(bytes.len());
+                    session.binary(bytes).await
+                }
+                actix_ws::Message::Ping(bytes) => session.pong(&bytes).await,
+                actix_ws::Message::Close(reason) => {
+                    let _ = session.close(reason).await;
+                    break;
+                }
+                _ => Ok(()),
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+        stats.disconnect();
+        xlog!(Level::Debug, target: "echo", "{} disconnected", peer);
+    });
+    Ok(response)
+}
@@ -5,14 +5,16 @@ use uuid::Uuid;
 #[derive(Clone, Debug)]
 pub struct Entity {
     pub id: Uuid,
+    pub at: u64,
     pub load: Bytes,
     pub tags: BTreeMap<String, String>,
 }
 
 impl Entity {
-    pub fn new(load: Bytes, tags: BTreeMap<String, String>) -> Self {
+    pub fn new(load: Bytes, tags: BTreeMap<String, String>, at: u64) -> Self {
         Self {
             id: Uuid::new_v4(),
+            at,
             load,
             tags,
         }
@@ -0,0 +1,182 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::entity::Entity;
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Federation {
+    pub name: String,
+    client: reqwest::Client,
+}
+
+impl Federation {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            client: reqwest::Client::builder()
+                .timeout(SEND_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn peers(channel: &Entity) -> Vec<&str> {
+    channel.tags.get("peers")
+        .map(|list| list.split('+').filter(|p| !p.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+fn home(room: &str) -> &str {
+    room.rsplit_once('@').map(|(_, home)| home).unwrap_or("")
+}
+
+async fn peer(store: &dyn Store, name: &str) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "peer".into());
+    pred.insert("name".into(), name.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+async fn channel(store: &dyn Store, room: &str) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "channel".into());
+    pred.insert("federate".into(), room.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+async fn sender(store: &dyn Store, from: Option<&String>) -> String {
+    let Some(from) = from else {
+        return "-".into();
+    };
+    if let Ok(id) = from.parse::<Uuid>() {
+        if let Ok(Some(user)) = store.read(id).await {
+            if let Some(name) = user.tags.get("name") {
+                return name.clone();
+            }
+        }
+    }
+    from.clone()
+}
+
+pub fn start(state: web::Data<State>) {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    let (_, mut rx) = state.hub.subscribe(pred);
+    task::spawn("federation", async move {
+        while let Some(entity) = rx.recv().await {
+            forward(&state, &entity).await;
+        }
+    });
+}
+
+async fn forward(state: &State, entity: &Entity) {
+    let store = &*state.store;
+    let Some(room_id) = entity.tags.get("in").and_then(|v| v.parse::<Uuid>().ok()) else {
+        return;
+    };
+    let Ok(Some(channel)) = store.read(room_id).await else {
+        return;
+    };
+    let Some(room) = channel.tags.get("federate") else {
+        return;
+    };
+    let origin = entity.tags.get("origin").map(String::as_str);
+    if origin.is_some() && home(room) != state.federation.name {
+        return;
+    }
+
+    let from = match origin {
+        Some(_) => entity.tags.get("from").cloned().unwrap_or_else(|| "-".into()),
+        None => format!("{}@{}", sender(store, entity.tags.get("from")).await, state.federation.name),
+    };
+    let payload = json!({
+        "room": room,
+        "fid": entity.tags.get("fid").cloned().unwrap_or_else(|| entity.id.to_string()),
+        "from": from,
+        "at": entity.at,
+        "load": STANDARD.encode(&entity.load),
+    });
+
+    for name in peers(&channel).into_iter().filter(|p| Some(*p) != origin) {
+        let Some(peer) = peer(store, name).await else {
+            xlog!(Level::Warn, target: "federation", "room {} lists unknown peer {}", room, name);
+            continue;
+        };
+        let Some(url) = peer.tags.get("url") else {
+            continue;
+        };
+        let result = state.federation.client
+            .post(format!("{}/federation/inbox", url.trim_end_matches('/')))
+            .header("Authorization", format!("Bearer {}", String::from_utf8_lossy(&peer.load)))
+            .header("x-morce-origin", &state.federation.name)
+            .json(&payload)
+            .send().await;
+        match result {
+            Ok(res) if res.status().is_success() => {}
+            Ok(res) => xlog!(Level::Warn, target: "federation", "peer {} refused {}: {}", name, room, res.status()),
+            Err(err) => xlog!(Level::Warn, target: "federation", "peer {} unreachable: {}", name, err),
+        }
+    }
+}
+
+pub async fn inbox(
+    state: web::Data<State>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let store = &*state.store;
+    let origin = req.headers().get("x-morce-origin").and_then(|v| v.to_str().ok())
+        .ok_or(actix_web::error::ErrorUnauthorized("missing origin"))?;
+    let secret = req.headers().get("Authorization").and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or(actix_web::error::ErrorUnauthorized("missing secret"))?;
+    let peer = peer(store, origin).await
+        .filter(|p| !p.load.is_empty() && p.load.as_ref() == secret.as_bytes())
+        .ok_or(actix_web::error::ErrorUnauthorized("unknown peer"))?;
+
+    let payload: Value = serde_json::from_slice(&body)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid json"))?;
+    let field = |key: &str| payload.get(key).and_then(Value::as_str);
+    let (Some(room), Some(fid), Some(from), Some(load)) = (field("room"), field("fid"), field("from"), field("load")) else {
+        return Err(actix_web::error::ErrorBadRequest("expected room, fid, from, and load"));
+    };
+    let load = STANDARD.decode(load).map_err(|_| actix_web::error::ErrorBadRequest("load is not base64"))?;
+
+    let channel = channel(store, room).await
+        .filter(|c| peers(c).contains(&origin))
+        .ok_or(actix_web::error::ErrorForbidden("room is not shared with this peer"))?;
+
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    pred.insert("fid".into(), fid.to_string());
+    if !store.query(&pred).await?.is_empty() {
+        return Ok(HttpResponse::Ok().insert_header(("x-duplicate", "true")).finish());
+    }
+
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "msg".into());
+    tags.insert("in".into(), channel.id.to_string());
+    tags.insert("from".into(), from.replace([',', '='], "_"));
+    tags.insert("origin".into(), peer.tags.get("name").cloned().unwrap_or_default());
+    tags.insert("fid".into(), fid.to_string());
+    let (tags, load) = state.hooks.filter(&state, None, tags, load.into()).await?;
+    let entity = store.create(load, tags).await?;
+    state.stats.record_in(&entity);
+    state.hub.publish(&entity);
+    Ok(HttpResponse::Created()
+        .insert_header(("x-entity-id", entity.id.to_string()))
+        .finish())
+}
@@ -0,0 +1,385 @@
+use actix_web::web;
+use async_trait::async_trait;
+use reqwest::header::LOCATION;
+use reqwest::Url;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::{self, State};
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::guard;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::room;
+use crate::store::Store;
+use crate::task;
+use crate::unfurl;
+use crate::xlog;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_FEED: usize = 2 << 20;
+const MAX_REDIRECTS: usize = 3;
+const MAX_SEEN: usize = 500;
+const MAX_POSTS: usize = 5;
+
+pub struct Feeds {
+    interval: Duration,
+}
+
+impl Feeds {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+
+    async fn fetch(&self, raw: &str) -> Result<(String, Vec<Item>), String> {
+        let mut url = Url::parse(raw).map_err(|e| e.to_string())?;
+        for _ in 0..=MAX_REDIRECTS {
+            let addr = unfurl::check(&url).await?;
+            let mut client = reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none())
+                .no_proxy()
+                .user_agent(concat!("morce/", env!("CARGO_PKG_VERSION")));
+            if let Some(host) = url.domain() {
+                client = client.resolve(host, addr);
+            }
+            let client = client.build().map_err(|e| e.to_string())?;
+            let mut response = client.get(url.clone()).send().await.map_err(|e| e.without_url().to_string())?;
+            if response.status().is_redirection() {
+                let location = response.headers().get(LOCATION).and_then(|v| v.to_str().ok())
+                    .ok_or("redirect without a location")?;
+                url = url.join(location).map_err(|e| e.to_string())?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(format!("answered {}", response.status()));
+            }
+            if response.content_length().is_some_and(|len| len > MAX_FEED as u64) {
+                return Err("feed is too large".into());
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(|e| e.without_url().to_string())? {
+                if body.len() + chunk.len() > MAX_FEED {
+                    return Err("feed is too large".into());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            return parse(&String::from_utf8_lossy(&body)).ok_or("not an RSS or Atom feed".into());
+        }
+        Err("too many redirects".into())
+    }
+}
+
+struct Item {
+    guid: String,
+    title: String,
+    link: String,
+}
+
+pub fn opening(doc: &str, name: &str, from: usize) -> Option<(usize, usize)> {
+    let needle = format!("<{}", name);
+    let mut at = from;
+    while let Some(pos) = doc.get(at..)?.find(&needle) {
+        let start = at + pos;
+        let after = start + 1 + name.len();
+        match doc[after..].chars().next() {
+            Some(c) if c == '>' || c == '/' || c.is_whitespace() => {
+                let end = after + doc[after..].find('>')?;
+                return Some((start, end + 1));
+            }
+            _ => at = after,
+        }
+    }
+    None
+}
+
+fn blocks<'a>(doc: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let close = format!("</{}>", name);
+    let mut at = 0;
+    while let Some((start, open)) = opening(doc, name, at) {
+        let Some(end) = doc[open..].find(&close) else {
+            break;
+        };
+        found.push(&doc[start..open + end]);
+        at = open + end + close.len();
+    }
+    found
+}
+
+pub fn element<'a>(block: &'a str, name: &str) -> Option<&'a str> {
+    let (start, open) = opening(block, name, 0)?;
+    if block[start..open].ends_with("/>") {
+        return None;
+    }
+    let end = block[open..].find(&format!("</{}>", name))?;
+    Some(&block[open..open + end])
+}
+
+pub fn attribute(tag: &str, name: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let key = format!(" {}={}", name, quote);
+        if let Some(pos) = tag.find(&key) {
+            let rest = &tag[pos + key.len()..];
+            return Some(entities(&rest[..rest.find(quote)?]));
+        }
+    }
+    None
+}
+
+fn entities(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let Some(end) = rest.find(';').filter(|&end| end <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let code = &rest[1..end];
+        let decoded = match code {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => code.strip_prefix("#x").or_else(|| code.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| code.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn text(raw: &str) -> String {
+    let raw = raw.trim();
+    let decoded = match raw.strip_prefix("<![CDATA[").and_then(|r| r.strip_suffix("]]>")) {
+        Some(data) => data.to_string(),
+        None => entities(raw),
+    };
+    let mut plain = String::new();
+    let mut tag = false;
+    for c in decoded.chars() {
+        match c {
+            '<' => tag = true,
+            '>' if tag => tag = false,
+            c if !tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn atom_link(entry: &str) -> Option<String> {
+    let mut at = 0;
+    while let Some((start, end)) = opening(entry, "link", at) {
+        let tag = &entry[start..end];
+        if attribute(tag, "rel").is_none_or(|rel| rel == "alternate") {
+            return attribute(tag, "href");
+        }
+        at = end;
+    }
+    None
+}
+
+fn parse(doc: &str) -> Option<(String, Vec<Item>)> {
+    let atom = opening(doc, "feed", 0).is_some() && opening(doc, "rss", 0).is_none();
+    if !atom && opening(doc, "rss", 0).is_none() && opening(doc, "rdf:RDF", 0).is_none() {
+        return None;
+    }
+    let (kind, id) = if atom { ("entry", "id") } else { ("item", "guid") };
+    let head = opening(doc, kind, 0).map_or(doc, |(start, _)| &doc[..start]);
+    let title = element(head, "title").map(text).unwrap_or_default();
+    let items = blocks(doc, kind).into_iter().filter_map(|block| {
+        let title = element(block, "title").map(text).unwrap_or_default();
+        let link = match atom {
+            true => atom_link(block).unwrap_or_default(),
+            false => element(block, "link").map(text).unwrap_or_default(),
+        };
+        let guid = element(block, id).map(text).filter(|g| !g.is_empty())
+            .or_else(|| Some(link.clone()).filter(|l| !l.is_empty()))
+            .or_else(|| Some(title.clone()).filter(|t| !t.is_empty()))?;
+        Some(Item { guid, title, link })
+    }).collect();
+    Some((title, items))
+}
+
+struct Watch {
+    url: String,
+    seen: Vec<String>,
+}
+
+impl Watch {
+    fn of(feed: &Entity) -> Watch {
+        let load = String::from_utf8_lossy(&feed.load);
+        let mut lines = load.lines().map(String::from);
+        Watch { url: lines.next().unwrap_or_default(), seen: lines.collect() }
+    }
+
+    fn load(&self) -> bytes::Bytes {
+        let mut out = self.url.clone();
+        for guid in self.seen.iter().take(MAX_SEEN) {
+            out.push('\n');
+            out.push_str(guid);
+        }
+        out.into()
+    }
+}
+
+fn clean(text: &str) -> String {
+    layout::truncate(&text.replace([',', '=', '\n'], " "), 80)
+}
+
+async fn feeds(store: &dyn Store, room: Option<Uuid>) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "feed".into());
+    if let Some(room) = room {
+        pred.insert("in".into(), room.to_string());
+    }
+    let mut found = store.query(&pred).await.unwrap_or_default();
+    found.sort_by_key(|f| f.at);
+    found
+}
+
+async fn post(state: &State, feed: &Entity, room: &str, item: &Item) {
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "msg".into());
+    tags.insert("from".into(), "system".into());
+    tags.insert("in".into(), room.to_string());
+    tags.insert("feed".into(), feed.id.to_string());
+    let source = feed.tags.get("title").cloned().unwrap_or_else(|| tr("feed.untitled").into());
+    let text = match item.link.is_empty() {
+        true => format!("{}: {}", source, item.title),
+        false => format!("{}: {} {}", source, item.title, item.link),
+    };
+    if let Err(err) = api::accept(state, None, None, Some(feed.id.to_string()), tags, text.into()).await {
+        xlog!(Level::Warn, target: "feed", "{}: {}", Watch::of(feed).url, err);
+    }
+}
+
+async fn poll(state: &State, feed: &Entity) -> Result<usize, String> {
+    let Some(room) = feed.tags.get("in") else {
+        return Ok(0);
+    };
+    let mut watch = Watch::of(feed);
+    let (_, items) = state.feeds.fetch(&watch.url).await?;
+    let seen: BTreeSet<&str> = watch.seen.iter().map(String::as_str).collect();
+    let fresh: Vec<&Item> = items.iter().filter(|item| !seen.contains(item.guid.as_str())).collect();
+    if fresh.is_empty() {
+        return Ok(0);
+    }
+    for item in fresh.iter().take(MAX_POSTS).rev() {
+        post(state, feed, room, item).await;
+    }
+    let mut guids: Vec<String> = fresh.iter().map(|item| item.guid.clone()).collect();
+    guids.append(&mut watch.seen);
+    watch.seen = guids;
+    state.store.update(feed.id, watch.load(), feed.tags.clone()).await.map_err(|e| e.to_string())?;
+    Ok(fresh.len())
+}
+
+pub fn start(state: web::Data<State>) {
+    task::spawn("feed", async move {
+        let mut ticker = tokio::time::interval(state.feeds.interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            for feed in feeds(&*state.store, None).await {
+                match poll(&state, &feed).await {
+                    Ok(0) => {}
+                    Ok(n) => xlog!(Level::Debug, target: "feed", "{} new entries from {}", n, Watch::of(&feed).url),
+                    Err(err) => xlog!(Level::Warn, target: "feed", "{}: {}", Watch::of(&feed).url, err),
+                }
+            }
+        }
+    });
+}
+
+pub struct Feed;
+
+#[async_trait]
+impl Command for Feed {
+    fn help(&self) -> Help {
+        Help { category: Category::Rooms, role: Role::User, usage: "<room> [add <url>|remove <n>]", about: "help.feed" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("feed.moderator"))?;
+        let mut parts = args.split_whitespace();
+        let room = parts.next().ok_or(tr("feed.usage"))?;
+        let store = &*ctx.state.store;
+        let channel = room::find(store, room).await.ok_or_else(|| trf("join.noroom", &[room]))?;
+        if !guard::is_moderator(store, actor, channel.id).await {
+            return Err(tr("feed.moderator").into());
+        }
+        let current = feeds(store, Some(channel.id)).await;
+        match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => {
+                if current.is_empty() {
+                    return Ok(trf("feed.empty", &[room]));
+                }
+                let rows: Vec<Vec<String>> = current.iter().enumerate()
+                    .map(|(n, feed)| vec![
+                        (n + 1).to_string(),
+                        feed.tags.get("title").cloned().unwrap_or_default(),
+                        layout::truncate(&Watch::of(feed).url, 60),
+                    ])
+                    .collect();
+                Ok(layout::table(&["#", tr("feed.title"), tr("feed.url")], &rows))
+            }
+            (Some("add"), Some(url), None) => {
+                if !matches!(reqwest::Url::parse(url).map(|u| u.scheme().to_string()).as_deref(), Ok("http" | "https")) {
+                    return Err(trf("feed.badurl", &[&layout::truncate(url, 64)]));
+                }
+                if current.iter().any(|feed| Watch::of(feed).url == url) {
+                    return Err(trf("feed.exists", &[room]));
+                }
+                let (title, items) = ctx.state.feeds.fetch(url).await.map_err(|err| {
+                    xlog!(Level::Debug, target: "feed", "{}: {}", url, err);
+                    tr("feed.failed")
+                })?;
+                let title = match clean(&title) {
+                    title if title.is_empty() => clean(url),
+                    title => title,
+                };
+                let watch = Watch { url: url.to_string(), seen: items.into_iter().map(|item| item.guid).collect() };
+                let mut tags = BTreeMap::new();
+                tags.insert("kind".into(), "feed".into());
+                tags.insert("in".into(), channel.id.to_string());
+                tags.insert("by".into(), actor.to_string());
+                tags.insert("title".into(), title.clone());
+                store.create(watch.load(), tags).await.map_err(|e| e.to_string())?;
+                let minutes = (ctx.state.feeds.interval.as_secs() / 60).to_string();
+                Ok(trf("feed.added", &[&title, room, &minutes]))
+            }
+            (Some("remove"), Some(n), None) => {
+                let feed = n.parse::<usize>().ok()
+                    .and_then(|n| current.get(n.checked_sub(1)?))
+                    .ok_or_else(|| trf("feed.nofeed", &[n]))?;
+                store.delete(feed.id).await.map_err(|e| e.to_string())?;
+                Ok(trf("feed.removed", &[feed.tags.get("title").map_or("", String::as_str), room]))
+            }
+            _ => Err(tr("feed.usage").into()),
+        }
+    }
+}
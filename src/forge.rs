@@ -0,0 +1,260 @@
+use actix_web::HttpRequest;
+use serde_json::Value;
+use std::fmt;
+
+use crate::layout;
+
+const MAX_TITLE: usize = 72;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    GitHub,
+    GitLab,
+    Gitea,
+}
+
+impl Source {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "github" => Some(Source::GitHub),
+            "gitlab" => Some(Source::GitLab),
+            "gitea" | "forgejo" => Some(Source::Gitea),
+            _ => None,
+        }
+    }
+
+    fn header(&self) -> &'static str {
+        match self {
+            Source::GitHub => "X-GitHub-Event",
+            Source::GitLab => "X-Gitlab-Event",
+            Source::Gitea => "X-Gitea-Event",
+        }
+    }
+
+    pub fn detect(req: &HttpRequest) -> Option<Self> {
+        [Source::Gitea, Source::GitLab, Source::GitHub].into_iter()
+            .find(|source| req.headers().contains_key(source.header()))
+    }
+
+    pub fn event(&self, req: &HttpRequest, payload: &Value) -> String {
+        match self {
+            Source::GitLab => get(payload, "/object_kind").to_string(),
+            _ => req.headers().get(self.header()).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::GitHub => "github",
+            Source::GitLab => "gitlab",
+            Source::Gitea => "gitea",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tone {
+    Ok,
+    Fail,
+    Info,
+}
+
+impl fmt::Display for Tone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Tone::Ok => "ok",
+            Tone::Fail => "fail",
+            Tone::Info => "info",
+        })
+    }
+}
+
+fn get<'a>(payload: &'a Value, path: &str) -> &'a str {
+    payload.pointer(path).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn number(payload: &Value, path: &str) -> String {
+    payload.pointer(path).and_then(Value::as_u64).map(|n| n.to_string()).unwrap_or_default()
+}
+
+fn first_line(text: &str) -> String {
+    layout::truncate(text.lines().next().unwrap_or_default().trim(), MAX_TITLE)
+}
+
+fn short(sha: &str) -> &str {
+    sha.get(..7).unwrap_or(sha)
+}
+
+fn branch(git_ref: &str) -> &str {
+    git_ref.strip_prefix("refs/heads/").or_else(|| git_ref.strip_prefix("refs/tags/")).unwrap_or(git_ref)
+}
+
+fn line(repo: &str, text: String, url: &str) -> String {
+    match url.is_empty() {
+        true => format!("[{}] {}", repo, text),
+        false => format!("[{}] {} {}", repo, text, url),
+    }
+}
+
+fn pushed(who: &str, count: usize, to: &str, head: &Value) -> String {
+    let (sha, message) = (short(get(head, "/id")), first_line(get(head, "/message")));
+    match count {
+        1 => format!("{} pushed 1 commit to {}: {} {}", who, to, sha, message),
+        _ => format!("{} pushed {} commits to {}: {} {} (+{} more)", who, count, to, sha, message, count - 1),
+    }
+}
+
+fn verdict(conclusion: &str) -> Tone {
+    match conclusion {
+        "success" | "succeeded" | "passed" => Tone::Ok,
+        "failure" | "failed" | "timed_out" | "cancelled" | "canceled" | "action_required" | "error" => Tone::Fail,
+        _ => Tone::Info,
+    }
+}
+
+fn github(event: &str, p: &Value, gitea: bool) -> (String, Tone) {
+    let repo = get(p, "/repository/full_name");
+    let who = match get(p, "/sender/login") {
+        "" => get(p, "/pusher/name"),
+        login => login,
+    };
+    let action = get(p, "/action");
+    match event {
+        "push" => {
+            let to = branch(get(p, "/ref"));
+            let list = p.get("commits").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+            let count = p.get("total_commits").and_then(Value::as_u64).map_or(list.len(), |n| n as usize);
+            let head = match p.get("head_commit").filter(|c| !c.is_null()) {
+                Some(head) => head,
+                None => list.last().unwrap_or(&Value::Null),
+            };
+            let compare = match gitea {
+                true => get(p, "/compare_url"),
+                false => get(p, "/compare"),
+            };
+            let text = match (p.get("deleted").and_then(Value::as_bool), count) {
+                (Some(true), _) => format!("{} deleted {}", who, to),
+                (_, 0) => format!("{} created {}", who, to),
+                _ => pushed(who, count, to, head),
+            };
+            (line(repo, text, compare), Tone::Info)
+        }
+        "pull_request" => {
+            let merged = p.pointer("/pull_request/merged").and_then(Value::as_bool) == Some(true);
+            let verb = if action == "closed" && merged { "merged" } else { action };
+            let text = format!("{} {} PR #{}: {}", who, verb, number(p, "/number"), first_line(get(p, "/pull_request/title")));
+            (line(repo, text, get(p, "/pull_request/html_url")), if merged { Tone::Ok } else { Tone::Info })
+        }
+        "issues" => {
+            let text = format!("{} {} issue #{}: {}", who, action, number(p, "/issue/number"), first_line(get(p, "/issue/title")));
+            (line(repo, text, get(p, "/issue/html_url")), Tone::Info)
+        }
+        "issue_comment" => {
+            let text = format!("{} commented on #{}: {}", who, number(p, "/issue/number"), first_line(get(p, "/comment/body")));
+            (line(repo, text, get(p, "/comment/html_url")), Tone::Info)
+        }
+        "release" => {
+            let text = format!("{} {} release {}", who, action, get(p, "/release/tag_name"));
+            (line(repo, text, get(p, "/release/html_url")), Tone::Info)
+        }
+        "workflow_run" | "check_suite" | "check_run" => {
+            let key = match event {
+                "workflow_run" => "/workflow_run",
+                "check_suite" => "/check_suite",
+                _ => "/check_run",
+            };
+            let name = match get(p, &format!("{}/name", key)) {
+                "" => get(p, "/check_suite/app/name"),
+                name => name,
+            };
+            let conclusion = match get(p, &format!("{}/conclusion", key)) {
+                "" => get(p, &format!("{}/status", key)),
+                conclusion => conclusion,
+            };
+            let text = format!("{} {} on {}", name, conclusion, get(p, &format!("{}/head_branch", key)));
+            (line(repo, text, get(p, &format!("{}/html_url", key))), verdict(conclusion))
+        }
+        "status" => {
+            let text = format!("{} {} on {}", get(p, "/context"), get(p, "/state"), short(get(p, "/sha")));
+            (line(repo, text, get(p, "/target_url")), verdict(get(p, "/state")))
+        }
+        "ping" => (line(repo, format!("webhook connected: {}", get(p, "/zen")), ""), Tone::Ok),
+        _ => (line(repo, format!("{} {} {}", who, event, action).trim_end().to_string(), ""), Tone::Info),
+    }
+}
+
+fn gitlab(kind: &str, p: &Value) -> (String, Tone) {
+    let repo = get(p, "/project/path_with_namespace");
+    let who = match get(p, "/user_name") {
+        "" => get(p, "/user/username"),
+        name => name,
+    };
+    let attrs = p.get("object_attributes").unwrap_or(&Value::Null);
+    let past = |action: &str| match action {
+        "open" => "opened".to_string(),
+        "close" => "closed".to_string(),
+        "reopen" => "reopened".to_string(),
+        "update" => "updated".to_string(),
+        "merge" => "merged".to_string(),
+        other => other.to_string(),
+    };
+    match kind {
+        "push" | "tag_push" => {
+            let to = branch(get(p, "/ref"));
+            let count = p.get("total_commits_count").and_then(Value::as_u64).unwrap_or_default() as usize;
+            let list = p.get("commits").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+            let sha = get(p, "/checkout_sha");
+            let head = list.iter().find(|c| get(c, "/id") == sha).or(list.last()).unwrap_or(&Value::Null);
+            let text = match (kind, count) {
+                ("tag_push", _) => format!("{} pushed tag {}", who, to),
+                (_, 0) if sha.is_empty() => format!("{} deleted {}", who, to),
+                (_, 0) => format!("{} created {}", who, to),
+                _ => pushed(who, count, to, head),
+            };
+            (line(repo, text, get(head, "/url")), Tone::Info)
+        }
+        "merge_request" => {
+            let action = get(attrs, "/action");
+            let text = format!("{} {} MR !{}: {}", who, past(action), number(attrs, "/iid"), first_line(get(attrs, "/title")));
+            (line(repo, text, get(attrs, "/url")), if action == "merge" { Tone::Ok } else { Tone::Info })
+        }
+        "issue" => {
+            let text = format!("{} {} issue #{}: {}", who, past(get(attrs, "/action")), number(attrs, "/iid"), first_line(get(attrs, "/title")));
+            (line(repo, text, get(attrs, "/url")), Tone::Info)
+        }
+        "note" => {
+            let text = format!("{} commented: {}", who, first_line(get(attrs, "/note")));
+            (line(repo, text, get(attrs, "/url")), Tone::Info)
+        }
+        "pipeline" => {
+            let status = get(attrs, "/status");
+            let id = number(attrs, "/id");
+            let text = format!("pipeline #{} {} on {}", id, status, get(attrs, "/ref"));
+            let url = match get(attrs, "/url") {
+                "" => format!("{}/-/pipelines/{}", get(p, "/project/web_url"), id),
+                url => url.to_string(),
+            };
+            (line(repo, text, &url), verdict(status))
+        }
+        "build" => {
+            let status = get(p, "/build_status");
+            let text = format!("job {} {} on {}", get(p, "/build_name"), status, get(p, "/ref"));
+            (line(get(p, "/project_name"), text, ""), verdict(status))
+        }
+        "release" => {
+            let text = format!("{} release {}", get(p, "/action"), get(p, "/tag"));
+            (line(repo, text, get(p, "/url")), Tone::Info)
+        }
+        _ => (line(repo, format!("{} {}", who, kind).trim().to_string(), ""), Tone::Info),
+    }
+}
+
+pub fn format(source: Source, event: &str, payload: &Value) -> (String, Tone) {
+    match source {
+        Source::GitHub => github(event, payload, false),
+        Source::Gitea => github(event, payload, true),
+        Source::GitLab => gitlab(event, payload),
+    }
+}
@@ -2,9 +2,10 @@ use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 
 use crate::entity::Entity;
+use crate::time;
 
 pub fn entity(e: &Entity) -> String {
-    let mut out = format!("id:{}\ntags:", e.id);
+    let mut out = format!("id:{}\nat:{}\ntags:", e.id, time::iso_millis(e.at));
     for (i, (k, v)) in e.tags.iter().enumerate() {
         if i > 0 {
             out.push(',');
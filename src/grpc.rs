@@ -0,0 +1,281 @@
+use actix_web::web;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::account;
+use crate::api::{self, Posted, State};
+use crate::entity::Entity;
+use crate::guard;
+use crate::hub::Hub;
+use crate::log::Level;
+use crate::parse;
+use crate::predicate::Predicate;
+use crate::presence;
+use crate::room;
+use crate::store::Store;
+use crate::time;
+use crate::xlog;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChatMessage {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub at: String,
+    #[prost(map = "string, string", tag = "3")]
+    pub tags: HashMap<String, String>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub load: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SendMessageRequest {
+    #[prost(string, tag = "1")]
+    pub room: String,
+    #[prost(string, tag = "2")]
+    pub to: String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub load: Vec<u8>,
+    #[prost(map = "string, string", tag = "4")]
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StreamMessagesRequest {
+    #[prost(string, tag = "1")]
+    pub filter: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListUsersRequest {
+    #[prost(string, tag = "1")]
+    pub prefix: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct User {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub status: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListUsersResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub users: Vec<User>,
+}
+
+impl From<&Entity> for ChatMessage {
+    fn from(entity: &Entity) -> Self {
+        ChatMessage {
+            id: entity.id.to_string(),
+            at: time::iso_millis(entity.at),
+            tags: entity.tags.clone().into_iter().collect(),
+            load: entity.load.to_vec(),
+        }
+    }
+}
+
+fn status(err: actix_web::Error) -> Status {
+    let message = err.to_string();
+    match err.as_response_error().status_code().as_u16() {
+        400 | 413 => Status::invalid_argument(message),
+        401 => Status::unauthenticated(message),
+        403 => Status::permission_denied(message),
+        404 => Status::not_found(message),
+        409 => Status::already_exists(message),
+        429 => Status::resource_exhausted(message),
+        _ => Status::internal(message),
+    }
+}
+
+struct Unsubscribe(Arc<Hub>, Uuid);
+
+impl Drop for Unsubscribe {
+    fn drop(&mut self) {
+        self.0.unsubscribe(self.1);
+    }
+}
+
+#[derive(Clone)]
+pub struct Chat {
+    state: web::Data<State>,
+}
+
+impl Chat {
+    pub fn new(state: web::Data<State>) -> Self {
+        Self { state }
+    }
+
+    async fn actor<T>(&self, request: &Request<T>, action: &str, resource: Option<Uuid>) -> Result<Option<Uuid>, Status> {
+        if let Some(peer) = request.remote_addr() {
+            if self.state.bans.contains(peer.ip()) {
+                return Err(Status::permission_denied("banned"));
+            }
+        }
+        let header = request.metadata().get("authorization").and_then(|v| v.to_str().ok());
+        guard::check(&*self.state.store, header, action, resource).await
+            .map_err(|denied| Status::permission_denied(denied.to_string()))
+    }
+
+    async fn send_message(&self, request: Request<SendMessageRequest>) -> Result<Response<ChatMessage>, Status> {
+        let state = &self.state;
+        let room = match request.get_ref().room.as_str() {
+            "" => None,
+            name => Some(room::find(&*state.store, name).await.ok_or(Status::not_found(format!("no room {}", name)))?),
+        };
+        let actor = self.actor(&request, "create", room.as_ref().map(|c| c.id)).await?;
+        let metadata = |key: &str| request.metadata().get(key).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let session = guard::session_id(metadata("authorization").as_deref()).and_then(Result::ok);
+        let request_id = metadata("x-request-id");
+        let seq = match metadata("x-seq") {
+            Some(raw) => Some(raw.parse::<u64>().map_err(|_| Status::invalid_argument("x-seq must be a number"))?),
+            None => None,
+        };
+        let ip = request.remote_addr().map(|addr| addr.ip());
+        let body = request.into_inner();
+        let mut tags: std::collections::BTreeMap<String, String> = body.tags.into_iter().collect();
+        if tags.iter().any(|(k, v)| format!("{}{}", k, v).contains([',', '=', '\n'])) {
+            return Err(Status::invalid_argument("tags cannot contain commas, equals signs, or newlines"));
+        }
+        tags.insert("kind".into(), "msg".into());
+        if let Some(channel) = room {
+            tags.insert("in".into(), channel.id.to_string());
+        }
+        if !body.to.is_empty() {
+            let to: Uuid = body.to.parse().map_err(|_| Status::invalid_argument("to must be a user id"))?;
+            tags.insert("to".into(), to.to_string());
+        }
+        if let Some(actor) = actor {
+            tags.insert("from".into(), actor.to_string());
+        }
+        let post = api::Post { actor, session, request_id, seq, ip, tags, body: body.load.into() };
+        let entity = match api::post(state, post).await.map_err(status)? {
+            Posted::Created(entity, _) | Posted::Duplicate(entity) | Posted::Private(entity) => entity,
+        };
+        Ok(Response::new(ChatMessage::from(&entity)))
+    }
+
+    async fn stream_messages(&self, request: Request<StreamMessagesRequest>) -> Result<Response<BoxStream<'static, Result<ChatMessage, Status>>>, Status> {
+        self.actor(&request, "subscribe", None).await?;
+        let predicate = parse::predicate(&request.get_ref().filter);
+        if predicate.is_empty() {
+            return Err(Status::invalid_argument("filter is empty, try kind=msg&in=<room id>"));
+        }
+        let (id, rx) = self.state.hub.subscribe(predicate);
+        xlog!(Level::Debug, target: "grpc", "subscription {} on {}", id, request.get_ref().filter);
+        let guard = Unsubscribe(self.state.hub.clone(), id);
+        let messages = stream::unfold((rx, guard), |(mut rx, guard)| async move {
+            let entity = loop {
+                let entity = rx.recv().await?;
+                if !account::secret(&entity) {
+                    break entity;
+                }
+            };
+            Some((Ok(ChatMessage::from(&entity)), (rx, guard)))
+        });
+        Ok(Response::new(messages.boxed()))
+    }
+
+    async fn list_users(&self, request: Request<ListUsersRequest>) -> Result<Response<ListUsersResponse>, Status> {
+        self.actor(&request, "read", None).await?;
+        let prefix = &request.get_ref().prefix;
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "user".into());
+        let found = self.state.store.query(&pred).await.map_err(|e| Status::internal(e.to_string()))?;
+        let field = |user: &Entity, key: &str| user.tags.get(key).cloned().unwrap_or_default();
+        let mut users = Vec::new();
+        for user in found.iter().filter(|user| field(user, "name").starts_with(prefix.as_str())) {
+            let status = presence::status(&self.state, user.id).await.unwrap_or_default();
+            users.push(User { id: user.id.to_string(), name: field(user, "name"), status });
+        }
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Response::new(ListUsersResponse { users }))
+    }
+}
+
+macro_rules! unary {
+    ($chat:expr, $req:expr, $method:ident, $request:ty, $response:ty) => {{
+        struct Method(Chat);
+        impl tonic::server::UnaryService<$request> for Method {
+            type Response = $response;
+            type Future = BoxFuture<Response<$response>, Status>;
+            fn call(&mut self, request: Request<$request>) -> Self::Future {
+                let chat = self.0.clone();
+                Box::pin(async move { chat.$method(request).await })
+            }
+        }
+        let (chat, req) = ($chat, $req);
+        Box::pin(async move {
+            let mut grpc = tonic::server::Grpc::new(tonic::codec::ProstCodec::default());
+            Ok(grpc.unary(Method(chat), req).await)
+        })
+    }};
+}
+
+impl<B> Service<http::Request<B>> for Chat
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        match req.uri().path() {
+            "/morce.v1.Chat/SendMessage" => unary!(self.clone(), req, send_message, SendMessageRequest, ChatMessage),
+            "/morce.v1.Chat/ListUsers" => unary!(self.clone(), req, list_users, ListUsersRequest, ListUsersResponse),
+            "/morce.v1.Chat/StreamMessages" => {
+                struct Method(Chat);
+                impl tonic::server::ServerStreamingService<StreamMessagesRequest> for Method {
+                    type Response = ChatMessage;
+                    type ResponseStream = BoxStream<'static, Result<ChatMessage, Status>>;
+                    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+                    fn call(&mut self, request: Request<StreamMessagesRequest>) -> Self::Future {
+                        let chat = self.0.clone();
+                        Box::pin(async move { chat.stream_messages(request).await })
+                    }
+                }
+                let chat = self.clone();
+                Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic::codec::ProstCodec::default());
+                    Ok(grpc.server_streaming(Method(chat), req).await)
+                })
+            }
+            _ => Box::pin(async move {
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", (tonic::Code::Unimplemented as i32).to_string())
+                    .header(http::header::CONTENT_TYPE, tonic::metadata::GRPC_CONTENT_TYPE)
+                    .body(empty_body())
+                    .unwrap())
+            }),
+        }
+    }
+}
+
+impl tonic::server::NamedService for Chat {
+    const NAME: &'static str = "morce.v1.Chat";
+}
+
+pub async fn serve(addr: SocketAddr, state: web::Data<State>) -> Result<(), String> {
+    xlog!(Level::Info, target: "grpc", "gRPC service morce.v1.Chat on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(Chat::new(state))
+        .serve(addr)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,6 +1,6 @@
-use std::collections::BTreeMap;
 use uuid::Uuid;
 
+use crate::ban;
 use crate::store::Store;
 use crate::predicate::Predicate;
 
@@ -9,6 +9,7 @@ pub enum Denied {
     NoSession,
     NoActor,
     Forbidden,
+    Banned,
 }
 
 impl std::fmt::Display for Denied {
@@ -17,6 +18,7 @@ impl std::fmt::Display for Denied {
             Denied::NoSession => write!(f, "no session"),
             Denied::NoActor => write!(f, "no actor"),
             Denied::Forbidden => write!(f, "forbidden"),
+            Denied::Banned => write!(f, "banned"),
         }
     }
 }
@@ -27,17 +29,20 @@ impl actix_web::ResponseError for Denied {
     }
 }
 
+pub fn session_id(header: Option<&str>) -> Option<Result<Uuid, Denied>> {
+    let token = header?.strip_prefix("Bearer ")?;
+    Some(token.parse::<Uuid>().map_err(|_| Denied::NoSession))
+}
+
 pub async fn check(
     store: &dyn Store,
     header: Option<&str>,
     action: &str,
     resource: Option<Uuid>,
 ) -> Result<Option<Uuid>, Denied> {
-    let session_id = match header {
-        Some(val) if val.starts_with("Bearer ") => {
-            val[7..].parse::<Uuid>().map_err(|_| Denied::NoSession)?
-        }
-        _ => return Ok(None),
+    let session_id = match session_id(header) {
+        Some(id) => id?,
+        None => return Ok(None),
     };
 
     let session = store.read(session_id).await.map_err(|_| Denied::NoSession)?
@@ -47,6 +52,10 @@ pub async fn check(
         .and_then(|v| v.parse::<Uuid>().ok())
         .ok_or(Denied::NoActor)?;
 
+    if ban::user_banned(store, actor).await {
+        return Err(Denied::Banned);
+    }
+
     if resource.is_none() {
         return Ok(Some(actor));
     }
@@ -63,12 +72,34 @@ pub async fn check(
         return Ok(Some(actor));
     }
 
-    let mut any_pred = pred.clone();
-    any_pred.insert("can".into(), "*".into());
-    let any_perms = store.query(&any_pred).await.map_err(|_| Denied::Forbidden)?;
-    if !any_perms.is_empty() {
-        return Ok(Some(actor));
+    let member = matches!(action, "create" | "read" | "subscribe").then_some("post");
+    for can in ["*", "moderate"].into_iter().chain(member) {
+        let mut any_pred = pred.clone();
+        any_pred.insert("can".into(), can.into());
+        let any_perms = store.query(&any_pred).await.map_err(|_| Denied::Forbidden)?;
+        if !any_perms.is_empty() {
+            return Ok(Some(actor));
+        }
     }
 
     Err(Denied::Forbidden)
-}
\ No newline at end of file
+}
+
+pub async fn is_moderator(store: &dyn Store, actor: Uuid, room: Uuid) -> bool {
+    if is_admin(store, actor).await {
+        return true;
+    }
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "perm".into());
+    pred.insert("who".into(), actor.to_string());
+    pred.insert("what".into(), room.to_string());
+    pred.insert("can".into(), "moderate".into());
+    store.query(&pred).await.is_ok_and(|perms| !perms.is_empty())
+}
+
+pub async fn is_admin(store: &dyn Store, actor: Uuid) -> bool {
+    matches!(
+        store.read(actor).await,
+        Ok(Some(user)) if user.tags.get("admin").map(String::as_str) == Some("true")
+    )
+}
@@ -0,0 +1,112 @@
+use actix_web::dev::ServerHandle;
+use actix_web::web;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::TcpListener;
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::Ordering;
+
+use crate::api::State;
+use crate::backup;
+use crate::entity::Entity;
+use crate::log::Level;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+fn send_fd(stream: &UnixStream, fd: RawFd) -> std::io::Result<()> {
+    let mut byte = [b'M'];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr().cast(), iov_len: 1 };
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = space as _;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd);
+        if libc::sendmsg(stream.as_raw_fd(), &msg, 0) < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn recv_fd(stream: &UnixStream) -> std::io::Result<RawFd> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr().cast(), iov_len: 1 };
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = space as _;
+    unsafe {
+        if libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) < 1 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "old server sent no listener"));
+        }
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null() || (*cmsg).cmsg_level != libc::SOL_SOCKET || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Err(Error::new(ErrorKind::InvalidData, "old server sent no listener"));
+        }
+        Ok(std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>()))
+    }
+}
+
+pub fn take_over(path: &str) -> std::io::Result<Option<(TcpListener, Vec<Entity>)>> {
+    let mut stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let fd = recv_fd(&stream)?;
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    let mut snapshot = Vec::new();
+    stream.read_to_end(&mut snapshot)?;
+    let entities = backup::decode(&snapshot).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(Some((listener, entities)))
+}
+
+fn hand(stream: tokio::net::UnixStream, listener: &TcpListener, snapshot: &[u8]) -> std::io::Result<()> {
+    let mut stream = stream.into_std()?;
+    stream.set_nonblocking(false)?;
+    send_fd(&stream, listener.as_raw_fd())?;
+    stream.write_all(snapshot)
+}
+
+pub fn listen(path: &str, listener: TcpListener, state: web::Data<State>, handle: ServerHandle) -> std::io::Result<()> {
+    let _ = std::fs::remove_file(path);
+    let socket = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    let path = path.to_string();
+    task::spawn("handover", async move {
+        while let Ok((stream, _)) = socket.accept().await {
+            handle.pause().await;
+            state.draining.store(true, Ordering::Relaxed);
+            let result = match state.store.snapshot().await {
+                Ok(entities) => backup::encode(&entities).and_then(|snapshot| hand(stream, &listener, &snapshot)),
+                Err(err) => Err(Error::other(err.to_string())),
+            };
+            match result {
+                Ok(()) => {
+                    xlog!(Level::Info, target: "handover", "handed listener to a new server on {}, draining", path);
+                    handle.stop(true).await;
+                    break;
+                }
+                Err(err) => {
+                    xlog!(Level::Warn, target: "handover", "handover failed, still serving: {}", err);
+                    state.draining.store(false, Ordering::Relaxed);
+                    handle.resume().await;
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use actix_web::{web, HttpResponse};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::store::Store;
+
+pub const USAGE: &str = "usage: morce healthcheck [--target ADDR] [--timeout SECS]";
+
+const STORE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub async fn healthz(state: web::Data<State>) -> HttpResponse {
+    if state.draining.load(Ordering::Relaxed) {
+        return HttpResponse::ServiceUnavailable().body("draining");
+    }
+    match tokio::time::timeout(STORE_TIMEOUT, state.store.read(Uuid::nil())).await {
+        Ok(Ok(_)) => HttpResponse::Ok().body("ok"),
+        Ok(Err(err)) => HttpResponse::ServiceUnavailable().body(format!("store: {}", err)),
+        Err(_) => HttpResponse::ServiceUnavailable().body("store: timed out"),
+    }
+}
+
+pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<(String, Duration), String> {
+    let (mut target, mut timeout) = ("127.0.0.1:8080".to_string(), Duration::from_secs(3));
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--target" => target = value,
+            "--timeout" => timeout = value.parse().map(Duration::from_secs)
+                .map_err(|_| format!("--timeout expects seconds, got {}", value))?,
+            _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+        }
+    }
+    Ok((target, timeout))
+}
+
+pub async fn check(target: &str, timeout: Duration) -> Result<String, String> {
+    let base = target.trim_end_matches('/');
+    let url = if base.contains("://") { format!("{}/healthz", base) } else { format!("http://{}/healthz", base) };
+    let client = reqwest::Client::builder().timeout(timeout).build().map_err(|e| e.to_string())?;
+    let response = client.get(&url).send().await.map_err(|e| format!("{}: {}", url, e))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(format!("{}: {}", status, body))
+    }
+}
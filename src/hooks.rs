@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::log::Level;
+use crate::xlog;
+
+pub struct Connection {
+    pub id: Uuid,
+    pub peer: String,
+    pub session: Option<Uuid>,
+    pub actor: Option<Uuid>,
+}
+
+pub struct Message {
+    pub actor: Option<Uuid>,
+    pub tags: BTreeMap<String, String>,
+    pub load: Bytes,
+}
+
+#[async_trait]
+pub trait Hook: Send + Sync {
+    async fn on_connect(&self, _state: &State, _conn: &Connection) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn on_message(&self, _state: &State, _msg: &mut Message) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn on_disconnect(&self, _state: &State, _conn: &Connection) {}
+}
+
+#[derive(Default)]
+pub struct Hooks {
+    items: RwLock<Vec<Arc<dyn Hook>>>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, hook: impl Hook + 'static) {
+        self.items.write().unwrap().push(Arc::new(hook));
+    }
+
+    fn all(&self) -> Vec<Arc<dyn Hook>> {
+        self.items.read().unwrap().clone()
+    }
+
+    pub async fn connect(&self, state: &State, conn: &Connection) -> Result<(), String> {
+        for hook in self.all() {
+            hook.on_connect(state, conn).await
+                .inspect_err(|err| xlog!(Level::Debug, target: "hooks", "connection from {} refused: {}", conn.peer, err))?;
+        }
+        Ok(())
+    }
+
+    pub async fn message(&self, state: &State, msg: &mut Message) -> Result<(), String> {
+        if msg.tags.get("kind").map(String::as_str) != Some("msg") {
+            return Ok(());
+        }
+        for hook in self.all() {
+            hook.on_message(state, msg).await
+                .inspect_err(|err| xlog!(Level::Debug, target: "hooks", "message refused: {}", err))?;
+        }
+        Ok(())
+    }
+
+    pub async fn disconnect(&self, state: &State, conn: &Connection) {
+        for hook in self.all() {
+            hook.on_disconnect(state, conn).await;
+        }
+    }
+
+    pub async fn filter(
+        &self,
+        state: &State,
+        actor: Option<Uuid>,
+        tags: BTreeMap<String, String>,
+        load: Bytes,
+    ) -> actix_web::Result<(BTreeMap<String, String>, Bytes)> {
+        let mut msg = Message { actor, tags, load };
+        self.message(state, &mut msg).await.map_err(actix_web::error::ErrorForbidden)?;
+        Ok((msg.tags, msg.load))
+    }
+}
@@ -1,49 +1,142 @@
 use dashmap::DashMap;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use uuid::Uuid;
 
 use crate::entity::Entity;
+use crate::log::Level;
 use crate::predicate::Predicate;
+use crate::xlog;
+
+const ROOM_CAPACITY: usize = 256;
 
 struct Subscription {
     predicate: Predicate,
     sender: mpsc::UnboundedSender<Entity>,
 }
 
+struct Link {
+    id: Uuid,
+    sender: mpsc::UnboundedSender<Entity>,
+}
+
+struct Member {
+    room: String,
+    _closer: oneshot::Sender<()>,
+}
+
+pub enum Receiver {
+    Direct(mpsc::UnboundedReceiver<Entity>),
+    Room {
+        rx: broadcast::Receiver<Entity>,
+        predicate: Predicate,
+        closed: oneshot::Receiver<()>,
+    },
+}
+
+impl Receiver {
+    pub async fn recv(&mut self) -> Option<Entity> {
+        match self {
+            Receiver::Direct(rx) => rx.recv().await,
+            Receiver::Room { rx, predicate, closed } => loop {
+                tokio::select! {
+                    _ = &mut *closed => return None,
+                    result = rx.recv() => match result {
+                        Ok(entity) if matches_predicate(&entity.tags, predicate) => return Some(entity),
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            xlog!(Level::Warn, target: "hub", "receiver lagged, {} skipped", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    },
+                }
+            },
+        }
+    }
+}
+
 pub struct Hub {
     subs: DashMap<Uuid, Subscription>,
+    rooms: DashMap<String, broadcast::Sender<Entity>>,
+    members: DashMap<Uuid, Member>,
+    links: DashMap<Uuid, Vec<Link>>,
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Hub {
     pub fn new() -> Self {
         Self {
             subs: DashMap::new(),
+            rooms: DashMap::new(),
+            members: DashMap::new(),
+            links: DashMap::new(),
         }
     }
 
-    pub fn subscribe(&self, predicate: Predicate) -> (Uuid, mpsc::UnboundedReceiver<Entity>) {
-        let (tx, rx) = mpsc::unbounded_channel();
+    pub fn subscribe(&self, mut predicate: Predicate) -> (Uuid, Receiver) {
         let id = Uuid::new_v4();
+        if let Some(room) = predicate.remove("in") {
+            let rx = self.rooms.entry(room.clone())
+                .or_insert_with(|| broadcast::channel(ROOM_CAPACITY).0)
+                .subscribe();
+            let (closer, closed) = oneshot::channel();
+            self.members.insert(id, Member { room, _closer: closer });
+            return (id, Receiver::Room { rx, predicate, closed });
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
         self.subs.insert(id, Subscription {
             predicate,
             sender: tx,
         });
-        (id, rx)
+        (id, Receiver::Direct(rx))
     }
 
     pub fn unsubscribe(&self, id: Uuid) {
         self.subs.remove(&id);
+        if let Some((_, member)) = self.members.remove(&id) {
+            self.rooms.remove_if(&member.room, |_, tx| tx.receiver_count() <= 1);
+        }
+    }
+
+    pub fn attach(&self, user: Uuid) -> (Uuid, mpsc::UnboundedReceiver<Entity>) {
+        let id = Uuid::new_v4();
+        let (sender, rx) = mpsc::unbounded_channel();
+        self.links.entry(user).or_default().push(Link { id, sender });
+        (id, rx)
+    }
+
+    pub fn detach(&self, user: Uuid, id: Uuid) {
+        if let Some(mut links) = self.links.get_mut(&user) {
+            links.retain(|link| link.id != id);
+        }
+        self.links.remove_if(&user, |_, links| links.is_empty());
+    }
+
+    pub fn deliver_to(&self, user: Uuid, entity: Entity) -> bool {
+        let Some(mut links) = self.links.get_mut(&user) else {
+            return false;
+        };
+        links.retain(|link| !link.sender.is_closed());
+        links.last().is_some_and(|link| link.sender.send(entity).is_ok())
     }
 
     pub fn publish(&self, entity: &Entity) {
+        if let Some(room) = entity.tags.get("in") {
+            let orphaned = self.rooms.get(room).is_some_and(|tx| tx.send(entity.clone()).is_err());
+            if orphaned {
+                self.rooms.remove_if(room, |_, tx| tx.receiver_count() == 0);
+            }
+        }
         let dead: Vec<Uuid> = self.subs
             .iter()
             .filter_map(|entry| {
                 let sub = entry.value();
-                if matches_predicate(&entity.tags, &sub.predicate) {
-                    if sub.sender.send(entity.clone()).is_err() {
-                        return Some(*entry.key());
-                    }
+                if matches_predicate(&entity.tags, &sub.predicate) && sub.sender.send(entity.clone()).is_err() {
+                    return Some(*entry.key());
                 }
                 None
             })
@@ -62,4 +155,4 @@ fn matches_predicate(tags: &std::collections::BTreeMap<String, String>, predicat
         }
     }
     true
-}
\ No newline at end of file
+}
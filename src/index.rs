@@ -10,6 +10,12 @@ pub struct Index {
     keys: DashMap<String, DashMap<String, Arc<DashSet<Uuid>>>>,
 }
 
+impl Default for Index {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Index {
     pub fn new() -> Self {
         Self {
@@ -19,7 +25,7 @@ impl Index {
 
     pub fn insert(&self, id: Uuid, tags: &BTreeMap<String, String>) {
         for (key, val) in tags {
-            let column = self.keys.entry(key.clone()).or_insert_with(DashMap::new);
+            let column = self.keys.entry(key.clone()).or_default();
             let set = column.entry(val.clone()).or_insert_with(|| Arc::new(DashSet::new()));
             set.insert(id);
         }
@@ -0,0 +1,101 @@
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::client::Theme;
+use crate::health;
+use crate::paths::Dirs;
+use crate::profile;
+
+pub const USAGE: &str = "usage: morce init";
+
+fn ask(question: &str, default: &str) -> Result<String, String> {
+    print!("{} [{}]: ", question, default);
+    std::io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    if std::io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+        println!();
+        return Err("setup cancelled, nothing written".into());
+    }
+    let answer = line.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+fn reachable(target: &str) -> Result<(), String> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().map_err(|e| e.to_string())?;
+    runtime.block_on(health::check(target, Duration::from_secs(5))).map(|_| ())
+}
+
+fn expand(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+pub fn run<I: IntoIterator<Item = String>>(args: I) -> Result<String, String> {
+    if let Some(arg) = args.into_iter().next() {
+        return Err(format!("unexpected argument {}\n{}", arg, USAGE));
+    }
+    let mut config = profile::defaults()?;
+    println!("Setting up the morce client. Press Enter to keep the value in brackets.");
+
+    loop {
+        let target = ask("Server address", config.get("target").unwrap_or("127.0.0.1:8080"))?;
+        print!("Checking {}... ", target);
+        std::io::stdout().flush().map_err(|e| e.to_string())?;
+        match reachable(&target) {
+            Ok(()) => println!("ok"),
+            Err(err) => {
+                println!("failed\n  {}", err);
+                if !ask("Use it anyway? (y/n)", "n")?.eq_ignore_ascii_case("y") {
+                    continue;
+                }
+            }
+        }
+        match config.set("--target", target) {
+            Ok(()) => break,
+            Err(err) => println!("  {}", err),
+        }
+    }
+
+    let user = std::env::var("USER").unwrap_or_else(|_| "guest".into());
+    loop {
+        let name = ask("Username", config.get("name").unwrap_or(&user))?;
+        if name.contains(char::is_whitespace) {
+            println!("  a username cannot contain spaces");
+            continue;
+        }
+        match config.set("--name", name) {
+            Ok(()) => break,
+            Err(err) => println!("  {}", err),
+        }
+    }
+
+    loop {
+        let theme = ask("Theme: green, amber, mono, or light", config.get("theme").unwrap_or("green"))?;
+        if Theme::parse(&theme).is_none() {
+            println!("  unknown theme {}", theme);
+            continue;
+        }
+        config.set("--theme", theme)?;
+        break;
+    }
+
+    let downloads = Dirs::resolve().downloads();
+    loop {
+        let default = config.get("downloads").map(PathBuf::from).unwrap_or_else(|| downloads.clone());
+        let dir = expand(&ask("Download directory", &default.to_string_lossy())?);
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            println!("  cannot create {}: {}", dir.display(), err);
+            continue;
+        }
+        match config.set("--downloads", dir.to_string_lossy().into_owned()) {
+            Ok(()) => break,
+            Err(err) => println!("  {}", err),
+        }
+    }
+
+    let file = profile::save_defaults(&config)?;
+    Ok(format!("Wrote {}. Run `morce client` to connect.", file.display()))
+}
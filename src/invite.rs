@@ -0,0 +1,218 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::ban;
+use crate::guard;
+use crate::log::Level;
+use crate::qr;
+use crate::room;
+use crate::store::Store;
+use crate::xlog;
+
+pub const USAGE: &str = "usage: morce invite [--target ADDR] --session ID --room ROOM [--uses N] [--expires AGE] [--host HOST:PORT] [--qr]";
+
+const SCHEME: &str = "morce://";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub target: String,
+    pub room: String,
+    pub token: String,
+}
+
+impl Link {
+    pub fn parse(raw: &str) -> Option<Link> {
+        let rest = raw.strip_prefix(SCHEME)?;
+        let (target, rest) = rest.split_once('/')?;
+        let (room, query) = rest.split_once('?')?;
+        let token = query.split('&').find_map(|pair| pair.strip_prefix("token="))?;
+        let link = Link { target: target.to_string(), room: unescape(room.trim_end_matches('/'))?, token: unescape(token)? };
+        (!link.target.is_empty() && room::valid_name(&link.room) && !link.token.is_empty()).then_some(link)
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}/{}?token={}", SCHEME, self.target, escape(&self.room), escape(&self.token))
+    }
+}
+
+fn escape(raw: &str) -> String {
+    raw.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn unescape(raw: &str) -> Option<String> {
+    let mut out = Vec::new();
+    let mut bytes = raw.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b => out.push(b),
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+pub async fn create(state: web::Data<State>, req: HttpRequest, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+    let store = &*state.store;
+    let actor = guard::check(store, auth_header, "invite", None).await?
+        .ok_or(guard::Denied::NoSession)?;
+    let payload: Value = serde_json::from_slice(&body)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid json"))?;
+    let room = payload.get("room").and_then(Value::as_str)
+        .ok_or(actix_web::error::ErrorBadRequest("expected room"))?;
+    let channel = room::find(store, room).await
+        .ok_or(actix_web::error::ErrorNotFound("no such room"))?;
+    if !guard::is_moderator(store, actor, channel.id).await {
+        return Err(actix_web::error::ErrorForbidden("only moderators of the room can invite"));
+    }
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "invite".into());
+    tags.insert("room".into(), channel.id.to_string());
+    tags.insert("by".into(), actor.to_string());
+    if let Some(uses) = payload.get("uses") {
+        let uses = uses.as_u64().filter(|&n| n > 0)
+            .ok_or(actix_web::error::ErrorBadRequest("uses must be a positive number"))?;
+        tags.insert("uses".into(), uses.to_string());
+    }
+    if let Some(expires) = payload.get("expires").and_then(Value::as_str) {
+        let age = ban::parse_duration(expires)
+            .ok_or(actix_web::error::ErrorBadRequest("expires takes an age like 30m, 12h, or 7d"))?;
+        tags.insert("until".into(), (state.clock.millis() + age.as_millis() as u64).to_string());
+    }
+    let invite = store.create(bytes::Bytes::new(), tags).await?;
+    xlog!(Level::Info, target: "invite", "{} invited to {}", actor, room);
+    Ok(HttpResponse::Created().body(invite.id.to_string()))
+}
+
+pub async fn redeem(state: web::Data<State>, req: HttpRequest, path: web::Path<Uuid>) -> actix_web::Result<HttpResponse> {
+    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+    let store = &*state.store;
+    let actor = guard::check(store, auth_header, "join", None).await?
+        .ok_or(guard::Denied::NoSession)?;
+    let invite = store.read(*path).await?
+        .filter(|e| e.tags.get("kind").map(String::as_str) == Some("invite"))
+        .ok_or(actix_web::error::ErrorNotFound("no such invite"))?;
+    let expired = invite.tags.get("until").and_then(|v| v.parse::<u64>().ok()).is_some_and(|until| until < state.clock.millis());
+    let channel = match invite.tags.get("room") {
+        Some(room) if !expired => room::find(store, room).await,
+        _ => None,
+    };
+    let Some(channel) = channel else {
+        store.delete(invite.id).await?;
+        return Err(actix_web::error::ErrorGone("invite expired"));
+    };
+    let joined = room::let_in(&state, &channel, &actor.to_string()).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if joined {
+        match invite.tags.get("uses").and_then(|v| v.parse::<u64>().ok()) {
+            Some(1) => store.delete(invite.id).await?,
+            Some(n) => {
+                let mut tags = invite.tags.clone();
+                tags.insert("uses".into(), (n - 1).to_string());
+                store.update(invite.id, invite.load.clone(), tags).await?;
+            }
+            None => {}
+        }
+    }
+    Ok(HttpResponse::Ok().body(channel.tags.get("name").cloned().unwrap_or_else(|| channel.id.to_string())))
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub target: String,
+    pub session: String,
+    pub room: String,
+    pub uses: Option<u64>,
+    pub expires: Option<String>,
+    pub host: Option<String>,
+    pub qr: bool,
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut target = "127.0.0.1:8080".to_string();
+        let (mut session, mut room, mut uses, mut expires, mut host) = (None, None, None, None, None);
+        let mut qr = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--qr" {
+                qr = true;
+                continue;
+            }
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--target" => target = value,
+                "--session" => session = Some(value),
+                "--room" => room = Some(value),
+                "--uses" => uses = Some(value.parse::<u64>().ok().filter(|&n| n > 0)
+                    .ok_or(format!("--uses expects a positive number, got {}", value))?),
+                "--expires" => expires = Some(value),
+                "--host" => host = Some(value),
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        Ok(Options {
+            target,
+            session: session.ok_or(format!("--session is required\n{}", USAGE))?,
+            room: room.ok_or(format!("--room is required\n{}", USAGE))?,
+            uses,
+            expires,
+            host,
+            qr,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        let base = self.target.trim_end_matches('/');
+        if base.contains("://") {
+            format!("{}{}", base, path)
+        } else {
+            format!("http://{}{}", base, path)
+        }
+    }
+}
+
+pub async fn run(options: Options) -> Result<String, String> {
+    let mut body = json!({ "room": options.room });
+    if let Some(uses) = options.uses {
+        body["uses"] = json!(uses);
+    }
+    if let Some(expires) = &options.expires {
+        body["expires"] = json!(expires);
+    }
+    let response = reqwest::Client::new()
+        .post(options.url("/invites"))
+        .bearer_auth(&options.session)
+        .body(body.to_string())
+        .send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, text));
+    }
+    let target = match &options.host {
+        Some(host) => host.clone(),
+        None => options.target.split("://").last().unwrap_or_default().trim_end_matches('/').to_string(),
+    };
+    let link = Link { target, room: options.room.clone(), token: text.trim().to_string() }.to_string();
+    match options.qr {
+        true => Ok(format!("{}\n{}", qr::render(&link).ok_or("link is too long for a QR code")?, link)),
+        false => Ok(link),
+    }
+}
@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::predicate::Predicate;
+use crate::qr;
+use crate::store::Store;
+
+pub fn fingerprint(raw: &[u8]) -> String {
+    let hex: String = Sha256::digest(raw)[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    hex.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub struct Fingerprint;
+
+#[async_trait]
+impl Command for Fingerprint {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::Anyone, usage: "<user> [qr]", about: "help.fingerprint" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let (args, code) = match args.rsplit_once(char::is_whitespace) {
+            Some((user, "qr")) => (user.trim(), true),
+            _ => (args, false),
+        };
+        if args.is_empty() {
+            return Err(tr("fingerprint.usage").into());
+        }
+        let store = &*ctx.state.store;
+        let user = match args.parse::<uuid::Uuid>() {
+            Ok(id) => id.to_string(),
+            Err(_) => {
+                let mut pred = Predicate::new();
+                pred.insert("kind".into(), "user".into());
+                pred.insert("name".into(), args.to_string());
+                let found = store.query(&pred).await.map_err(|e| e.to_string())?;
+                found.first().ok_or_else(|| trf("fingerprint.nouser", &[args]))?.id.to_string()
+            }
+        };
+
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "key".into());
+        pred.insert("user".into(), user);
+        let mut keys = store.query(&pred).await.map_err(|e| e.to_string())?;
+        if keys.is_empty() {
+            return Err(trf("fingerprint.none", &[args]));
+        }
+        keys.sort_by_key(|k| k.at);
+        let rows: Vec<Vec<String>> = keys.iter()
+            .map(|key| {
+                let print = URL_SAFE_NO_PAD.decode(key.load.as_ref())
+                    .map(|raw| fingerprint(&raw))
+                    .unwrap_or_else(|_| "-".into());
+                vec![key.id.to_string()[..8].to_string(), print]
+            })
+            .collect();
+        let table = layout::table(&[tr("fingerprint.key"), tr("fingerprint.print")], &rows);
+        match rows.last().filter(|_| code) {
+            Some(row) => Ok(format!("{}\n{}", table, qr::render(&row[1]).unwrap_or_default())),
+            None => Ok(table),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+pub fn truncate(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let w = ch.width().unwrap_or(0);
+        if used + w + 1 > max {
+            break;
+        }
+        out.push(ch);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+pub fn wrap(text: &str, max: usize) -> Vec<String> {
+    let max = max.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut used = 0;
+        for word in paragraph.split(' ') {
+            let w = width(word);
+            if used > 0 && used + 1 + w > max {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+            }
+            if used > 0 {
+                line.push(' ');
+                used += 1;
+            }
+            for ch in word.chars() {
+                let cw = ch.width().unwrap_or(0);
+                if used + cw > max && used > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    used = 0;
+                }
+                line.push(ch);
+                used += cw;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| width(h)).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            if i < widths.len() {
+                widths[i] = widths[i].max(width(cell));
+            }
+        }
+    }
+
+    let line = |cells: Vec<&str>| -> String {
+        let mut out = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if i > 0 {
+                out.push_str("  ");
+            }
+            out.push_str(cell);
+            if i + 1 < cells.len() {
+                out.push_str(&" ".repeat(widths[i] - width(cell)));
+            }
+        }
+        out.trim_end().to_string()
+    };
+
+    let mut out = vec![line(headers.to_vec())];
+    let rule: Vec<String> = widths.iter().map(|w| "─".repeat(*w)).collect();
+    out.push(line(rule.iter().map(String::as_str).collect()));
+    for row in rows {
+        out.push(line(row.iter().take(widths.len()).map(String::as_str).collect()));
+    }
+    out.join("\n")
+}
+
+pub fn panel(title: &str, body: &str) -> String {
+    let lines = wrap(body, 72);
+    let inner = lines.iter().map(|l| width(l)).chain(std::iter::once(width(title))).max().unwrap_or(0);
+    let mut out = format!("┌ {} {}┐\n", title, "─".repeat(inner - width(title)));
+    for line in &lines {
+        out.push_str(&format!("│ {}{} │\n", line, " ".repeat(inner - width(line))));
+    }
+    out.push_str(&format!("└{}┘", "─".repeat(inner + 2)));
+    out
+}
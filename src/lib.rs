@@ -0,0 +1,68 @@
+pub mod entity;
+pub mod predicate;
+pub mod store;
+pub mod index;
+pub mod memory;
+pub mod hub;
+pub mod hooks;
+pub mod api;
+pub mod ws;
+pub mod echo;
+pub mod record;
+pub mod transport;
+pub mod console;
+pub mod parse;
+pub mod format;
+pub mod guard;
+pub mod ban;
+pub mod quota;
+pub mod retention;
+pub mod room;
+pub mod welcome;
+pub mod account;
+pub mod names;
+pub mod backup;
+pub mod oidc;
+pub mod keys;
+pub mod federation;
+pub mod mqtt;
+pub mod mail;
+pub mod push;
+pub mod feed;
+pub mod translate;
+pub mod unfurl;
+pub mod invite;
+pub mod marker;
+pub mod notify;
+pub mod qr;
+pub mod handover;
+pub mod bot;
+pub mod webhook;
+pub mod forge;
+pub mod command;
+pub mod poll;
+pub mod presence;
+pub mod log;
+pub mod layout;
+pub mod locale;
+pub mod time;
+pub mod task;
+pub mod window;
+pub mod dedup;
+pub mod replay;
+pub mod pow;
+pub mod stats;
+pub mod health;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod cli;
+pub mod server;
+pub mod daemon;
+pub mod stress;
+pub mod tor;
+pub mod p2p;
+pub mod client;
+pub mod profile;
+pub mod paths;
+pub mod init;
+pub mod doctor;
@@ -0,0 +1,482 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Fa,
+}
+
+impl Lang {
+    pub fn parse(raw: &str) -> Option<Lang> {
+        let raw = raw.trim().to_ascii_lowercase();
+        if raw.starts_with("fa") {
+            Some(Lang::Fa)
+        } else if raw.starts_with("en") || raw == "c" || raw.starts_with("c.") || raw == "posix" {
+            Some(Lang::En)
+        } else {
+            None
+        }
+    }
+}
+
+static LANG: AtomicU8 = AtomicU8::new(Lang::En as u8);
+
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::Fa,
+        _ => Lang::En,
+    }
+}
+
+pub fn init_from_env() {
+    let detected = ["MORCE_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find_map(|val| Lang::parse(&val));
+    if let Some(lang) = detected {
+        set_lang(lang);
+    }
+}
+
+const EN: &[(&str, &str)] = &[
+    ("command.unknown", "unknown command /{}"),
+    ("command.failed", "/{}: {}"),
+    ("help.chat", "chat"),
+    ("help.rooms", "rooms"),
+    ("help.account", "account"),
+    ("help.server", "server"),
+    ("help.admin", "admin"),
+    ("help.anyone", "anyone"),
+    ("help.user", "signed in"),
+    ("help.admins", "admins"),
+    ("help.one", "{}\n  {}\n  category: {}, who: {}"),
+    ("help.more", "/help <command> shows one command"),
+    ("help.help", "list commands, or describe one"),
+    ("help.roll", "roll dice, 1d6 by default"),
+    ("help.date", "today's date"),
+    ("help.stats", "server traffic counters"),
+    ("help.poll", "open a poll in this room"),
+    ("help.vote", "vote in the room's open poll"),
+    ("help.status", "set or clear your status"),
+    ("help.who", "who is online"),
+    ("help.away", "mark yourself away with an auto-reply for direct messages, or come back"),
+    ("help.ban", "ban a user, address, or network"),
+    ("help.unban", "lift a ban"),
+    ("help.bans", "list active bans"),
+    ("help.retention", "show or set how long rooms keep history"),
+    ("help.register", "turn your guest user into an account"),
+    ("help.token", "issue a login token"),
+    ("help.email", "get an email digest of missed direct messages and mentions"),
+    ("help.push", "send direct messages and mentions to ntfy or Gotify while offline"),
+    ("help.notify", "choose which messages in a room notify you: all, mentions, or mute"),
+    ("help.feed", "post new RSS or Atom entries to a room"),
+    ("help.translate", "translate a message or some text, only you see the result"),
+    ("help.unfurl", "show or toggle link previews in a room"),
+    ("help.sessions", "list or revoke your sessions"),
+    ("help.fingerprint", "a user's signing key fingerprints"),
+    ("help.quota", "your usage today against the quotas"),
+    ("help.join", "join a room (the default room if none is given), or wait in line when it is full"),
+    ("help.leave", "leave a room or its waiting line"),
+    ("help.roomlist", "list rooms with members, your unread messages, and last activity"),
+    ("help.agree", "accept the server rules"),
+    ("roll.usage", "usage: /roll NdM"),
+    ("roll.count", "bad dice count"),
+    ("roll.sides", "bad side count"),
+    ("roll.range", "dice out of range"),
+    ("poll.usage", "usage: /poll question | option | option ..."),
+    ("poll.options", "a poll needs at least two options"),
+    ("poll.many", "a poll can have at most 10 options"),
+    ("poll.opened", "poll {} opened, answer with /vote <n>"),
+    ("vote.signin", "sign in to vote"),
+    ("vote.usage", "usage: /vote <n>"),
+    ("vote.nopoll", "no open poll here"),
+    ("vote.option", "no such option"),
+    ("vote.recorded", "vote recorded for option {}"),
+    ("status.signin", "sign in to set a status"),
+    ("status.nouser", "no user entity for this actor"),
+    ("status.nosession", "this session no longer exists"),
+    ("status.cleared", "status cleared"),
+    ("status.set", "status set: {}"),
+    ("away.set", "you are away: {}"),
+    ("away.back", "you are back"),
+    ("away.reply", "{} is away: {}"),
+    ("who.empty", "nobody is online"),
+    ("who.name", "NAME"),
+    ("who.status", "STATUS"),
+    ("stats.metric", "METRIC"),
+    ("stats.value", "VALUE"),
+    ("ban.admin", "only admins can manage bans"),
+    ("ban.usage", "usage: /ban <user|ip|cidr> [duration]"),
+    ("ban.duration", "bad duration, use e.g. 30m, 12h, 7d"),
+    ("ban.nouser", "no such user {}"),
+    ("ban.added", "banned {}"),
+    ("ban.until", "banned {} until {}"),
+    ("unban.usage", "usage: /unban <user|ip|cidr>"),
+    ("unban.none", "no active ban for {}"),
+    ("unban.removed", "unbanned {}"),
+    ("bans.empty", "no active bans"),
+    ("bans.target", "TARGET"),
+    ("bans.until", "UNTIL"),
+    ("bans.forever", "forever"),
+    ("quota.warn", "you have used {} of your {} daily quota"),
+    ("quota.signin", "sign in to see your quota"),
+    ("quota.off", "no quotas on this server"),
+    ("quota.unlimited", "unlimited"),
+    ("quota.usage", "today: {} of {} sent, {} of {} in files"),
+    ("retention.admin", "only admins can manage retention"),
+    ("retention.usage", "usage: /retention [<room> [<days like 7d> | <count> | forever | none]]"),
+    ("retention.noroom", "no such room {}"),
+    ("retention.empty", "every room keeps its history forever"),
+    ("retention.current", "{} keeps {}"),
+    ("retention.set", "{} now keeps {}, pruned {} messages"),
+    ("retention.room", "ROOM"),
+    ("retention.policy", "KEEP"),
+    ("retention.messages", "MESSAGES"),
+    ("join.signin", "sign in to join rooms"),
+    ("join.usage", "usage: /join <room> (this server has no default room)"),
+    ("join.noroom", "no such room {}"),
+    ("join.already", "you are already in {}"),
+    ("join.joined", "joined {}"),
+    ("join.created", "created and joined {}"),
+    ("join.full", "{} is full ({} members)"),
+    ("join.queued", "{} is full, you are number {} in line"),
+    ("join.waiting", "you are still waiting for {}, number {} in line"),
+    ("join.admitted", "a place opened up, you are now in {}"),
+    ("leave.usage", "usage: /leave <room>"),
+    ("leave.notin", "you are not in {}"),
+    ("leave.left", "left {}"),
+    ("rooms.empty", "there are no rooms yet"),
+    ("rooms.room", "ROOM"),
+    ("rooms.members", "MEMBERS"),
+    ("rooms.unread", "UNREAD"),
+    ("rooms.active", "LAST ACTIVE"),
+    ("announce.readonly", "{} is announcement-only, only moderators can post there"),
+    ("welcome.rules", "be kind, stay on topic, and do not spam"),
+    ("welcome.greeting", "welcome, {}! please read the rules:\n{}\nsend /agree to accept them and start posting. /help lists the commands."),
+    ("welcome.pending", "you have not accepted the rules yet. send /agree first, or /help for the commands."),
+    ("welcome.signin", "sign in to accept the rules"),
+    ("welcome.nothing", "there is nothing to agree to"),
+    ("welcome.agreed", "thanks, you can post now"),
+    ("register.signin", "join as a guest first, then /register"),
+    ("register.usage", "usage: /register <password>"),
+    ("register.short", "password must be at least 8 characters"),
+    ("register.taken", "this name is already registered"),
+    ("register.done", "registered {}, sign in later with POST /login"),
+    ("token.signin", "only registered users can issue tokens"),
+    ("token.issued", "login token: {}"),
+    ("email.signin", "sign in to set up email digests"),
+    ("email.disabled", "this server does not send email"),
+    ("email.register", "only registered users can add an email address, see /register"),
+    ("email.invalid", "{} is not an email address"),
+    ("email.none", "no email address set, add one with /email <address>"),
+    ("email.show", "digests go to {} after {} minutes offline, /email off to pause"),
+    ("email.paused", "digests to {} are paused, /email on to resume"),
+    ("email.set", "digests of missed messages will go to {} after {} minutes offline"),
+    ("email.resumed", "email digests resumed"),
+    ("email.stopped", "email digests paused"),
+    ("email.removed", "email address removed"),
+    ("email.subject", "{} new message(s) while you were away"),
+    ("email.greeting", "hi {}, you missed these while you were away:"),
+    ("email.footer", "reply in the chat; send /email off there to stop these emails."),
+    ("push.signin", "sign in to set up push notifications"),
+    ("push.disabled", "this server does not relay push notifications"),
+    ("push.register", "only registered users can set up push notifications, see /register"),
+    ("push.usage", "usage: /push [ntfy <url> [token]|gotify <url> <token>|test|off]"),
+    ("push.token", "gotify needs an application token"),
+    ("push.badurl", "{} is not an http or https URL"),
+    ("push.host", "{} is not allowed here, use one of: {}"),
+    ("push.none", "no push target set, add one with /push ntfy <url> or /push gotify <url> <token>"),
+    ("push.show", "pushing to {} at {} while you are offline"),
+    ("push.set", "direct messages and mentions will be pushed to {} at {} while you are offline"),
+    ("push.removed", "push notifications turned off"),
+    ("push.title", "morce"),
+    ("push.test", "push notifications are working"),
+    ("push.sent", "test notification sent to {}"),
+    ("push.failed", "push failed: {}"),
+    ("push.direct", "{} sent you a message"),
+    ("push.mention", "{} mentioned you in #{}"),
+    ("push.room", "{} in #{}"),
+    ("notify.signin", "sign in to choose notification levels"),
+    ("notify.usage", "usage: /notify [<room> [all|mentions|mute]]"),
+    ("notify.none", "every room notifies you of mentions only, change one with /notify <room> all or mute"),
+    ("notify.room", "ROOM"),
+    ("notify.level", "LEVEL"),
+    ("notify.all", "#{} notifies you of every message"),
+    ("notify.mentions", "#{} notifies you of mentions only"),
+    ("notify.mute", "#{} is muted"),
+    ("feed.usage", "usage: /feed <room> [add <url>|remove <n>]"),
+    ("feed.moderator", "only moderators of the room can manage its feeds"),
+    ("feed.empty", "{} follows no feeds"),
+    ("feed.title", "TITLE"),
+    ("feed.url", "URL"),
+    ("feed.badurl", "{} is not an http or https URL"),
+    ("feed.exists", "{} already follows this feed"),
+    ("feed.failed", "could not read the feed"),
+    ("feed.added", "{} now posts to {}, checked every {} minutes"),
+    ("feed.nofeed", "no feed number {}, see /feed <room>"),
+    ("feed.removed", "{} no longer posts to {}"),
+    ("feed.untitled", "feed"),
+    ("translate.disabled", "this server has no translation service"),
+    ("translate.usage", "usage: /translate <lang> <message_id|text>"),
+    ("translate.missing", "no message {}"),
+    ("translate.empty", "nothing to translate"),
+    ("translate.long", "text is too long to translate, the limit is {} bytes"),
+    ("translate.failed", "translation failed: {}"),
+    ("translate.from", "[{} → {}] {}"),
+    ("translate.into", "[{}] {}"),
+    ("unfurl.disabled", "this server does not preview links"),
+    ("unfurl.usage", "usage: /unfurl <room> [on|off]"),
+    ("unfurl.moderator", "only moderators of the room can toggle link previews"),
+    ("unfurl.on", "links posted in {} get previews"),
+    ("unfurl.off", "links posted in {} are not previewed"),
+    ("who.guest", " (guest)"),
+    ("who.away", " (away)"),
+    ("sessions.signin", "sign in to manage sessions"),
+    ("sessions.usage", "usage: /sessions [revoke <id>|revoke all]"),
+    ("sessions.none", "no session matches {}"),
+    ("sessions.revoked", "revoked {} session(s)"),
+    ("sessions.id", "ID"),
+    ("sessions.via", "VIA"),
+    ("sessions.device", "DEVICE"),
+    ("sessions.since", "SINCE"),
+    ("fingerprint.usage", "usage: /fingerprint <user> [qr]"),
+    ("fingerprint.nouser", "no such user {}"),
+    ("fingerprint.none", "{} has no published keys"),
+    ("fingerprint.key", "KEY"),
+    ("fingerprint.print", "FINGERPRINT"),
+];
+
+const FA: &[(&str, &str)] = &[
+    ("command.unknown", "دستور ناشناخته /{}"),
+    ("help.chat", "گفتگو"),
+    ("help.rooms", "اتاق‌ها"),
+    ("help.account", "حساب"),
+    ("help.server", "سرور"),
+    ("help.admin", "مدیریت"),
+    ("help.anyone", "همه"),
+    ("help.user", "کاربران واردشده"),
+    ("help.admins", "مدیران"),
+    ("help.one", "{}\n  {}\n  دسته: {}، برای: {}"),
+    ("help.more", "/help <دستور> یک دستور را توضیح می‌دهد"),
+    ("help.help", "فهرست دستورها، یا توضیح یکی از آن‌ها"),
+    ("help.roll", "ریختن تاس، به‌طور پیش‌فرض 1d6"),
+    ("help.date", "تاریخ امروز"),
+    ("help.stats", "شمارنده‌های ترافیک سرور"),
+    ("help.poll", "ایجاد نظرسنجی در این اتاق"),
+    ("help.vote", "رأی دادن در نظرسنجی باز اتاق"),
+    ("help.status", "تنظیم یا پاک کردن وضعیت"),
+    ("help.who", "چه کسانی آنلاین هستند"),
+    ("help.away", "اعلام غیبت با پاسخ خودکار به پیام‌های خصوصی، یا بازگشت"),
+    ("help.ban", "مسدود کردن کاربر، نشانی یا شبکه"),
+    ("help.unban", "برداشتن مسدودیت"),
+    ("help.bans", "فهرست مسدودیت‌های فعال"),
+    ("help.retention", "نمایش یا تنظیم مدت نگهداری تاریخچه اتاق‌ها"),
+    ("help.register", "تبدیل کاربر مهمان به حساب"),
+    ("help.token", "صدور توکن ورود"),
+    ("help.email", "دریافت خلاصهٔ ایمیلی پیام‌های خصوصی و اشاره‌های ازدست‌رفته"),
+    ("help.push", "ارسال پیام‌های خصوصی و اشاره‌ها به ntfy یا Gotify هنگام آفلاین بودن"),
+    ("help.notify", "انتخاب پیام‌هایی از یک اتاق که اعلان می‌فرستند: همه، اشاره‌ها، یا بی‌صدا"),
+    ("help.feed", "انتشار مطالب تازهٔ RSS یا Atom در یک اتاق"),
+    ("help.translate", "ترجمهٔ یک پیام یا متن، نتیجه فقط برای شما نمایش داده می‌شود"),
+    ("help.unfurl", "نمایش یا تغییر پیش‌نمایش پیوندها در یک اتاق"),
+    ("help.sessions", "فهرست یا لغو نشست‌ها"),
+    ("help.fingerprint", "اثر انگشت کلیدهای امضای یک کاربر"),
+    ("help.quota", "مصرف امروز شما نسبت به سهمیه‌ها"),
+    ("help.join", "پیوستن به اتاق (اتاق پیش‌فرض اگر نامی داده نشود)، یا انتظار در صف وقتی پر است"),
+    ("help.leave", "ترک اتاق یا صف انتظار آن"),
+    ("help.roomlist", "فهرست اتاق‌ها با اعضا، پیام‌های خوانده‌نشده شما و آخرین فعالیت"),
+    ("help.agree", "پذیرفتن قوانین سرور"),
+    ("roll.usage", "استفاده: /roll NdM"),
+    ("roll.count", "تعداد تاس نامعتبر است"),
+    ("roll.sides", "تعداد وجه‌ها نامعتبر است"),
+    ("roll.range", "تاس‌ها خارج از محدوده‌اند"),
+    ("poll.usage", "استفاده: /poll پرسش | گزینه | گزینه ..."),
+    ("poll.options", "نظرسنجی دست‌کم به دو گزینه نیاز دارد"),
+    ("poll.many", "نظرسنجی حداکثر ۱۰ گزینه می‌تواند داشته باشد"),
+    ("poll.opened", "نظرسنجی {} باز شد، با /vote <n> پاسخ دهید"),
+    ("vote.signin", "برای رأی دادن وارد شوید"),
+    ("vote.usage", "استفاده: /vote <n>"),
+    ("vote.nopoll", "اینجا نظرسنجی بازی نیست"),
+    ("vote.option", "چنین گزینه‌ای وجود ندارد"),
+    ("vote.recorded", "رأی شما برای گزینه {} ثبت شد"),
+    ("status.signin", "برای تنظیم وضعیت وارد شوید"),
+    ("status.nouser", "کاربری برای این هویت پیدا نشد"),
+    ("status.nosession", "این نشست دیگر وجود ندارد"),
+    ("status.cleared", "وضعیت پاک شد"),
+    ("status.set", "وضعیت تنظیم شد: {}"),
+    ("away.set", "شما غایب هستید: {}"),
+    ("away.back", "شما برگشتید"),
+    ("away.reply", "{} غایب است: {}"),
+    ("who.empty", "کسی آنلاین نیست"),
+    ("who.name", "نام"),
+    ("who.status", "وضعیت"),
+    ("stats.metric", "شاخص"),
+    ("stats.value", "مقدار"),
+    ("ban.admin", "فقط مدیران می‌توانند محرومیت‌ها را مدیریت کنند"),
+    ("ban.usage", "استفاده: /ban <کاربر|ip|cidr> [مدت]"),
+    ("ban.duration", "مدت نامعتبر است، مثلاً 30m، 12h، 7d"),
+    ("ban.nouser", "کاربر {} پیدا نشد"),
+    ("ban.added", "{} محروم شد"),
+    ("ban.until", "{} تا {} محروم شد"),
+    ("unban.usage", "استفاده: /unban <کاربر|ip|cidr>"),
+    ("unban.none", "محرومیت فعالی برای {} نیست"),
+    ("unban.removed", "محرومیت {} برداشته شد"),
+    ("bans.empty", "محرومیت فعالی وجود ندارد"),
+    ("bans.target", "هدف"),
+    ("bans.until", "تا"),
+    ("bans.forever", "همیشه"),
+    ("quota.warn", "{} از سهمیه روزانه {} شما مصرف شده است"),
+    ("quota.signin", "برای دیدن سهمیه وارد شوید"),
+    ("quota.off", "این سرور سهمیه ندارد"),
+    ("quota.unlimited", "نامحدود"),
+    ("quota.usage", "امروز: {} از {} ارسال، {} از {} در فایل‌ها"),
+    ("retention.admin", "فقط مدیران می‌توانند نگهداری پیام‌ها را مدیریت کنند"),
+    ("retention.usage", "استفاده: /retention [<اتاق> [<مدت مثل 7d> | <تعداد> | forever | none]]"),
+    ("retention.noroom", "اتاق {} پیدا نشد"),
+    ("retention.empty", "همه اتاق‌ها تاریخچه را برای همیشه نگه می‌دارند"),
+    ("retention.current", "{} این را نگه می‌دارد: {}"),
+    ("retention.set", "{} اکنون این را نگه می‌دارد: {}، {} پیام حذف شد"),
+    ("retention.room", "اتاق"),
+    ("retention.policy", "نگهداری"),
+    ("retention.messages", "پیام‌ها"),
+    ("join.signin", "برای پیوستن به اتاق‌ها وارد شوید"),
+    ("join.usage", "استفاده: /join <اتاق> (این سرور اتاق پیش‌فرض ندارد)"),
+    ("join.noroom", "اتاق {} پیدا نشد"),
+    ("join.already", "شما از قبل در {} هستید"),
+    ("join.joined", "به {} پیوستید"),
+    ("join.created", "{} ساخته شد و به آن پیوستید"),
+    ("join.full", "{} پر است ({} عضو)"),
+    ("join.queued", "{} پر است، شما نفر {} در صف هستید"),
+    ("join.waiting", "هنوز در صف {} هستید، نفر {}"),
+    ("join.admitted", "جایی باز شد، اکنون در {} هستید"),
+    ("leave.usage", "استفاده: /leave <اتاق>"),
+    ("leave.notin", "شما در {} نیستید"),
+    ("leave.left", "{} را ترک کردید"),
+    ("rooms.empty", "هنوز اتاقی وجود ندارد"),
+    ("rooms.room", "اتاق"),
+    ("rooms.members", "اعضا"),
+    ("rooms.unread", "خوانده‌نشده"),
+    ("rooms.active", "آخرین فعالیت"),
+    ("announce.readonly", "{} فقط برای اطلاعیه است و تنها مدیران اتاق می‌توانند در آن پیام بفرستند"),
+    ("welcome.rules", "مهربان باشید، از موضوع خارج نشوید و هرزنامه نفرستید"),
+    ("welcome.greeting", "{}، خوش آمدید! لطفاً قوانین را بخوانید:\n{}\nبرای پذیرفتن آن‌ها و شروع گفتگو /agree را بفرستید. /help فهرست فرمان‌ها را نشان می‌دهد."),
+    ("welcome.pending", "هنوز قوانین را نپذیرفته‌اید. ابتدا /agree را بفرستید، یا /help برای فهرست فرمان‌ها."),
+    ("welcome.signin", "برای پذیرفتن قوانین وارد شوید"),
+    ("welcome.nothing", "چیزی برای پذیرفتن وجود ندارد"),
+    ("welcome.agreed", "سپاس، اکنون می‌توانید پیام بفرستید"),
+    ("register.signin", "ابتدا به‌عنوان مهمان وارد شوید، سپس /register"),
+    ("register.usage", "استفاده: /register <گذرواژه>"),
+    ("register.short", "گذرواژه باید دست‌کم ۸ نویسه باشد"),
+    ("register.taken", "این نام قبلاً ثبت شده است"),
+    ("register.done", "{} ثبت شد، بعداً با POST /login وارد شوید"),
+    ("token.signin", "فقط کاربران ثبت‌شده می‌توانند توکن بگیرند"),
+    ("token.issued", "توکن ورود: {}"),
+    ("email.signin", "برای تنظیم خلاصهٔ ایمیلی وارد شوید"),
+    ("email.disabled", "این سرور ایمیل نمی‌فرستد"),
+    ("email.register", "فقط کاربران ثبت‌شده می‌توانند ایمیل اضافه کنند، /register را ببینید"),
+    ("email.invalid", "{} نشانی ایمیل نیست"),
+    ("email.none", "نشانی ایمیلی تنظیم نشده، با /email <نشانی> اضافه کنید"),
+    ("email.show", "خلاصه‌ها به {} فرستاده می‌شوند، پس از {} دقیقه آفلاین بودن؛ برای توقف /email off"),
+    ("email.paused", "خلاصه‌های {} متوقف است، برای ادامه /email on"),
+    ("email.set", "خلاصهٔ پیام‌های ازدست‌رفته به {} فرستاده می‌شود، پس از {} دقیقه آفلاین بودن"),
+    ("email.resumed", "خلاصه‌های ایمیلی از سر گرفته شد"),
+    ("email.stopped", "خلاصه‌های ایمیلی متوقف شد"),
+    ("email.removed", "نشانی ایمیل حذف شد"),
+    ("email.subject", "{} پیام تازه در نبود شما"),
+    ("email.greeting", "سلام {}، این پیام‌ها را در نبودتان از دست دادید:"),
+    ("email.footer", "در گفتگو پاسخ دهید؛ برای توقف این ایمیل‌ها آنجا /email off بفرستید."),
+    ("push.signin", "برای تنظیم اعلان‌ها وارد شوید"),
+    ("push.disabled", "این سرور اعلان ارسال نمی‌کند"),
+    ("push.register", "فقط کاربران ثبت‌شده می‌توانند اعلان تنظیم کنند، /register را ببینید"),
+    ("push.usage", "استفاده: /push [ntfy <نشانی> [توکن]|gotify <نشانی> <توکن>|test|off]"),
+    ("push.token", "gotify به توکن برنامه نیاز دارد"),
+    ("push.badurl", "{} نشانی http یا https نیست"),
+    ("push.host", "{} اینجا مجاز نیست، یکی از این‌ها را به کار ببرید: {}"),
+    ("push.none", "مقصد اعلانی تنظیم نشده، با /push ntfy <نشانی> یا /push gotify <نشانی> <توکن> اضافه کنید"),
+    ("push.show", "هنگام آفلاین بودن اعلان‌ها به {} در {} فرستاده می‌شوند"),
+    ("push.set", "پیام‌های خصوصی و اشاره‌ها هنگام آفلاین بودن به {} در {} فرستاده می‌شوند"),
+    ("push.removed", "اعلان‌ها خاموش شد"),
+    ("push.test", "اعلان‌ها کار می‌کنند"),
+    ("push.sent", "اعلان آزمایشی به {} فرستاده شد"),
+    ("push.failed", "ارسال اعلان ناموفق بود: {}"),
+    ("push.direct", "{} به شما پیام داد"),
+    ("push.mention", "{} در #{} به شما اشاره کرد"),
+    ("push.room", "{} در #{}"),
+    ("notify.signin", "برای انتخاب سطح اعلان وارد شوید"),
+    ("notify.usage", "استفاده: /notify [<اتاق> [all|mentions|mute]]"),
+    ("notify.none", "همه اتاق‌ها فقط برای اشاره‌ها اعلان می‌فرستند، با /notify <اتاق> all یا mute تغییرش دهید"),
+    ("notify.room", "اتاق"),
+    ("notify.level", "سطح"),
+    ("notify.all", "#{} برای همه پیام‌ها اعلان می‌فرستد"),
+    ("notify.mentions", "#{} فقط برای اشاره‌ها اعلان می‌فرستد"),
+    ("notify.mute", "#{} بی‌صدا است"),
+    ("feed.usage", "استفاده: /feed <اتاق> [add <نشانی>|remove <شماره>]"),
+    ("feed.moderator", "فقط ناظران اتاق می‌توانند خوراک‌هایش را مدیریت کنند"),
+    ("feed.empty", "{} هیچ خوراکی را دنبال نمی‌کند"),
+    ("feed.title", "عنوان"),
+    ("feed.url", "نشانی"),
+    ("feed.badurl", "{} نشانی http یا https نیست"),
+    ("feed.exists", "{} این خوراک را از پیش دنبال می‌کند"),
+    ("feed.failed", "خواندن خوراک ناموفق بود"),
+    ("feed.added", "{} اکنون در {} منتشر می‌شود، هر {} دقیقه بررسی می‌شود"),
+    ("feed.nofeed", "خوراک شمارهٔ {} وجود ندارد، /feed <اتاق> را ببینید"),
+    ("feed.removed", "{} دیگر در {} منتشر نمی‌شود"),
+    ("feed.untitled", "خوراک"),
+    ("translate.disabled", "این سرور سرویس ترجمه ندارد"),
+    ("translate.usage", "استفاده: /translate <زبان> <شناسهٔ_پیام|متن>"),
+    ("translate.missing", "پیام {} وجود ندارد"),
+    ("translate.empty", "متنی برای ترجمه نیست"),
+    ("translate.long", "متن برای ترجمه بیش از حد طولانی است، حداکثر {} بایت"),
+    ("translate.failed", "ترجمه ناموفق بود: {}"),
+    ("translate.from", "[{} → {}] {}"),
+    ("translate.into", "[{}] {}"),
+    ("unfurl.disabled", "این سرور پیش‌نمایش پیوند ندارد"),
+    ("unfurl.usage", "استفاده: /unfurl <اتاق> [on|off]"),
+    ("unfurl.moderator", "فقط ناظران اتاق می‌توانند پیش‌نمایش پیوندها را تغییر دهند"),
+    ("unfurl.on", "پیوندهای {} پیش‌نمایش می‌گیرند"),
+    ("unfurl.off", "پیوندهای {} پیش‌نمایش نمی‌گیرند"),
+    ("who.guest", " (مهمان)"),
+    ("who.away", " (غایب)"),
+    ("sessions.signin", "برای مدیریت نشست‌ها وارد شوید"),
+    ("sessions.usage", "استفاده: /sessions [revoke <شناسه>|revoke all]"),
+    ("sessions.none", "نشستی با {} پیدا نشد"),
+    ("sessions.revoked", "{} نشست لغو شد"),
+    ("sessions.id", "شناسه"),
+    ("sessions.via", "روش"),
+    ("sessions.device", "دستگاه"),
+    ("sessions.since", "از"),
+    ("fingerprint.usage", "استفاده: /fingerprint <کاربر> [qr]"),
+    ("fingerprint.nouser", "کاربر {} پیدا نشد"),
+    ("fingerprint.none", "{} کلید منتشرشده‌ای ندارد"),
+    ("fingerprint.key", "کلید"),
+    ("fingerprint.print", "اثر انگشت"),
+];
+
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+pub fn tr(key: &'static str) -> &'static str {
+    let table = match lang() {
+        Lang::En => EN,
+        Lang::Fa => FA,
+    };
+    lookup(table, key).or_else(|| lookup(EN, key)).unwrap_or(key)
+}
+
+pub fn trf(key: &'static str, args: &[&str]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut rest = tr(key);
+    while let Some(pos) = rest.find("{}") {
+        out.push_str(&rest[..pos]);
+        out.push_str(args.next().copied().unwrap_or(""));
+        rest = &rest[pos + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::time::timestamp;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn parse(raw: &str) -> Option<Level> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        };
+        f.pad(label)
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn init_from_env() {
+    if let Some(level) = std::env::var("MORCE_LOG").ok().as_deref().and_then(Level::parse) {
+        set_level(level);
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 >= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn write(level: Level, target: Option<&str>, args: std::fmt::Arguments) {
+    match target {
+        Some(target) => eprintln!("{} {:<5} [{}] {}", timestamp(), level, target, args),
+        None => eprintln!("{} {:<5} {}", timestamp(), level, args),
+    }
+}
+
+#[macro_export]
+macro_rules! xlog {
+    ($level:expr, target: $target:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, Some($target), format_args!($($arg)+));
+        }
+    };
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, None, format_args!($($arg)+));
+        }
+    };
+}
@@ -0,0 +1,366 @@
+use actix_web::web;
+use async_trait::async_trait;
+use base64::Engine;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use uuid::Uuid;
+
+use crate::account;
+use crate::api::State;
+use crate::command::{Category, Command, Commands, Context, Help, Role};
+use crate::entity::Entity;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::presence::{self, Online};
+use crate::room;
+use crate::store::Store;
+use crate::task;
+use crate::time::{self, Clock};
+use crate::transport::{Tcp, Transport, Wire};
+use crate::xlog;
+
+const FLUSH_EVERY: Duration = Duration::from_secs(60);
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_DIGEST: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Security {
+    Plain,
+    StartTls,
+    Tls,
+}
+
+impl Security {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "plain" => Some(Security::Plain),
+            "starttls" => Some(Security::StartTls),
+            "tls" => Some(Security::Tls),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Security::Plain => "plain",
+            Security::StartTls => "starttls",
+            Security::Tls => "tls",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Smtp {
+    pub addr: String,
+    pub security: Security,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+}
+
+impl Smtp {
+    fn host(&self) -> &str {
+        self.addr.rsplit_once(':').map_or(self.addr.as_str(), |(host, _)| host).trim_matches(['[', ']'])
+    }
+
+    fn domain(&self) -> &str {
+        self.from.rsplit_once('@').map_or("localhost", |(_, domain)| domain)
+    }
+}
+
+pub struct Mail {
+    smtp: Smtp,
+    after: Duration,
+    sent: DashMap<Uuid, Instant>,
+    queue: DashMap<Uuid, Vec<String>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Mail {
+    pub fn new(smtp: Smtp, after: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            smtp,
+            after,
+            sent: DashMap::new(),
+            queue: DashMap::new(),
+            clock,
+        }
+    }
+
+    pub fn forget(&self, user: Uuid) {
+        self.queue.remove(&user);
+    }
+
+    fn away(&self, online: &Online, user: Uuid) -> bool {
+        online.away_for(user) >= self.after
+    }
+
+    fn due(&self, online: &Online, user: Uuid) -> bool {
+        self.away(online, user) && self.sent.get(&user).is_none_or(|at| self.clock.since(*at) >= self.after)
+    }
+}
+
+pub fn valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && address.len() <= 254
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains('@')
+        && !address.contains(|c: char| c.is_whitespace() || c.is_control() || "<>,=&\"".contains(c))
+}
+
+async fn contact(store: &dyn Store, user: Uuid) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "contact".into());
+    pred.insert("user".into(), user.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+fn subscribed(contact: &Entity) -> bool {
+    contact.tags.get("digest").map(String::as_str) != Some("off")
+}
+
+async fn notice(state: &State, mail: &Mail, entity: &Entity) {
+    let from = entity.tags.get("from").cloned().unwrap_or_default();
+    if from == "system" || Commands::parse(&entity.load).is_some() {
+        return;
+    }
+    let text = String::from_utf8_lossy(&entity.load);
+    let mut waiting = Vec::new();
+    for user in presence::recipients(state, entity, &text).await {
+        if from != user.to_string() && mail.away(&state.online, user) && contact(&*state.store, user).await.is_some_and(|c| subscribed(&c)) {
+            waiting.push(user);
+        }
+    }
+    if waiting.is_empty() {
+        return;
+    }
+    let sender = presence::display_name(&*state.store, &from).await;
+    let line = match entity.tags.get("in") {
+        Some(room) => {
+            let room = room::find(&*state.store, room).await
+                .and_then(|c| c.tags.get("name").cloned())
+                .unwrap_or_else(|| room.clone());
+            format!("[{}] {} #{}: {}", time::iso_millis(entity.at), sender, room, text)
+        }
+        None => format!("[{}] {}: {}", time::iso_millis(entity.at), sender, text),
+    };
+    for user in waiting {
+        let mut queued = mail.queue.entry(user).or_default();
+        if queued.len() < MAX_DIGEST {
+            queued.push(line.clone());
+        }
+    }
+}
+
+fn header(text: &str) -> String {
+    match text.is_ascii() {
+        true => text.to_string(),
+        false => format!("=?utf-8?b?{}?=", base64::engine::general_purpose::STANDARD.encode(text)),
+    }
+}
+
+fn compose(smtp: &Smtp, to: &str, subject: &str, body: &str, now: Duration) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(body);
+    let lines: Vec<&str> = encoded.as_bytes().chunks(76).map(|c| std::str::from_utf8(c).unwrap_or_default()).collect();
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        smtp.from, to, header(subject), time::rfc2822(now), Uuid::new_v4().simple(), smtp.domain(), lines.join("\r\n")
+    )
+}
+
+type Line = BufReader<Box<dyn Wire>>;
+
+async fn reply(line: &mut Line) -> Result<(u16, String), String> {
+    let mut text = String::new();
+    loop {
+        let mut row = String::new();
+        if line.read_line(&mut row).await.map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed".into());
+        }
+        let code = row.get(..3).and_then(|c| c.parse().ok()).ok_or(format!("bad reply {:?}", row.trim_end()))?;
+        text.push_str(row.get(4..).unwrap_or_default());
+        if row.as_bytes().get(3) != Some(&b'-') {
+            return Ok((code, text));
+        }
+    }
+}
+
+async fn send(line: &mut Line, command: &str, expect: u16) -> Result<String, String> {
+    line.write_all(format!("{}\r\n", command).as_bytes()).await.map_err(|e| e.to_string())?;
+    line.flush().await.map_err(|e| e.to_string())?;
+    let (code, text) = reply(line).await?;
+    if code / 100 != expect / 100 {
+        let command = command.split(' ').next().unwrap_or(command);
+        return Err(format!("{} answered {} {}", command, code, text.trim_end()));
+    }
+    Ok(text)
+}
+
+async fn tls(stream: Box<dyn Wire>, host: &str) -> Result<Box<dyn Wire>, String> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+    let stream = TlsConnector::from(Arc::new(config)).connect(name, stream).await.map_err(|e| e.to_string())?;
+    Ok(Box::new(stream))
+}
+
+async fn deliver(smtp: &Smtp, to: &str, message: &str) -> Result<(), String> {
+    let stream = Tcp::direct().connect(&smtp.addr).await.map_err(|e| e.to_string())?;
+    let stream = match smtp.security {
+        Security::Tls => tls(stream, smtp.host()).await?,
+        _ => stream,
+    };
+    let mut line = BufReader::new(stream);
+    match reply(&mut line).await? {
+        (220, _) => {}
+        (code, text) => return Err(format!("greeting {} {}", code, text.trim_end())),
+    }
+    let ehlo = format!("EHLO {}", smtp.domain());
+    send(&mut line, &ehlo, 250).await?;
+    if smtp.security == Security::StartTls {
+        send(&mut line, "STARTTLS", 220).await?;
+        line = BufReader::new(tls(line.into_inner(), smtp.host()).await?);
+        send(&mut line, &ehlo, 250).await?;
+    }
+    if let (Some(user), Some(password)) = (&smtp.user, &smtp.password) {
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", user, password));
+        send(&mut line, &format!("AUTH PLAIN {}", token), 235).await?;
+    }
+    send(&mut line, &format!("MAIL FROM:<{}>", smtp.from), 250).await?;
+    send(&mut line, &format!("RCPT TO:<{}>", to), 250).await?;
+    send(&mut line, "DATA", 354).await?;
+    send(&mut line, &format!("{}.", message), 250).await?;
+    let _ = send(&mut line, "QUIT", 221).await;
+    Ok(())
+}
+
+async fn flush(state: &State, mail: &Mail) {
+    let users: Vec<Uuid> = mail.queue.iter().map(|e| *e.key()).collect();
+    for user in users {
+        if !mail.due(&state.online, user) {
+            continue;
+        }
+        let Some((_, lines)) = mail.queue.remove(&user) else {
+            continue;
+        };
+        let Some(contact) = contact(&*state.store, user).await.filter(subscribed) else {
+            continue;
+        };
+        let Some(address) = contact.tags.get("email").filter(|a| valid_address(a)) else {
+            continue;
+        };
+        let who = presence::display_name(&*state.store, &user.to_string()).await;
+        let subject = trf("email.subject", &[&lines.len().to_string()]);
+        let body = format!("{}\n\n{}\n\n{}\n", trf("email.greeting", &[&who]), lines.join("\n"), tr("email.footer"));
+        let message = compose(&mail.smtp, address, &subject, &body, mail.clock.unix());
+        mail.sent.insert(user, mail.clock.instant());
+        match tokio::time::timeout(SMTP_TIMEOUT, deliver(&mail.smtp, address, &message)).await {
+            Ok(Ok(())) => xlog!(Level::Info, target: "mail", "sent a digest of {} to {}", lines.len(), who),
+            Ok(Err(err)) => xlog!(Level::Warn, target: "mail", "digest for {} failed: {}", who, err),
+            Err(_) => xlog!(Level::Warn, target: "mail", "digest for {} timed out", who),
+        }
+    }
+}
+
+pub fn start(state: web::Data<State>) {
+    let Some(mail) = state.mail.clone() else {
+        return;
+    };
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    let (_, mut rx) = state.hub.subscribe(pred);
+    let (hub_state, hub_mail) = (state.clone(), mail.clone());
+    task::spawn("mail.hub", async move {
+        while let Some(entity) = rx.recv().await {
+            notice(&hub_state, &hub_mail, &entity).await;
+        }
+    });
+    task::spawn("mail", async move {
+        xlog!(Level::Info, target: "mail", "digests via {} ({}) after {} minutes away", mail.smtp.addr, mail.smtp.security, mail.after.as_secs() / 60);
+        let mut ticker = tokio::time::interval(FLUSH_EVERY);
+        loop {
+            ticker.tick().await;
+            flush(&state, &mail).await;
+        }
+    });
+}
+
+pub struct Email;
+
+#[async_trait]
+impl Command for Email {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "[address|on|off|remove]", about: "help.email" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("email.signin"))?;
+        let Some(mail) = &ctx.state.mail else {
+            return Err(tr("email.disabled").into());
+        };
+        let store = &*ctx.state.store;
+        let current = contact(store, actor).await;
+        let minutes = (mail.after.as_secs() / 60).to_string();
+        match (args, current) {
+            ("", None) => Ok(tr("email.none").into()),
+            ("", Some(c)) => Ok(match subscribed(&c) {
+                true => trf("email.show", &[c.tags.get("email").map_or("", String::as_str), &minutes]),
+                false => trf("email.paused", &[c.tags.get("email").map_or("", String::as_str)]),
+            }),
+            ("on" | "off" | "remove", None) => Err(tr("email.none").into()),
+            ("remove", Some(c)) => {
+                store.delete(c.id).await.map_err(|e| e.to_string())?;
+                mail.queue.remove(&actor);
+                Ok(tr("email.removed").into())
+            }
+            (toggle @ ("on" | "off"), Some(c)) => {
+                let mut tags = c.tags.clone();
+                tags.insert("digest".into(), toggle.into());
+                store.update(c.id, c.load.clone(), tags).await.map_err(|e| e.to_string())?;
+                if toggle == "off" {
+                    mail.queue.remove(&actor);
+                }
+                Ok(tr(if toggle == "on" { "email.resumed" } else { "email.stopped" }).into())
+            }
+            (address, current) => {
+                if !account::registered(store, actor).await {
+                    return Err(tr("email.register").into());
+                }
+                if !valid_address(address) {
+                    return Err(trf("email.invalid", &[&layout::truncate(address, 64)]));
+                }
+                let mut tags = BTreeMap::new();
+                tags.insert("kind".into(), "contact".into());
+                tags.insert("user".into(), actor.to_string());
+                tags.insert("email".into(), address.to_string());
+                tags.insert("digest".into(), "on".into());
+                match current {
+                    Some(c) => store.update(c.id, c.load.clone(), tags).await,
+                    None => store.create(bytes::Bytes::new(), tags).await,
+                }.map_err(|e| e.to_string())?;
+                Ok(trf("email.set", &[address, &minutes]))
+            }
+        }
+    }
+}
@@ -1,49 +1,125 @@
-mod entity;
-mod predicate;
-mod store;
-mod index;
-mod memory;
-mod hub;
-mod api;
-mod ws;
-mod console;
-mod parse;
-mod format;
-mod guard;
-
-use actix_web::{web, App, HttpServer};
-use std::sync::Arc;
-
-use api::State;
-use memory::Memory;
-use hub::Hub;
-
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    println!("morce server starting on http://127.0.0.1:8080");
-    println!("open http://127.0.0.1:8080/console for the terminal");
-
-    let store = Arc::new(Memory::new());
-    let hub = Arc::new(Hub::new());
-
-    let state = web::Data::new(State {
-        store: store.clone(),
-        hub: hub.clone(),
-    });
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(state.clone())
-            .route("/entities", web::post().to(api::create))
-            .route("/entities", web::get().to(api::query))
-            .route("/entities/{id}", web::get().to(api::read))
-            .route("/entities/{id}", web::put().to(api::update))
-            .route("/entities/{id}", web::delete().to(api::delete))
-            .route("/eval/{id}", web::get().to(api::eval))
-            .route("/ws", web::get().to(ws::handler))
-            .route("/console", web::get().to(console::page))
-    })
-        .bind("127.0.0.1:8080")?
-        .run()
-        .await
-}
\ No newline at end of file
+use morce::backup;
+use morce::cli::{self, Shell};
+use morce::client;
+use morce::doctor;
+use morce::health;
+use morce::init;
+use morce::invite;
+use morce::locale;
+use morce::log;
+use morce::p2p;
+use morce::profile;
+use morce::record;
+use morce::server::{self, Config};
+use morce::stress::{self, Options};
+use morce::task;
+
+fn main() -> std::io::Result<()> {
+    task::init();
+    log::init_from_env();
+    locale::init_from_env();
+
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("stress") {
+        args.next();
+        let options = Options::from_args(args).unwrap_or_else(|err| exit(&err));
+        let mut report = tokio::runtime::Runtime::new()?.block_on(stress::run(options));
+        println!("{}", report.render());
+        return Ok(());
+    }
+
+    if args.peek().map(String::as_str) == Some("p2p") {
+        args.next();
+        let options = p2p::Options::from_args(args).unwrap_or_else(|err| exit(&err));
+        return tokio::runtime::Runtime::new()?.block_on(p2p::run(options));
+    }
+
+    if args.next_if(|a| a == "client").is_some() || args.peek().is_some_and(|a| a.starts_with("morce://")) {
+        let servers = client::Options::from_args(args).unwrap_or_else(|err| exit(&err));
+        let result = tokio::runtime::Runtime::new()?.block_on(client::run(servers));
+        result.unwrap_or_else(|err| exit(&err));
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "init").is_some() {
+        println!("{}", init::run(args).unwrap_or_else(|err| exit(&err)));
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "profile").is_some() {
+        println!("{}", profile::run(args).unwrap_or_else(|err| exit(&err)));
+        return Ok(());
+    }
+
+    if let Some(command) = args.next_if(|a| a == "backup" || a == "restore") {
+        let file_flag = if command == "backup" { "--out" } else { "--in" };
+        let options = backup::Options::from_args(args, file_flag).unwrap_or_else(|err| exit(&err));
+        let runtime = tokio::runtime::Runtime::new()?;
+        let result = if command == "backup" {
+            runtime.block_on(backup::backup(options))
+        } else {
+            runtime.block_on(backup::restore(options))
+        };
+        println!("{}", result.unwrap_or_else(|err| exit(&err)));
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "invite").is_some() {
+        let options = invite::Options::from_args(args).unwrap_or_else(|err| exit(&err));
+        let result = tokio::runtime::Runtime::new()?.block_on(invite::run(options));
+        println!("{}", result.unwrap_or_else(|err| exit(&err)));
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "healthcheck").is_some() {
+        let (target, timeout) = health::from_args(args).unwrap_or_else(|err| exit(&err));
+        match tokio::runtime::Runtime::new()?.block_on(health::check(&target, timeout)) {
+            Ok(body) => println!("{}", body),
+            Err(err) => {
+                eprintln!("unhealthy: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "playback").is_some() {
+        let options = record::Options::from_args(args).unwrap_or_else(|err| exit(&err));
+        let report = tokio::runtime::Runtime::new()?.block_on(record::run(options)).unwrap_or_else(|err| exit(&err));
+        println!("{}", report.render());
+        if report.failed() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "doctor").is_some() {
+        let options = doctor::Options::from_args(args).unwrap_or_else(|err| exit(&err));
+        let checks = tokio::runtime::Runtime::new()?.block_on(doctor::run(&options));
+        println!("{}", doctor::report(&options.addr, &checks));
+        if checks.iter().any(|c| c.outcome == doctor::Outcome::Fail) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "completions").is_some() {
+        let shell = args.next().as_deref().and_then(Shell::parse).unwrap_or_else(|| exit(cli::USAGE));
+        print!("{}", cli::completions(shell));
+        return Ok(());
+    }
+
+    if args.next_if(|a| a == "man").is_some() {
+        print!("{}", cli::man());
+        return Ok(());
+    }
+
+    args.next_if(|a| a == "server");
+    let config = Config::from_args(args).unwrap_or_else(|err| exit(&err));
+    server::run(config)
+}
+
+fn exit(err: &str) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(2);
+}
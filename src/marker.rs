@@ -0,0 +1,116 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::entity::Entity;
+use crate::guard;
+use crate::predicate::Predicate;
+use crate::room;
+use crate::store::Store;
+use crate::time;
+
+async fn marker(store: &dyn Store, user: Uuid, room: Uuid) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "marker".into());
+    pred.insert("user".into(), user.to_string());
+    pred.insert("room".into(), room.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+fn at(marker: &Entity) -> u64 {
+    marker.tags.get("at").and_then(|v| v.parse().ok()).unwrap_or_default()
+}
+
+pub async fn position(store: &dyn Store, user: Uuid, room: Uuid) -> u64 {
+    if let Some(marker) = marker(store, user, room).await {
+        return at(&marker);
+    }
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "perm".into());
+    pred.insert("who".into(), user.to_string());
+    pred.insert("what".into(), room.to_string());
+    store.query(&pred).await.unwrap_or_default().iter().map(|p| p.at).min().unwrap_or_default()
+}
+
+pub async fn history(store: &dyn Store, room: Uuid) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    pred.insert("in".into(), room.to_string());
+    let mut found = store.query(&pred).await.unwrap_or_default();
+    found.sort_by_key(|m| m.at);
+    found
+}
+
+pub fn unread(history: &[Entity], user: Uuid, since: u64) -> Vec<&Entity> {
+    let me = user.to_string();
+    history[history.partition_point(|m| m.at <= since)..].iter()
+        .filter(|m| m.tags.get("from") != Some(&me))
+        .collect()
+}
+
+pub async fn advance(store: &dyn Store, user: Uuid, room: Uuid, to: u64) -> Result<u64, String> {
+    let current = marker(store, user, room).await;
+    if let Some(current) = &current {
+        if at(current) >= to {
+            return Ok(at(current));
+        }
+    }
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "marker".into());
+    tags.insert("user".into(), user.to_string());
+    tags.insert("room".into(), room.to_string());
+    tags.insert("at".into(), to.to_string());
+    match current {
+        Some(current) => store.update(current.id, bytes::Bytes::new(), tags).await,
+        None => store.create(bytes::Bytes::new(), tags).await,
+    }.map_err(|e| e.to_string())?;
+    Ok(to)
+}
+
+async fn member(state: &State, req: &HttpRequest, room: &str) -> actix_web::Result<(Uuid, Entity)> {
+    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+    let store = &*state.store;
+    let channel = room::find(store, room).await
+        .ok_or(actix_web::error::ErrorNotFound("no such room"))?;
+    let actor = guard::check(store, auth_header, "read", Some(channel.id)).await?
+        .ok_or(guard::Denied::NoSession)?;
+    Ok((actor, channel))
+}
+
+async fn report(store: &dyn Store, actor: Uuid, channel: &Entity) -> HttpResponse {
+    let since = position(store, actor, channel.id).await;
+    let history = history(store, channel.id).await;
+    let unread = unread(&history, actor, since);
+    HttpResponse::Ok().json(json!({
+        "room": channel.id.to_string(),
+        "at": since,
+        "read": time::iso_millis(since),
+        "unread": unread.len(),
+        "first": unread.first().map(|m| m.id.to_string()),
+    }))
+}
+
+pub async fn show(state: web::Data<State>, req: HttpRequest, path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+    let (actor, channel) = member(&state, &req, &path).await?;
+    Ok(report(&*state.store, actor, &channel).await)
+}
+
+pub async fn mark(state: web::Data<State>, req: HttpRequest, path: web::Path<String>, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let (actor, channel) = member(&state, &req, &path).await?;
+    let store = &*state.store;
+    let raw = String::from_utf8_lossy(&body);
+    let to = match raw.trim() {
+        "" => history(store, channel.id).await.last().map_or(0, |m| m.at),
+        id => {
+            let id = id.parse::<Uuid>().map_err(|_| actix_web::error::ErrorBadRequest("expected a message id"))?;
+            store.read(id).await?
+                .filter(|m| m.tags.get("in") == Some(&channel.id.to_string()))
+                .ok_or(actix_web::error::ErrorNotFound("no such message in this room"))?
+                .at
+        }
+    };
+    advance(store, actor, channel.id, to).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(report(store, actor, &channel).await)
+}
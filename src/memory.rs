@@ -1,23 +1,35 @@
 use async_trait::async_trait;
 use dashmap::DashMap;
 use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
 use crate::entity::Entity;
 use crate::index::Index;
 use crate::predicate::Predicate;
 use crate::store::{Error, Store};
+use crate::time::{self, Clock};
 
 pub struct Memory {
     items: DashMap<Uuid, Entity>,
     index: Index,
+    gate: RwLock<()>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new(time::system())
+    }
 }
 
 impl Memory {
-    pub fn new() -> Self {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
         Self {
             items: DashMap::new(),
             index: Index::new(),
+            gate: RwLock::new(()),
+            clock,
         }
     }
 
@@ -33,17 +45,23 @@ impl Memory {
 #[async_trait]
 impl Store for Memory {
     async fn create(&self, load: bytes::Bytes, tags: BTreeMap<String, String>) -> Result<Entity, Error> {
-        let entity = Entity::new(load, tags);
+        let entity = Entity::new(load, tags, self.clock.millis());
+        let _gate = self.gate.read().map_err(|_| Error::Internal)?;
         self.items.insert(entity.id, entity.clone());
         self.update_index(&entity);
         Ok(entity)
     }
 
+    fn now(&self) -> u64 {
+        self.clock.millis()
+    }
+
     async fn read(&self, id: Uuid) -> Result<Option<Entity>, Error> {
         Ok(self.items.get(&id).map(|e| e.clone()))
     }
 
     async fn update(&self, id: Uuid, load: bytes::Bytes, tags: BTreeMap<String, String>) -> Result<Entity, Error> {
+        let _gate = self.gate.read().map_err(|_| Error::Internal)?;
         let mut entity = self.items.get_mut(&id).ok_or(Error::NotFound)?;
         self.remove_index(&entity);
         entity.load = load;
@@ -53,6 +71,7 @@ impl Store for Memory {
     }
 
     async fn delete(&self, id: Uuid) -> Result<(), Error> {
+        let _gate = self.gate.read().map_err(|_| Error::Internal)?;
         if let Some((_, entity)) = self.items.remove(&id) {
             self.remove_index(&entity);
             Ok(())
@@ -94,6 +113,24 @@ impl Store for Memory {
         }
         Ok(result)
     }
+
+    async fn snapshot(&self) -> Result<Vec<Entity>, Error> {
+        let _gate = self.gate.write().map_err(|_| Error::Internal)?;
+        Ok(self.items.iter().map(|entry| entry.value().clone()).collect())
+    }
+
+    async fn restore(&self, entities: Vec<Entity>) -> Result<(), Error> {
+        let _gate = self.gate.write().map_err(|_| Error::Internal)?;
+        for entry in self.items.iter() {
+            self.remove_index(entry.value());
+        }
+        self.items.clear();
+        for entity in entities {
+            self.update_index(&entity);
+            self.items.insert(entity.id, entity);
+        }
+        Ok(())
+    }
 }
 
 fn matches_predicate(tags: &BTreeMap<String, String>, predicate: &Predicate) -> bool {
@@ -0,0 +1,283 @@
+use actix_web::web;
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::entity::Entity;
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+const KEEP_ALIVE: u16 = 60;
+const PING_EVERY: Duration = Duration::from_secs(30);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+const MAX_PACKET: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub struct Broker {
+    pub addr: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+enum Packet {
+    ConnAck(u8),
+    Publish { topic: String, payload: Bytes, ack: Option<u16> },
+    Other,
+}
+
+fn string(out: &mut Vec<u8>, text: &str) {
+    out.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        out.push(if len > 0 { byte | 0x80 } else { byte });
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+fn connect(broker: &Broker, client: &str) -> Vec<u8> {
+    let mut flags = 0x02;
+    if broker.user.is_some() {
+        flags |= 0x80;
+    }
+    if broker.password.is_some() {
+        flags |= 0x40;
+    }
+    let mut body = Vec::new();
+    string(&mut body, "MQTT");
+    body.extend_from_slice(&[4, flags]);
+    body.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+    string(&mut body, client);
+    for field in [&broker.user, &broker.password].into_iter().flatten() {
+        string(&mut body, field);
+    }
+    packet(0x10, &body)
+}
+
+fn subscribe(id: u16, filters: &BTreeSet<String>) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+    for filter in filters {
+        string(&mut body, filter);
+        body.push(0);
+    }
+    packet(0x82, &body)
+}
+
+fn publish(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    string(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(0x30, &body)
+}
+
+async fn read_packet(reader: &mut OwnedReadHalf) -> std::io::Result<Packet> {
+    let head = reader.read_u8().await?;
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        let byte = reader.read_u8().await?;
+        len |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if shift > 21 {
+            return Err(std::io::Error::other("malformed remaining length"));
+        }
+    }
+    if len > MAX_PACKET {
+        return Err(std::io::Error::other(format!("packet of {} bytes is too large", len)));
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).await?;
+    Ok(match head >> 4 {
+        2 => Packet::ConnAck(body.get(1).copied().unwrap_or(0xff)),
+        3 => {
+            let qos = (head >> 1) & 0x03;
+            let size = u16::from_be_bytes([body.first().copied().unwrap_or(0), body.get(1).copied().unwrap_or(0)]) as usize;
+            let topic = String::from_utf8_lossy(body.get(2..2 + size).unwrap_or_default()).into_owned();
+            let mut at = 2 + size;
+            let ack = (qos > 0).then(|| {
+                at += 2;
+                u16::from_be_bytes([body.get(at - 2).copied().unwrap_or(0), body.get(at - 1).copied().unwrap_or(0)])
+            });
+            Packet::Publish { topic, payload: Bytes::from(body.get(at..).unwrap_or_default().to_vec()), ack }
+        }
+        _ => Packet::Other,
+    })
+}
+
+pub fn matches(filter: &str, topic: &str) -> bool {
+    let mut topic = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(part)) if level == part => {}
+            _ => return false,
+        }
+    }
+    topic.next().is_none()
+}
+
+async fn channels(store: &dyn Store) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "channel".into());
+    store.query(&pred).await.unwrap_or_default()
+}
+
+async fn inbound(state: &State, topic: &str, payload: Bytes) {
+    for channel in channels(&*state.store).await {
+        if !channel.tags.get("mqtt").is_some_and(|filter| matches(filter, topic)) {
+            continue;
+        }
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "msg".into());
+        tags.insert("in".into(), channel.id.to_string());
+        tags.insert("from".into(), "mqtt".into());
+        tags.insert("topic".into(), topic.replace([',', '='], "_"));
+        let Ok((tags, load)) = state.hooks.filter(state, None, tags, payload.clone()).await else {
+            continue;
+        };
+        let Ok(entity) = state.store.create(load, tags).await else {
+            continue;
+        };
+        state.stats.record_in(&entity);
+        state.hub.publish(&entity);
+    }
+}
+
+async fn outbound(state: &State, entity: &Entity) -> Option<Vec<u8>> {
+    if entity.tags.get("from").map(String::as_str) == Some("mqtt") {
+        return None;
+    }
+    let room = entity.tags.get("in")?.parse::<Uuid>().ok()?;
+    let channel = state.store.read(room).await.ok()??;
+    let topic = channel.tags.get("mqtt-command")?;
+    Some(publish(topic, &entity.load))
+}
+
+async fn session(state: &State, broker: &Broker, messages: &mut mpsc::UnboundedReceiver<Entity>) -> Result<(), String> {
+    let stream = TcpStream::connect(&broker.addr).await.map_err(|e| e.to_string())?;
+    let (mut reader, mut writer) = stream.into_split();
+    let fail = |e: std::io::Error| e.to_string();
+    writer.write_all(&connect(broker, &format!("morce-{}", Uuid::new_v4().simple()))).await.map_err(fail)?;
+    match read_packet(&mut reader).await.map_err(fail)? {
+        Packet::ConnAck(0) => {}
+        Packet::ConnAck(code) => return Err(format!("broker refused the connection (code {})", code)),
+        _ => return Err("expected CONNACK".into()),
+    }
+    xlog!(Level::Info, target: "mqtt", "connected to {}", broker.addr);
+
+    let (tx, mut packets) = mpsc::unbounded_channel();
+    let reading = task::spawn("mqtt.read", async move {
+        loop {
+            let read = read_packet(&mut reader).await;
+            let failed = read.is_err();
+            if tx.send(read).is_err() || failed {
+                return;
+            }
+        }
+    });
+    let mut subscribed = BTreeSet::new();
+    let (mut next_id, mut rescan) = (0u16, true);
+    let mut ticker = tokio::time::interval(PING_EVERY);
+    let result = loop {
+        if std::mem::take(&mut rescan) {
+            let fresh: BTreeSet<String> = channels(&*state.store).await.iter()
+                .filter_map(|c| c.tags.get("mqtt").cloned())
+                .filter(|filter| !subscribed.contains(filter))
+                .collect();
+            if !fresh.is_empty() {
+                next_id = next_id.wrapping_add(1).max(1);
+                if let Err(err) = writer.write_all(&subscribe(next_id, &fresh)).await {
+                    break Err(err.to_string());
+                }
+                xlog!(Level::Debug, target: "mqtt", "subscribed to {:?}", fresh);
+                subscribed.extend(fresh);
+            }
+        }
+        let sent = tokio::select! {
+            _ = ticker.tick() => writer.write_all(&[0xc0, 0x00]).await,
+            entity = messages.recv() => match entity {
+                Some(entity) if entity.tags.get("kind").map(String::as_str) == Some("channel") => {
+                    rescan = true;
+                    Ok(())
+                }
+                Some(entity) => match outbound(state, &entity).await {
+                    Some(bytes) => writer.write_all(&bytes).await,
+                    None => Ok(()),
+                },
+                None => break Ok(()),
+            },
+            read = packets.recv() => match read {
+                Some(Ok(Packet::Publish { topic, payload, ack })) => {
+                    inbound(state, &topic, payload).await;
+                    match ack {
+                        Some(id) => writer.write_all(&packet(0x40, &id.to_be_bytes())).await,
+                        None => Ok(()),
+                    }
+                }
+                Some(Ok(_)) => Ok(()),
+                Some(Err(err)) => break Err(err.to_string()),
+                None => break Err("connection closed".into()),
+            },
+        };
+        if let Err(err) = sent {
+            break Err(err.to_string());
+        }
+    };
+    reading.abort();
+    result
+}
+
+pub fn start(state: web::Data<State>, broker: Broker) {
+    let (tx, mut messages) = mpsc::unbounded_channel();
+    for kind in ["msg", "channel"] {
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), kind.into());
+        let (_, mut rx) = state.hub.subscribe(pred);
+        let tx = tx.clone();
+        task::spawn("mqtt.hub", async move {
+            while let Some(entity) = rx.recv().await {
+                if tx.send(entity).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    task::spawn("mqtt", async move {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            let began = state.clock.instant();
+            match session(&state, &broker, &mut messages).await {
+                Ok(()) => return,
+                Err(err) => xlog!(Level::Warn, target: "mqtt", "{}: {}, retrying in {}s", broker.addr, err, delay.as_secs()),
+            }
+            if state.clock.since(began) > RETRY_MAX {
+                delay = Duration::from_secs(1);
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(RETRY_MAX);
+            while messages.try_recv().is_ok() {}
+        }
+    });
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::xlog;
+
+const MAX_SUFFIX: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    #[default]
+    Reject,
+    Suffix,
+    Kick,
+}
+
+impl Policy {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "reject" => Some(Policy::Reject),
+            "suffix" => Some(Policy::Suffix),
+            "kick" => Some(Policy::Kick),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Policy::Reject => "reject",
+            Policy::Suffix => "suffix",
+            Policy::Kick => "kick",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Suffixed,
+    Kicked,
+}
+
+impl Resolution {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "suffix" => Some(Resolution::Suffixed),
+            "kick" => Some(Resolution::Kicked),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Resolution::Suffixed => "suffix",
+            Resolution::Kicked => "kick",
+        })
+    }
+}
+
+struct Claim {
+    registered: bool,
+    online: Vec<Uuid>,
+}
+
+impl Claim {
+    fn free(&self) -> bool {
+        !self.registered && self.online.is_empty()
+    }
+}
+
+async fn claimed(state: &State, name: &str) -> Claim {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "user".into());
+    pred.insert("name".into(), name.to_string());
+    let mut claim = Claim { registered: false, online: Vec::new() };
+    for user in state.store.query(&pred).await.unwrap_or_default() {
+        if user.tags.get("registered").map(String::as_str) == Some("true") {
+            claim.registered = true;
+        } else if state.online.is_online(user.id) {
+            claim.online.push(user.id);
+        }
+    }
+    claim
+}
+
+pub async fn kick(state: &State, user: Uuid) -> usize {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "session".into());
+    pred.insert("actor".into(), user.to_string());
+    let mut kicked = 0;
+    for session in state.store.query(&pred).await.unwrap_or_default() {
+        if state.store.delete(session.id).await.is_ok() {
+            kicked += 1;
+        }
+    }
+    kicked
+}
+
+pub async fn resolve(state: &State, ip: Option<IpAddr>, tags: &mut BTreeMap<String, String>) -> actix_web::Result<Option<Resolution>> {
+    if tags.get("kind").map(String::as_str) != Some("user") {
+        return Ok(None);
+    }
+    let Some(name) = tags.get("name").cloned() else {
+        return Ok(None);
+    };
+    let mut claim = claimed(state, &name).await;
+    if let Some(ip) = ip {
+        for user in claim.online.clone() {
+            let reaped = state.online.reap(user, ip, state.deadlines.probe, None).await;
+            if reaped > 0 {
+                xlog!(Level::Info, target: "names", "{} reconnected from {}, closed {} dead socket(s) of {}", name, ip, reaped, user);
+            }
+        }
+        claim.online.retain(|user| state.online.is_online(*user));
+    }
+    if claim.free() {
+        return Ok(None);
+    }
+    match state.names {
+        Policy::Suffix => {
+            for n in 2..MAX_SUFFIX {
+                let candidate = format!("{}_{}", name, n);
+                if claimed(state, &candidate).await.free() {
+                    xlog!(Level::Debug, target: "names", "{} is taken, using {}", name, candidate);
+                    tags.insert("name".into(), candidate);
+                    return Ok(Some(Resolution::Suffixed));
+                }
+            }
+            Err(actix_web::error::ErrorConflict("no free variant of this name"))
+        }
+        _ if claim.registered => Err(actix_web::error::ErrorConflict("name belongs to a registered account")),
+        Policy::Kick => {
+            let mut kicked = 0;
+            for user in claim.online {
+                let sessions = kick(state, user).await;
+                if sessions > 0 {
+                    xlog!(Level::Info, target: "names", "{} signed in again, closed {} session(s) of {}", name, sessions, user);
+                }
+                kicked += sessions;
+            }
+            Ok((kicked > 0).then_some(Resolution::Kicked))
+        }
+        Policy::Reject => Err(actix_web::error::ErrorConflict("name is in use")),
+    }
+}
@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use uuid::Uuid;
+
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::predicate::Predicate;
+use crate::room;
+use crate::store::Store;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Setting {
+    All,
+    #[default]
+    Mentions,
+    Mute,
+}
+
+impl Setting {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "all" => Some(Setting::All),
+            "mentions" => Some(Setting::Mentions),
+            "mute" | "none" => Some(Setting::Mute),
+            _ => None,
+        }
+    }
+
+    fn of(entity: &Entity) -> Self {
+        entity.tags.get("level").and_then(|v| Setting::parse(v)).unwrap_or_default()
+    }
+
+    fn describe(&self, room: &str) -> String {
+        trf(match self {
+            Setting::All => "notify.all",
+            Setting::Mentions => "notify.mentions",
+            Setting::Mute => "notify.mute",
+        }, &[room])
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Setting::All => "all",
+            Setting::Mentions => "mentions",
+            Setting::Mute => "mute",
+        })
+    }
+}
+
+async fn settings(store: &dyn Store, key: &str, value: &str) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "notify".into());
+    pred.insert(key.into(), value.into());
+    store.query(&pred).await.unwrap_or_default()
+}
+
+pub async fn levels(store: &dyn Store, room: Uuid) -> HashMap<String, Setting> {
+    settings(store, "room", &room.to_string()).await.iter()
+        .filter_map(|s| Some((s.tags.get("user")?.clone(), Setting::of(s))))
+        .collect()
+}
+
+pub struct Notify;
+
+#[async_trait]
+impl Command for Notify {
+    fn help(&self) -> Help {
+        Help { category: Category::Rooms, role: Role::User, usage: "[<room> [all|mentions|mute]]", about: "help.notify" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("notify.signin"))?;
+        let store = &*ctx.state.store;
+        let me = actor.to_string();
+        let mut parts = args.split_whitespace();
+        let (room, level) = match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => {
+                let mut rows = Vec::new();
+                for setting in settings(store, "user", &me).await {
+                    let Some(room) = setting.tags.get("room") else {
+                        continue;
+                    };
+                    let name = room::find(store, room).await
+                        .and_then(|c| c.tags.get("name").cloned())
+                        .unwrap_or_else(|| room.clone());
+                    rows.push(vec![name, Setting::of(&setting).to_string()]);
+                }
+                if rows.is_empty() {
+                    return Ok(tr("notify.none").into());
+                }
+                rows.sort();
+                return Ok(layout::table(&[tr("notify.room"), tr("notify.level")], &rows));
+            }
+            (Some(room), level, None) => (room, level),
+            _ => return Err(tr("notify.usage").into()),
+        };
+        let channel = room::find(store, room).await.ok_or_else(|| trf("join.noroom", &[room]))?;
+        let current: Vec<Entity> = settings(store, "user", &me).await.into_iter()
+            .filter(|s| s.tags.get("room") == Some(&channel.id.to_string()))
+            .collect();
+        let Some(raw) = level else {
+            return Ok(current.first().map(Setting::of).unwrap_or_default().describe(room));
+        };
+        let level = Setting::parse(raw).ok_or(tr("notify.usage"))?;
+        for setting in &current {
+            store.delete(setting.id).await.map_err(|e| e.to_string())?;
+        }
+        if level != Setting::default() {
+            let mut tags = BTreeMap::new();
+            tags.insert("kind".into(), "notify".into());
+            tags.insert("user".into(), me);
+            tags.insert("room".into(), channel.id.to_string());
+            tags.insert("level".into(), level.to_string());
+            store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+        }
+        Ok(level.describe(room))
+    }
+}
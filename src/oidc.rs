@@ -0,0 +1,222 @@
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::time::Clock;
+
+const KEYS_TTL: Duration = Duration::from_secs(3600);
+const REFETCH_AFTER: Duration = Duration::from_secs(60);
+const MAX_NAME_TRIES: usize = 100;
+const DEVICE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+struct Keys {
+    fetched: Option<(Instant, JwkSet)>,
+    attempted: Option<Instant>,
+}
+
+pub struct Oidc {
+    issuer: String,
+    audience: String,
+    algorithm: Algorithm,
+    admin_role: Option<String>,
+    client: reqwest::Client,
+    keys: RwLock<Keys>,
+    fetching: Mutex<()>,
+    clock: Arc<dyn Clock>,
+}
+
+async fn discover(client: &reqwest::Client, issuer: &str) -> Result<Value, String> {
+    client.get(format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/')))
+        .send().await.map_err(|e| e.to_string())?
+        .error_for_status().map_err(|e| e.to_string())?
+        .json().await.map_err(|e| e.to_string())
+}
+
+fn endpoint<'a>(discovery: &'a Value, key: &str) -> Result<&'a str, String> {
+    discovery.get(key).and_then(Value::as_str).ok_or(format!("discovery document has no {}", key))
+}
+
+pub fn algorithm(raw: &str) -> Option<Algorithm> {
+    Algorithm::from_str(raw).ok().filter(|alg| !matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512))
+}
+
+impl Oidc {
+    pub fn new(issuer: &str, audience: &str, algorithm: Algorithm, admin_role: Option<&str>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            audience: audience.to_string(),
+            algorithm,
+            admin_role: admin_role.map(str::to_string),
+            client: reqwest::Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default(),
+            keys: RwLock::new(Keys { fetched: None, attempted: None }),
+            fetching: Mutex::new(()),
+            clock,
+        }
+    }
+
+    async fn fetch_keys(&self) -> Result<JwkSet, String> {
+        let discovery = discover(&self.client, &self.issuer).await?;
+        self.client.get(endpoint(&discovery, "jwks_uri")?)
+            .send().await.map_err(|e| e.to_string())?
+            .json().await.map_err(|e| e.to_string())
+    }
+
+    async fn cached(&self, kid: &str) -> Option<Jwk> {
+        self.keys.read().await.fetched.as_ref()
+            .filter(|(at, _)| self.clock.since(*at) < KEYS_TTL)
+            .and_then(|(_, keys)| keys.find(kid).cloned())
+    }
+
+    async fn key(&self, kid: &str) -> Result<Jwk, String> {
+        if let Some(key) = self.cached(kid).await {
+            return Ok(key);
+        }
+        let _fetching = self.fetching.lock().await;
+        if let Some(key) = self.cached(kid).await {
+            return Ok(key);
+        }
+        {
+            let mut keys = self.keys.write().await;
+            if keys.attempted.is_some_and(|at| self.clock.since(at) < REFETCH_AFTER) {
+                return Err(format!("unknown key id {}", kid));
+            }
+            keys.attempted = Some(self.clock.instant());
+        }
+        let set = self.fetch_keys().await?;
+        let key = set.find(kid).cloned();
+        self.keys.write().await.fetched = Some((self.clock.instant(), set));
+        key.ok_or(format!("unknown key id {}", kid))
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Value, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        let kid = header.kid.ok_or("token has no key id")?;
+        let jwk = self.key(&kid).await?;
+        let expected = match jwk.common.key_algorithm {
+            Some(alg) => algorithm(&alg.to_string()).ok_or(format!("key {} uses unsupported algorithm {}", kid, alg))?,
+            None => self.algorithm,
+        };
+        if header.alg != expected {
+            return Err(format!("token is signed with {:?}, key {} expects {:?}", header.alg, kid, expected));
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+        let mut validation = Validation::new(expected);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        decode::<Value>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| e.to_string())
+    }
+
+    fn admin(&self, claims: &Value) -> bool {
+        let Some(role) = &self.admin_role else {
+            return false;
+        };
+        ["roles", "groups"].iter()
+            .filter_map(|key| claims.get(*key)?.as_array())
+            .flatten()
+            .any(|value| value.as_str() == Some(role.as_str()))
+    }
+
+    pub async fn sign_in(&self, store: &dyn Store, token: &str) -> Result<Uuid, String> {
+        let claims = self.verify(token).await?;
+        let sub = claims.get("sub").and_then(Value::as_str).ok_or("token has no subject")?;
+        let admin = self.admin_role.is_some().then(|| self.admin(&claims).to_string());
+        let subject = sub.replace([',', '='], "_");
+
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "user".into());
+        pred.insert("oidc".into(), subject.clone());
+        let existing = store.query(&pred).await.map_err(|e| e.to_string())?.into_iter().next();
+        if let Some(user) = existing {
+            if let Some(admin) = admin.filter(|admin| user.tags.get("admin") != Some(admin)) {
+                let mut tags = user.tags.clone();
+                tags.insert("admin".into(), admin);
+                store.update(user.id, user.load.clone(), tags).await.map_err(|e| e.to_string())?;
+            }
+            return Ok(user.id);
+        }
+
+        let base = ["preferred_username", "email", "sub"].iter()
+            .find_map(|key| claims.get(*key)?.as_str())
+            .unwrap_or(sub)
+            .replace([',', '='], "_");
+        let suffix: String = subject.chars().take(6).collect();
+        let mut name = base.clone();
+        let mut tries = 0;
+        while taken(store, &name).await? {
+            tries += 1;
+            if tries > MAX_NAME_TRIES {
+                return Err(format!("no free name for {}", base));
+            }
+            name = match tries {
+                1 => format!("{}-{}", base, suffix),
+                n => format!("{}-{}-{}", base, suffix, n),
+            };
+        }
+
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "user".into());
+        tags.insert("name".into(), name);
+        tags.insert("oidc".into(), subject);
+        tags.insert("registered".into(), "true".into());
+        if let Some(admin) = admin {
+            tags.insert("admin".into(), admin);
+        }
+        let user = store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+        Ok(user.id)
+    }
+}
+
+async fn taken(store: &dyn Store, name: &str) -> Result<bool, String> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "user".into());
+    pred.insert("name".into(), name.to_string());
+    Ok(!store.query(&pred).await.map_err(|e| e.to_string())?.is_empty())
+}
+
+pub async fn device_flow(issuer: &str, client_id: &str, prompt: impl Fn(&str, &str)) -> Result<String, String> {
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build().map_err(|e| e.to_string())?;
+    let discovery = discover(&client, issuer).await?;
+    let grant: Value = client.post(endpoint(&discovery, "device_authorization_endpoint")?)
+        .form(&[("client_id", client_id), ("scope", "openid profile email")])
+        .send().await.map_err(|e| e.to_string())?
+        .error_for_status().map_err(|e| e.to_string())?
+        .json().await.map_err(|e| e.to_string())?;
+    let field = |key: &str| grant.get(key).and_then(Value::as_str);
+    let device_code = field("device_code").ok_or("device authorization has no device_code")?;
+    let user_code = field("user_code").ok_or("device authorization has no user_code")?;
+    let uri = field("verification_uri_complete").or(field("verification_uri"))
+        .ok_or("device authorization has no verification_uri")?;
+    prompt(uri, user_code);
+
+    let mut interval = Duration::from_secs(grant.get("interval").and_then(Value::as_u64).unwrap_or(5));
+    let expires = Duration::from_secs(grant.get("expires_in").and_then(Value::as_u64).unwrap_or(600));
+    let deadline = tokio::time::Instant::now() + expires;
+    let token_url = endpoint(&discovery, "token_endpoint")?;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(interval).await;
+        let reply: Value = client.post(token_url)
+            .form(&[("grant_type", DEVICE_GRANT), ("device_code", device_code), ("client_id", client_id)])
+            .send().await.map_err(|e| e.to_string())?
+            .json().await.map_err(|e| e.to_string())?;
+        if let Some(id_token) = reply.get("id_token").and_then(Value::as_str) {
+            return Ok(id_token.to_string());
+        }
+        match reply.get("error").and_then(Value::as_str) {
+            Some("authorization_pending") => {}
+            Some("slow_down") => interval += Duration::from_secs(5),
+            Some(error) => return Err(format!("sign-in failed: {}", error)),
+            None => return Err("token endpoint returned no id_token".into()),
+        }
+    }
+    Err("sign-in timed out".into())
+}
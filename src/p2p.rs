@@ -0,0 +1,174 @@
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::UdpSocket;
+use uuid::Uuid;
+
+use crate::time::{self, Clock};
+
+pub const USAGE: &str = "usage: morce p2p [--name NAME] [--room ROOM] [--port PORT] [--peer ADDR ...] [--no-broadcast]";
+
+const HELLO_EVERY: Duration = Duration::from_secs(10);
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+const SEEN_CAP: usize = 4096;
+const TTL: u64 = 4;
+
+#[derive(Clone)]
+pub struct Options {
+    pub name: String,
+    pub room: String,
+    pub port: u16,
+    pub peers: Vec<SocketAddr>,
+    pub broadcast: bool,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            name: std::env::var("USER").unwrap_or_else(|_| "anon".into()),
+            room: "lobby".into(),
+            port: 7878,
+            peers: Vec::new(),
+            broadcast: true,
+            clock: time::system(),
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--no-broadcast" {
+                options.broadcast = false;
+                continue;
+            }
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--name" => options.name = value,
+                "--room" => options.room = value,
+                "--port" => options.port = value.parse().map_err(|_| format!("bad port {}", value))?,
+                "--peer" => options.peers.push(value.parse().map_err(|_| format!("bad peer address {}", value))?),
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+struct Seen {
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+}
+
+impl Seen {
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        if self.order.len() > SEEN_CAP {
+            if let Some(old) = self.order.pop_front() {
+                self.ids.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+pub async fn run(options: Options) -> std::io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, options.port)).await?;
+    socket.set_broadcast(options.broadcast)?;
+    let mut peers: HashMap<SocketAddr, Instant> = HashMap::new();
+    let mut seen = Seen { order: VecDeque::new(), ids: HashSet::new() };
+    let node = Uuid::new_v4().to_string();
+    let hello = json!({"type": "hello", "name": options.name, "node": node}).to_string();
+    let broadcast = SocketAddr::from((Ipv4Addr::BROADCAST, options.port));
+
+    println!("p2p as {} in #{} on udp {}; type to send, Ctrl-D to quit", options.name, options.room, options.port);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut ticker = tokio::time::interval(HELLO_EVERY);
+    let mut buf = vec![0u8; 65536];
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                peers.retain(|addr, at| options.clock.since(*at) < PEER_TIMEOUT || options.peers.contains(addr));
+                let mut targets: Vec<SocketAddr> = options.peers.clone();
+                if options.broadcast {
+                    targets.push(broadcast);
+                }
+                for addr in targets {
+                    let _ = socket.send_to(hello.as_bytes(), addr).await;
+                }
+            }
+            line = lines.next_line() => {
+                let Some(text) = line? else {
+                    return Ok(());
+                };
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let id = Uuid::new_v4().to_string();
+                seen.insert(&id);
+                let msg = json!({"type": "msg", "id": id, "from": options.name, "room": options.room, "text": text, "ttl": TTL});
+                gossip(&socket, &peers, &options.peers, &msg, None).await;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (n, src) = received?;
+                let Ok(packet) = serde_json::from_slice::<Value>(&buf[..n]) else {
+                    continue;
+                };
+                let field = |key: &str| packet.get(key).and_then(Value::as_str).unwrap_or("");
+                match field("type") {
+                    "hello" if field("node") != node => {
+                        let new = peers.insert(src, options.clock.instant()).is_none();
+                        if new {
+                            println!("* {} joined from {}", field("name"), src);
+                            let _ = socket.send_to(hello.as_bytes(), src).await;
+                        }
+                    }
+                    "msg" => {
+                        peers.insert(src, options.clock.instant());
+                        if !seen.insert(field("id")) {
+                            continue;
+                        }
+                        if field("room") == options.room {
+                            println!("<{}> {}", field("from"), field("text"));
+                        }
+                        let ttl = packet.get("ttl").and_then(Value::as_u64).unwrap_or(0);
+                        if ttl > 0 {
+                            let mut relay = packet.clone();
+                            relay["ttl"] = json!(ttl - 1);
+                            gossip(&socket, &peers, &options.peers, &relay, Some(src)).await;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+async fn gossip(
+    socket: &UdpSocket,
+    peers: &HashMap<SocketAddr, Instant>,
+    bootstrap: &[SocketAddr],
+    msg: &Value,
+    skip: Option<SocketAddr>,
+) {
+    let payload = msg.to_string();
+    let targets: HashSet<&SocketAddr> = peers.keys().chain(bootstrap).collect();
+    for addr in targets.into_iter().filter(|a| Some(**a) != skip) {
+        let _ = socket.send_to(payload.as_bytes(), addr).await;
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+const HEADER: &str = "# morce client defaults; profiles and command-line flags override these\n";
+
+const CONFIG: &str = "\
+target = \"127.0.0.1:8080\"
+theme = \"green\"
+# room = \"general\"
+# name = \"alice\"
+# downloads = \"/path/to/downloads\"
+";
+
+pub struct Dirs {
+    pub config: PathBuf,
+    pub cache: PathBuf,
+    pub logs: PathBuf,
+    pub data: PathBuf,
+}
+
+fn env(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+fn home() -> PathBuf {
+    env("HOME").or_else(|| env("USERPROFILE")).unwrap_or_else(|| ".".into())
+}
+
+impl Dirs {
+    pub fn resolve() -> Dirs {
+        if let Some(root) = env("MORCE_HOME") {
+            return Dirs { config: root.clone(), cache: root.join("cache"), logs: root.join("logs"), data: root };
+        }
+        let home = home();
+        if cfg!(target_os = "macos") {
+            let support = home.join("Library/Application Support/morce");
+            return Dirs {
+                config: support.clone(),
+                cache: home.join("Library/Caches/morce"),
+                logs: home.join("Library/Logs/morce"),
+                data: support,
+            };
+        }
+        if cfg!(windows) {
+            let roaming = env("APPDATA").unwrap_or_else(|| home.join("AppData/Roaming")).join("morce");
+            let local = env("LOCALAPPDATA").unwrap_or_else(|| home.join("AppData/Local")).join("morce");
+            return Dirs { config: roaming, cache: local.join("cache"), logs: local.join("logs"), data: local };
+        }
+        let xdg = |name: &str, fallback: &str| env(name).unwrap_or_else(|| home.join(fallback)).join("morce");
+        Dirs {
+            config: xdg("XDG_CONFIG_HOME", ".config"),
+            cache: xdg("XDG_CACHE_HOME", ".cache"),
+            logs: xdg("XDG_STATE_HOME", ".local/state").join("logs"),
+            data: xdg("XDG_DATA_HOME", ".local/share"),
+        }
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config.join("config.toml")
+    }
+
+    pub fn profiles(&self) -> PathBuf {
+        self.config.join("profiles")
+    }
+
+    pub fn downloads(&self) -> PathBuf {
+        self.data.join("downloads")
+    }
+
+    pub fn keys(&self) -> PathBuf {
+        self.data.join("keys")
+    }
+
+    pub fn ensure(&self) -> Result<(), String> {
+        for dir in [&self.config, &self.cache, &self.logs, &self.data, &self.downloads(), &self.keys()] {
+            std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let config = self.config_file();
+        if !config.exists() {
+            std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&config)
+                .and_then(|mut file| file.write_all(format!("{}{}", HEADER, CONFIG).as_bytes()))
+                .map_err(|e| format!("{}: {}", config.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn read_config(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or(format!("line {}: expected key = \"value\"", n + 1))?;
+        let value = value.trim();
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+            .ok_or(format!("line {}: {} must be a quoted string", n + 1, key.trim()))?;
+        pairs.push((key.trim().to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")));
+    }
+    Ok(pairs)
+}
+
+pub fn write_config(pairs: &[(&str, &str)]) -> String {
+    let mut out = HEADER.to_string();
+    for (key, value) in pairs {
+        out.push_str(&format!("{} = \"{}\"\n", key, value.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+    out
+}
@@ -0,0 +1,148 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::predicate::Predicate;
+use crate::store::Store;
+
+const MAX_QUESTION: usize = 200;
+const MAX_OPTION: usize = 80;
+const MAX_OPTIONS: usize = 10;
+
+pub struct Polls {
+    open: DashMap<String, Uuid>,
+}
+
+impl Default for Polls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Polls {
+    pub fn new() -> Self {
+        Self {
+            open: DashMap::new(),
+        }
+    }
+}
+
+fn options(poll: &Entity) -> Vec<String> {
+    let mut list: Vec<(usize, String)> = poll.tags.iter()
+        .filter_map(|(k, v)| Some((k.strip_prefix("opt.")?.parse().ok()?, v.clone())))
+        .collect();
+    list.sort();
+    list.into_iter().map(|(_, v)| v).collect()
+}
+
+fn clean(text: &str, max: usize) -> String {
+    layout::truncate(&text.replace([',', '=', '\n'], " "), max)
+}
+
+fn render(poll: &Entity, votes: &[Entity]) -> String {
+    let question = poll.tags.get("question").map(String::as_str).unwrap_or("");
+    let mut out = question.to_string();
+    for (i, option) in options(poll).iter().enumerate() {
+        let n = (i + 1).to_string();
+        let count = votes.iter().filter(|v| v.tags.get("choice") == Some(&n)).count();
+        out.push_str(&format!("\n{}. {} — {}", n, option, count));
+    }
+    out
+}
+
+pub struct Open(pub Arc<Polls>);
+
+#[async_trait]
+impl Command for Open {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::Anyone, usage: "question | option | option ...", about: "help.poll" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let mut parts = args.split('|').map(str::trim).filter(|p| !p.is_empty());
+        let question = parts.next().ok_or(tr("poll.usage"))?;
+        let choices: Vec<&str> = parts.collect();
+        if choices.len() < 2 {
+            return Err(tr("poll.options").into());
+        }
+        if choices.len() > MAX_OPTIONS {
+            return Err(tr("poll.many").into());
+        }
+
+        let mut tags = BTreeMap::new();
+        tags.insert("kind".into(), "poll".into());
+        tags.insert("question".into(), clean(question, MAX_QUESTION));
+        for (i, choice) in choices.iter().enumerate() {
+            tags.insert(format!("opt.{}", i + 1), clean(choice, MAX_OPTION));
+        }
+        if let Some(room) = ctx.message.tags.get("in") {
+            tags.insert("in".into(), room.clone());
+        }
+        if let Some(actor) = ctx.actor {
+            tags.insert("from".into(), actor.to_string());
+        }
+
+        let store = &ctx.state.store;
+        let draft = store.create(Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+        let poll = store.update(draft.id, render(&draft, &[]).into(), draft.tags.clone()).await
+            .map_err(|e| e.to_string())?;
+        ctx.state.hub.publish(&poll);
+
+        let room = ctx.message.tags.get("in").cloned().unwrap_or_default();
+        self.0.open.insert(room, poll.id);
+        Ok(trf("poll.opened", &[&poll.id.to_string()]))
+    }
+}
+
+pub struct Vote(pub Arc<Polls>);
+
+#[async_trait]
+impl Command for Vote {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::User, usage: "<n>", about: "help.vote" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("vote.signin"))?;
+        let choice: usize = args.parse().map_err(|_| tr("vote.usage"))?;
+
+        let store = &ctx.state.store;
+        let room = ctx.message.tags.get("in").cloned().unwrap_or_default();
+        let target = ctx.message.tags.get("reply")
+            .and_then(|v| v.parse::<Uuid>().ok())
+            .or_else(|| self.0.open.get(&room).map(|id| *id))
+            .ok_or(tr("vote.nopoll"))?;
+        let poll = store.read(target).await.map_err(|e| e.to_string())?
+            .filter(|p| p.tags.get("kind").map(String::as_str) == Some("poll"))
+            .ok_or(tr("vote.nopoll"))?;
+        if choice == 0 || choice > options(&poll).len() {
+            return Err(tr("vote.option").into());
+        }
+
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "vote".into());
+        pred.insert("poll".into(), poll.id.to_string());
+        pred.insert("who".into(), actor.to_string());
+        let mut tags = pred.clone();
+        tags.insert("choice".into(), choice.to_string());
+        let previous = store.query(&pred).await.map_err(|e| e.to_string())?;
+        match previous.first() {
+            Some(vote) => store.update(vote.id, Bytes::new(), tags).await,
+            None => store.create(Bytes::new(), tags).await,
+        }.map_err(|e| e.to_string())?;
+
+        pred.remove("who");
+        let votes = store.query(&pred).await.map_err(|e| e.to_string())?;
+        let poll = store.update(poll.id, render(&poll, &votes).into(), poll.tags.clone()).await
+            .map_err(|e| e.to_string())?;
+        ctx.state.hub.publish(&poll);
+        Ok(trf("vote.recorded", &[&choice.to_string()]))
+    }
+}
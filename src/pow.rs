@@ -0,0 +1,86 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::time::Clock;
+use crate::window::Window;
+
+const CAPACITY: usize = 65_536;
+
+pub struct Pow {
+    issued: Window<String, ()>,
+    bits: u32,
+}
+
+impl Pow {
+    pub fn new(bits: u32, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            issued: Window::new(window, CAPACITY, clock),
+            bits,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.bits > 0
+    }
+
+    pub fn issue(&self) -> Option<String> {
+        let challenge = Uuid::new_v4().simple().to_string();
+        self.issued.insert(challenge.clone(), ()).then_some(challenge)
+    }
+
+    pub fn verify(&self, solution: &str) -> bool {
+        let Some((challenge, _)) = solution.split_once(':') else {
+            return false;
+        };
+        self.issued.take(&challenge.to_string()).is_some()
+            && leading_zeros(&Sha256::digest(solution.as_bytes())) >= self.bits
+    }
+
+    pub fn prune(&self) -> usize {
+        self.issued.sweep()
+    }
+}
+
+pub fn solve(challenge: &str, bits: u32) -> String {
+    (0u64..)
+        .map(|counter| format!("{}:{}", challenge, counter))
+        .find(|solution| leading_zeros(&Sha256::digest(solution.as_bytes())) >= bits)
+        .unwrap_or_default()
+}
+
+fn leading_zeros(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+pub fn solution(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get("x-pow").and_then(|v| v.to_str().ok());
+    if let Some(solution) = header {
+        return Some(solution.to_string());
+    }
+    req.query_string().split('&')
+        .find_map(|part| part.strip_prefix("pow="))
+        .map(str::to_string)
+}
+
+pub async fn challenge(state: web::Data<State>) -> HttpResponse {
+    if !state.pow.enabled() {
+        return HttpResponse::NoContent().finish();
+    }
+    match state.pow.issue() {
+        Some(challenge) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("{} {}\n", challenge, state.pow.bits)),
+        None => HttpResponse::ServiceUnavailable().body("too many open challenges, try again shortly"),
+    }
+}
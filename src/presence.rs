@@ -0,0 +1,334 @@
+use actix_web::web;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::command::{self, Category, Command, Commands, Context, Help, Role};
+use crate::entity::Entity;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::notify::{self, Setting};
+use crate::predicate::Predicate;
+use crate::room;
+use crate::store::Store;
+use crate::task;
+use crate::time::{self, Clock};
+
+const AWAY_REPLY_EVERY: Duration = Duration::from_secs(10 * 60);
+const MAX_NOTE: usize = 100;
+
+pub struct Socket {
+    pub user: Uuid,
+    pub login: Option<Uuid>,
+    pub ip: Option<IpAddr>,
+    pub last_read: Arc<AtomicU64>,
+    pub session: Mutex<actix_ws::Session>,
+    pub reaped: Arc<Notify>,
+}
+
+pub struct Online {
+    started: Instant,
+    links: DashMap<Uuid, usize>,
+    left: DashMap<Uuid, Instant>,
+    sockets: DashMap<Uuid, Socket>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Online {
+    fn default() -> Self {
+        Self::new(time::system())
+    }
+}
+
+impl Online {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { started: clock.instant(), links: DashMap::new(), left: DashMap::new(), sockets: DashMap::new(), clock }
+    }
+
+    pub fn join(&self, id: Uuid, socket: Socket) {
+        self.arrive(socket.user);
+        self.sockets.insert(id, socket);
+    }
+
+    pub fn leave(&self, id: Uuid) {
+        if let Some((_, socket)) = self.sockets.remove(&id) {
+            self.depart(socket.user);
+        }
+    }
+
+    pub async fn reap(&self, user: Uuid, ip: IpAddr, window: Duration, except: Option<Uuid>) -> usize {
+        let suspects: Vec<(Uuid, u64, actix_ws::Session)> = self.sockets.iter()
+            .filter(|s| s.user == user && s.ip == Some(ip) && Some(*s.key()) != except)
+            .map(|s| (*s.key(), s.last_read.load(Ordering::Relaxed), s.session.lock().unwrap().clone()))
+            .collect();
+        if suspects.is_empty() {
+            return 0;
+        }
+        for (_, _, session) in &suspects {
+            let _ = tokio::time::timeout(window, session.clone().ping(b"")).await;
+        }
+        tokio::time::sleep(window).await;
+        let mut reaped = 0;
+        for (id, read, _) in suspects {
+            let silent = self.sockets.get(&id).is_some_and(|s| s.last_read.load(Ordering::Relaxed) == read);
+            if silent {
+                if let Some((_, socket)) = self.sockets.remove(&id) {
+                    socket.reaped.notify_one();
+                    self.depart(socket.user);
+                    reaped += 1;
+                }
+            }
+        }
+        reaped
+    }
+
+    fn arrive(&self, user: Uuid) {
+        *self.links.entry(user).or_default() += 1;
+    }
+
+    fn depart(&self, user: Uuid) {
+        let gone = match self.links.get_mut(&user) {
+            Some(mut count) => {
+                *count = count.saturating_sub(1);
+                *count == 0
+            }
+            None => false,
+        };
+        if gone {
+            self.links.remove_if(&user, |_, count| *count == 0);
+            self.left.insert(user, self.clock.instant());
+        }
+    }
+
+    pub fn is_online(&self, user: Uuid) -> bool {
+        self.links.contains_key(&user)
+    }
+
+    pub fn logins(&self) -> Vec<(Uuid, Option<Uuid>)> {
+        self.sockets.iter().map(|s| (s.user, s.login)).collect()
+    }
+
+    pub fn away_for(&self, user: Uuid) -> Duration {
+        if self.is_online(user) {
+            return Duration::ZERO;
+        }
+        self.clock.since(self.left.get(&user).map_or(self.started, |at| *at))
+    }
+}
+
+pub async fn display_name(store: &dyn Store, id: &str) -> String {
+    let user = match id.parse::<Uuid>() {
+        Ok(id) => store.read(id).await.ok().flatten(),
+        Err(_) => None,
+    };
+    user.and_then(|u| u.tags.get("name").cloned()).unwrap_or_else(|| id.to_string())
+}
+
+pub fn mentions(text: &str) -> BTreeSet<&str> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|word| word.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_' && c != '-'))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+pub async fn recipients(state: &State, entity: &Entity, text: &str) -> BTreeSet<Uuid> {
+    let mut found = BTreeSet::new();
+    if let Some(to) = entity.tags.get("to").and_then(|v| v.parse().ok()) {
+        found.insert(to);
+    }
+    let Some(room) = entity.tags.get("in").and_then(|v| v.parse::<Uuid>().ok()) else {
+        return found;
+    };
+    let named = mentions(text);
+    let levels = notify::levels(&*state.store, room).await;
+    if named.is_empty() && !levels.values().any(|&level| level == Setting::All) {
+        return found;
+    }
+    for member in room::members(&*state.store, room).await {
+        let Ok(id) = member.parse::<Uuid>() else {
+            continue;
+        };
+        match levels.get(&member).copied().unwrap_or_default() {
+            Setting::Mute => continue,
+            Setting::All => {
+                found.insert(id);
+                continue;
+            }
+            Setting::Mentions if named.is_empty() => continue,
+            Setting::Mentions => {}
+        }
+        let Ok(Some(user)) = state.store.read(id).await else {
+            continue;
+        };
+        if user.tags.get("name").is_some_and(|n| named.contains(n.as_str())) {
+            found.insert(id);
+        }
+    }
+    found
+}
+
+async fn answer_away(state: &State, replied: &mut HashMap<(Uuid, Uuid), Instant>, entity: &Entity) {
+    if entity.tags.contains_key("in") || Commands::parse(&entity.load).is_some() {
+        return;
+    }
+    let Some(sender) = entity.tags.get("from").and_then(|v| v.parse::<Uuid>().ok()) else {
+        return;
+    };
+    let Some(to) = entity.tags.get("to").and_then(|v| v.parse::<Uuid>().ok()).filter(|&to| to != sender) else {
+        return;
+    };
+    let Ok(Some(user)) = state.store.read(to).await else {
+        return;
+    };
+    let Some(message) = user.tags.get("away") else {
+        return;
+    };
+    replied.retain(|_, at| state.clock.since(*at) < AWAY_REPLY_EVERY);
+    if replied.insert((to, sender), state.clock.instant()).is_some() {
+        return;
+    }
+    let name = user.tags.get("name").cloned().unwrap_or_else(|| to.to_string());
+    command::ephemeral(state, sender, Some(entity), &trf("away.reply", &[&name, message])).await;
+}
+
+pub fn start(state: web::Data<State>) {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    let (_, mut rx) = state.hub.subscribe(pred);
+    task::spawn("away", async move {
+        let mut replied = HashMap::new();
+        while let Some(entity) = rx.recv().await {
+            answer_away(&state, &mut replied, &entity).await;
+        }
+    });
+}
+
+pub async fn status(state: &State, user: Uuid) -> Option<String> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "session".into());
+    pred.insert("actor".into(), user.to_string());
+    let mut sessions = state.store.query(&pred).await.ok()?;
+    sessions.retain(|s| s.tags.contains_key("status"));
+    sessions.sort_by_key(|s| s.at);
+    sessions.pop()?.tags.remove("status")
+}
+
+fn note(text: &str) -> String {
+    layout::truncate(text.replace([',', '=', '\n'], " ").trim(), MAX_NOTE)
+}
+
+pub struct Status;
+
+#[async_trait]
+impl Command for Status {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::User, usage: "[text]", about: "help.status" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let login = ctx.actor.and(ctx.session).ok_or(tr("status.signin"))?;
+        let store = &ctx.state.store;
+        let session = store.read(login).await.map_err(|e| e.to_string())?
+            .ok_or(tr("status.nosession"))?;
+
+        let text = note(args);
+        let mut tags = session.tags.clone();
+        if text.is_empty() {
+            tags.remove("status");
+        } else {
+            tags.insert("status".into(), text.clone());
+        }
+        store.update(session.id, session.load.clone(), tags).await.map_err(|e| e.to_string())?;
+
+        Ok(match text.as_str() {
+            "" => tr("status.cleared").into(),
+            text => trf("status.set", &[text]),
+        })
+    }
+}
+
+pub struct Away;
+
+#[async_trait]
+impl Command for Away {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::User, usage: "[message]", about: "help.away" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("status.signin"))?;
+        let store = &ctx.state.store;
+        let user = store.read(actor).await.map_err(|e| e.to_string())?
+            .ok_or(tr("status.nouser"))?;
+
+        let text = note(args);
+        let mut tags = user.tags.clone();
+        if text.is_empty() {
+            tags.remove("away");
+        } else {
+            tags.insert("away".into(), text.clone());
+        }
+        let user = store.update(user.id, user.load.clone(), tags).await.map_err(|e| e.to_string())?;
+        ctx.state.hub.publish(&user);
+
+        Ok(match text.as_str() {
+            "" => tr("away.back").into(),
+            text => trf("away.set", &[text]),
+        })
+    }
+}
+
+pub struct Who;
+
+#[async_trait]
+impl Command for Who {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::Anyone, usage: "", about: "help.who" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        let store = &ctx.state.store;
+        let mut statuses: BTreeMap<Uuid, BTreeSet<String>> = BTreeMap::new();
+        for (user, login) in ctx.state.online.logins() {
+            let found = statuses.entry(user).or_default();
+            let Some(login) = login else {
+                continue;
+            };
+            if let Some(status) = store.read(login).await.map_err(|e| e.to_string())?.and_then(|s| s.tags.get("status").cloned()) {
+                found.insert(status);
+            }
+        }
+
+        let mut rows = Vec::new();
+        for (actor, status) in statuses {
+            if !ctx.state.online.is_online(actor) {
+                continue;
+            }
+            let Some(user) = store.read(actor).await.map_err(|e| e.to_string())? else {
+                continue;
+            };
+            let mut name = user.tags.get("name").cloned().unwrap_or_else(|| user.id.to_string());
+            if user.tags.get("registered").map(String::as_str) != Some("true") {
+                name.push_str(tr("who.guest"));
+            }
+            if user.tags.contains_key("away") {
+                name.push_str(tr("who.away"));
+            }
+            let status = status.into_iter().collect::<Vec<_>>().join("; ");
+            rows.push(vec![layout::truncate(&name, 24), layout::truncate(&status, 40)]);
+        }
+        if rows.is_empty() {
+            return Ok(tr("who.empty").into());
+        }
+        rows.sort();
+        Ok(layout::table(&[tr("who.name"), tr("who.status")], &rows))
+    }
+}
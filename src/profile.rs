@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use crate::layout;
+use crate::parse;
+use crate::paths::{self, Dirs};
+
+pub const USAGE: &str = "usage: morce profile add NAME [--target ADDR] [--name NAME] [--token ID] [--oidc-issuer URL --oidc-client ID] [--room ROOM] [--theme THEME] [--downloads DIR] | list | remove NAME";
+
+const FIELDS: [&str; 8] = ["target", "name", "token", "oidc-issuer", "oidc-client", "room", "theme", "downloads"];
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    fields: BTreeMap<String, String>,
+}
+
+impl Profile {
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).map(String::as_str)
+    }
+
+    pub fn set(&mut self, flag: &str, value: String) -> Result<(), String> {
+        let field = flag.trim_start_matches("--");
+        if !flag.starts_with("--") || !FIELDS.contains(&field) {
+            return Err(format!("unknown flag {}", flag));
+        }
+        if value.contains([',', '\n']) {
+            return Err(format!("{} cannot contain commas or newlines", flag));
+        }
+        self.fields.insert(field.to_string(), value);
+        Ok(())
+    }
+
+    pub fn merge(mut self, over: Profile) -> Profile {
+        self.fields.extend(over.fields);
+        self
+    }
+
+    pub fn from_flags<I: IntoIterator<Item = String>>(args: I) -> Result<(Vec<String>, Profile), String> {
+        let (mut profile, mut chosen) = (Profile::default(), Vec::new());
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--profile" => chosen.push(value),
+                _ => profile.set(&flag, value)?,
+            }
+        }
+        Ok((chosen, profile))
+    }
+}
+
+fn path() -> PathBuf {
+    Dirs::resolve().profiles()
+}
+
+pub fn defaults() -> Result<Profile, String> {
+    let dirs = Dirs::resolve();
+    dirs.ensure()?;
+    let file = dirs.config_file();
+    let raw = std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let mut profile = Profile::default();
+    for (key, value) in paths::read_config(&raw).map_err(|e| format!("{}: {}", file.display(), e))? {
+        profile.set(&format!("--{}", key), value).map_err(|e| format!("{}: {}", file.display(), e))?;
+    }
+    Ok(profile)
+}
+
+pub fn save_defaults(profile: &Profile) -> Result<PathBuf, String> {
+    let dirs = Dirs::resolve();
+    dirs.ensure()?;
+    let file = dirs.config_file();
+    let pairs: Vec<(&str, &str)> = FIELDS.iter().filter_map(|f| profile.get(f).map(|v| (*f, v))).collect();
+    let fail = |err: std::io::Error| format!("{}: {}", file.display(), err);
+    let mut out = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600)
+        .open(&file).map_err(fail)?;
+    out.write_all(paths::write_config(&pairs).as_bytes()).map_err(fail)?;
+    Ok(file)
+}
+
+fn load_all() -> Result<BTreeMap<String, Profile>, String> {
+    let raw = match std::fs::read_to_string(path()) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(format!("{}: {}", path().display(), err)),
+    };
+    Ok(raw.lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, fields)| (name.to_string(), Profile { fields: parse::tags(fields) }))
+        .collect())
+}
+
+fn save_all(profiles: &BTreeMap<String, Profile>) -> Result<(), String> {
+    let path = path();
+    let fail = |err: std::io::Error| format!("{}: {}", path.display(), err);
+    Dirs::resolve().ensure()?;
+    let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600)
+        .open(&path).map_err(fail)?;
+    for (name, profile) in profiles {
+        let fields: Vec<String> = profile.fields.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        writeln!(file, "{} {}", name, fields.join(",")).map_err(fail)?;
+    }
+    Ok(())
+}
+
+pub fn load(name: &str) -> Result<Profile, String> {
+    load_all()?.remove(name).ok_or(format!("no profile named {}, see morce profile list", name))
+}
+
+pub fn resolve(chosen: Option<String>, flags: Profile) -> Result<Profile, String> {
+    let base = match chosen {
+        Some(name) => load(&name)?,
+        None => load_all()?.remove("default").unwrap_or_default(),
+    };
+    Ok(defaults()?.merge(base).merge(flags))
+}
+
+pub fn run<I: IntoIterator<Item = String>>(args: I) -> Result<String, String> {
+    let mut args = args.into_iter();
+    Dirs::resolve().ensure()?;
+    let mut profiles = load_all()?;
+    match args.next().as_deref() {
+        Some("add") => {
+            let name = args.next().filter(|n| !n.starts_with('-') && !n.contains(char::is_whitespace))
+                .ok_or(USAGE)?;
+            let (_, flags) = Profile::from_flags(args).map_err(|err| format!("{}\n{}", err, USAGE))?;
+            let updated = profiles.contains_key(&name);
+            let profile = profiles.remove(&name).unwrap_or_default().merge(flags);
+            profiles.insert(name.clone(), profile);
+            save_all(&profiles)?;
+            Ok(format!("{} profile {}", if updated { "updated" } else { "added" }, name))
+        }
+        Some("list") => {
+            if profiles.is_empty() {
+                return Ok("no profiles".into());
+            }
+            let rows: Vec<Vec<String>> = profiles.iter().map(|(name, p)| {
+                let mut row = vec![name.clone()];
+                row.extend(FIELDS.iter().map(|field| match (*field, p.get(field)) {
+                    ("token", Some(token)) => format!("{}…", token.chars().take(8).collect::<String>()),
+                    (_, value) => value.unwrap_or("-").to_string(),
+                }));
+                row
+            }).collect();
+            Ok(layout::table(&["PROFILE", "TARGET", "NAME", "TOKEN", "ROOM", "THEME", "DOWNLOADS"], &rows))
+        }
+        Some("remove") => {
+            let name = args.next().ok_or(USAGE)?;
+            profiles.remove(&name).ok_or(format!("no profile named {}", name))?;
+            save_all(&profiles)?;
+            Ok(format!("removed profile {}", name))
+        }
+        _ => Err(USAGE.into()),
+    }
+}
@@ -0,0 +1,241 @@
+use actix_web::web;
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::account;
+use crate::api::State;
+use crate::command::{Category, Command, Commands, Context, Help, Role};
+use crate::entity::Entity;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::presence;
+use crate::room;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BODY: usize = 280;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    Ntfy,
+    Gotify,
+}
+
+impl Service {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "ntfy" => Some(Service::Ntfy),
+            "gotify" => Some(Service::Gotify),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Service::Ntfy => "ntfy",
+            Service::Gotify => "gotify",
+        })
+    }
+}
+
+struct Target {
+    service: Service,
+    url: String,
+    token: Option<String>,
+}
+
+impl Target {
+    fn from_entity(entity: &Entity) -> Option<Target> {
+        let service = Service::parse(entity.tags.get("service")?)?;
+        let load = String::from_utf8_lossy(&entity.load);
+        let mut lines = load.lines();
+        let url = lines.next()?.to_string();
+        let token = lines.next().filter(|t| !t.is_empty()).map(String::from);
+        Some(Target { service, url, token })
+    }
+}
+
+pub struct Relay {
+    client: reqwest::Client,
+    hosts: Vec<String>,
+}
+
+impl Relay {
+    pub fn new(hosts: Vec<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(SEND_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            hosts,
+        }
+    }
+
+    fn check_url(&self, raw: &str) -> Result<String, String> {
+        let url = reqwest::Url::parse(raw).map_err(|_| trf("push.badurl", &[&layout::truncate(raw, 64)]))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(trf("push.badurl", &[&layout::truncate(raw, 64)]));
+        }
+        let host = url.host_str().unwrap_or_default();
+        if !self.hosts.is_empty() && !self.hosts.iter().any(|h| h == host) {
+            return Err(trf("push.host", &[host, &self.hosts.join(", ")]));
+        }
+        Ok(url.as_str().trim_end_matches('/').to_string())
+    }
+
+    async fn send(&self, target: &Target, title: &str, body: &str) -> Result<(), String> {
+        let request = match target.service {
+            Service::Ntfy => {
+                let mut request = self.client.post(&target.url)
+                    .header("Title", title)
+                    .header("Tags", "speech_balloon")
+                    .body(body.to_string());
+                if let Some(token) = &target.token {
+                    request = request.bearer_auth(token);
+                }
+                request
+            }
+            Service::Gotify => self.client.post(format!("{}/message", target.url))
+                .header("X-Gotify-Key", target.token.as_deref().unwrap_or_default())
+                .json(&json!({ "title": title, "message": body, "priority": 5 })),
+        };
+        let response = request.send().await.map_err(|e| e.without_url().to_string())?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(format!("{} answered {}", target.service, status)),
+        }
+    }
+}
+
+async fn target(store: &dyn Store, user: Uuid) -> Option<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "push".into());
+    pred.insert("user".into(), user.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+async fn notify(state: &State, push: &Relay, entity: &Entity) {
+    let from = entity.tags.get("from").cloned().unwrap_or_default();
+    if from == "system" || Commands::parse(&entity.load).is_some() {
+        return;
+    }
+    let text = String::from_utf8_lossy(&entity.load);
+    let mut targets = Vec::new();
+    for user in presence::recipients(state, entity, &text).await {
+        if from == user.to_string() || state.online.is_online(user) {
+            continue;
+        }
+        if let Some(target) = target(&*state.store, user).await.as_ref().and_then(Target::from_entity) {
+            targets.push((user, target));
+        }
+    }
+    if targets.is_empty() {
+        return;
+    }
+    let sender = presence::display_name(&*state.store, &from).await;
+    let room = match entity.tags.get("in") {
+        Some(room) => Some(room::find(&*state.store, room).await
+            .and_then(|c| c.tags.get("name").cloned())
+            .unwrap_or_else(|| room.clone())),
+        None => None,
+    };
+    let named = presence::mentions(&text);
+    let body = layout::truncate(&text, MAX_BODY);
+    for (user, target) in targets {
+        let name = presence::display_name(&*state.store, &user.to_string()).await;
+        let title = match &room {
+            Some(room) if named.contains(name.as_str()) => trf("push.mention", &[&sender, room]),
+            Some(room) => trf("push.room", &[&sender, room]),
+            None => trf("push.direct", &[&sender]),
+        };
+        if let Err(err) = push.send(&target, &title, &body).await {
+            xlog!(Level::Warn, target: "push", "{} notification for {} failed: {}", target.service, user, err);
+        }
+    }
+}
+
+pub fn start(state: web::Data<State>) {
+    let Some(push) = state.push.clone() else {
+        return;
+    };
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    let (_, mut rx) = state.hub.subscribe(pred);
+    task::spawn("push", async move {
+        while let Some(entity) = rx.recv().await {
+            notify(&state, &push, &entity).await;
+        }
+    });
+}
+
+pub struct Push;
+
+#[async_trait]
+impl Command for Push {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "[ntfy <url> [token]|gotify <url> <token>|test|off]", about: "help.push" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("push.signin"))?;
+        let Some(push) = &ctx.state.push else {
+            return Err(tr("push.disabled").into());
+        };
+        let store = &*ctx.state.store;
+        let current = target(store, actor).await;
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match (words.as_slice(), current) {
+            ([], None) => Ok(tr("push.none").into()),
+            ([], Some(c)) => match Target::from_entity(&c) {
+                Some(t) => Ok(trf("push.show", &[&t.service.to_string(), &t.url])),
+                None => Ok(tr("push.none").into()),
+            },
+            (["off" | "test"], None) => Err(tr("push.none").into()),
+            (["off"], Some(c)) => {
+                store.delete(c.id).await.map_err(|e| e.to_string())?;
+                Ok(tr("push.removed").into())
+            }
+            (["test"], Some(c)) => {
+                let t = Target::from_entity(&c).ok_or(tr("push.none"))?;
+                push.send(&t, tr("push.title"), tr("push.test")).await.map_err(|e| trf("push.failed", &[&e]))?;
+                Ok(trf("push.sent", &[&t.service.to_string()]))
+            }
+            ([service, url, rest @ ..], current) if rest.len() <= 1 => {
+                let service = Service::parse(service).ok_or(tr("push.usage"))?;
+                let token = rest.first().copied();
+                if service == Service::Gotify && token.is_none() {
+                    return Err(tr("push.token").into());
+                }
+                if !account::registered(store, actor).await {
+                    return Err(tr("push.register").into());
+                }
+                let url = push.check_url(url)?;
+                let mut tags = BTreeMap::new();
+                tags.insert("kind".into(), "push".into());
+                tags.insert("user".into(), actor.to_string());
+                tags.insert("service".into(), service.to_string());
+                let load = bytes::Bytes::from(format!("{}\n{}", url, token.unwrap_or_default()));
+                match current {
+                    Some(c) => store.update(c.id, load, tags).await,
+                    None => store.create(load, tags).await,
+                }.map_err(|e| e.to_string())?;
+                Ok(trf("push.set", &[&service.to_string(), &url]))
+            }
+            _ => Err(tr("push.usage").into()),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+const QUIET: usize = 4;
+
+const BLOCKS: [(usize, usize, usize, usize, usize); 10] = [
+    (10, 1, 16, 0, 0),
+    (16, 1, 28, 0, 0),
+    (26, 1, 44, 0, 0),
+    (18, 2, 32, 0, 0),
+    (24, 2, 43, 0, 0),
+    (16, 4, 27, 0, 0),
+    (18, 4, 31, 0, 0),
+    (22, 2, 38, 2, 39),
+    (22, 3, 36, 2, 37),
+    (26, 4, 43, 1, 44),
+];
+
+const ALIGNMENT: [&[usize]; 10] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+    &[6, 28, 50],
+];
+
+pub struct Qr {
+    size: usize,
+    dark: Vec<bool>,
+    function: Vec<bool>,
+}
+
+fn mul(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11d);
+        z ^= ((y as u16 >> i) & 1) * x as u16;
+    }
+    z as u8
+}
+
+fn divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = mul(root, 2);
+    }
+    result
+}
+
+fn remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result.remove(0);
+        result.push(0);
+        for (r, d) in result.iter_mut().zip(divisor) {
+            *r ^= mul(*d, factor);
+        }
+    }
+    result
+}
+
+fn codewords(data: &[u8], version: usize) -> Vec<u8> {
+    let (ec, short, short_len, long, long_len) = BLOCKS[version - 1];
+    let capacity = short * short_len + long * long_len;
+    let mut bits: Vec<bool> = Vec::new();
+    let mut push = |value: usize, len: usize| (0..len).rev().for_each(|i| bits.push((value >> i) & 1 == 1));
+    push(0b0100, 4);
+    push(data.len(), if version < 10 { 8 } else { 16 });
+    data.iter().for_each(|&b| push(b as usize, 8));
+    let terminator = (capacity * 8 - bits.len()).min(4);
+    bits.extend(std::iter::repeat_n(false, terminator));
+    bits.extend(std::iter::repeat_n(false, (8 - bits.len() % 8) % 8));
+    let mut bytes: Vec<u8> = bits.chunks(8).map(|c| c.iter().fold(0u8, |acc, &b| (acc << 1) | b as u8)).collect();
+    for pad in [0xec, 0x11].into_iter().cycle() {
+        if bytes.len() >= capacity {
+            break;
+        }
+        bytes.push(pad);
+    }
+
+    let divisor = divisor(ec);
+    let mut blocks = Vec::new();
+    let mut at = 0;
+    for len in std::iter::repeat_n(short_len, short).chain(std::iter::repeat_n(long_len, long)) {
+        let block = &bytes[at..at + len];
+        blocks.push((block, remainder(block, &divisor)));
+        at += len;
+    }
+    let mut out = Vec::new();
+    for i in 0..short_len.max(long_len) {
+        out.extend(blocks.iter().filter_map(|(block, _)| block.get(i)));
+    }
+    for i in 0..ec {
+        out.extend(blocks.iter().map(|(_, ecc)| ecc[i]));
+    }
+    out
+}
+
+impl Qr {
+    pub fn encode(data: &[u8]) -> Option<Qr> {
+        let version = (1..=BLOCKS.len()).find(|&v| {
+            let (_, short, short_len, long, long_len) = BLOCKS[v - 1];
+            let header = 4 + if v < 10 { 8 } else { 16 };
+            header + data.len() * 8 <= (short * short_len + long * long_len) * 8
+        })?;
+        let size = version * 4 + 17;
+        let mut qr = Qr { size, dark: vec![false; size * size], function: vec![false; size * size] };
+        qr.draw_patterns(version);
+        qr.draw_codewords(&codewords(data, version));
+        let mask = (0..8).min_by_key(|&mask| {
+            qr.apply_mask(mask);
+            qr.draw_format(mask);
+            let penalty = qr.penalty();
+            qr.apply_mask(mask);
+            penalty
+        })?;
+        qr.apply_mask(mask);
+        qr.draw_format(mask);
+        Some(qr)
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.dark[y * self.size + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        self.dark[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn draw_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set(6, i, i % 2 == 0);
+            self.set(i, 6, i % 2 == 0);
+        }
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if (0..size as i32).contains(&x) && (0..size as i32).contains(&y) {
+                        let dist = dx.abs().max(dy.abs());
+                        self.set(x as usize, y as usize, dist != 2 && dist != 4);
+                    }
+                }
+            }
+        }
+        let positions = ALIGNMENT[version - 1];
+        let last = positions.len().saturating_sub(1);
+        for (i, &cx) in positions.iter().enumerate() {
+            for (j, &cy) in positions.iter().enumerate() {
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        self.set((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dx.abs().max(dy.abs()) != 1);
+                    }
+                }
+            }
+        }
+        self.draw_format(0);
+        if version >= 7 {
+            let mut rem = version;
+            for _ in 0..12 {
+                rem = (rem << 1) ^ ((rem >> 11) * 0x1f25);
+            }
+            let bits = (version << 12) | rem;
+            for i in 0..18 {
+                let bit = (bits >> i) & 1 == 1;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set(a, b, bit);
+                self.set(b, a, bit);
+            }
+        }
+    }
+
+    fn draw_format(&mut self, mask: usize) {
+        let data = mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        let size = self.size;
+        for i in 0..=5 {
+            self.set(8, i, bit(i));
+        }
+        self.set(8, 7, bit(6));
+        self.set(8, 8, bit(7));
+        self.set(7, 8, bit(8));
+        for i in 9..15 {
+            self.set(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set(8, size - 15 + i, bit(i));
+        }
+        self.set(8, size - 8, true);
+    }
+
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.function[y * size + x] && i < data.len() * 8 {
+                        self.dark[y * size + x] = (data[i >> 3] >> (7 - (i & 7))) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: usize) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let at = y * self.size + x;
+                if flip && !self.function[at] {
+                    self.dark[at] ^= true;
+                }
+            }
+        }
+    }
+
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let line = |i: usize, j: usize, rows: bool| if rows { self.get(j, i) } else { self.get(i, j) };
+        let finder = [true, false, true, true, true, false, true, false, false, false, false];
+        let mut penalty = 0;
+        for rows in [true, false] {
+            for i in 0..size {
+                let mut run = 1;
+                for j in 1..size {
+                    if line(i, j, rows) == line(i, j - 1, rows) {
+                        run += 1;
+                        if run == 5 {
+                            penalty += 3;
+                        } else if run > 5 {
+                            penalty += 1;
+                        }
+                    } else {
+                        run = 1;
+                    }
+                }
+                for j in 0..size.saturating_sub(finder.len() - 1) {
+                    let forward = finder.iter().enumerate().all(|(k, &d)| line(i, j + k, rows) == d);
+                    let backward = finder.iter().rev().enumerate().all(|(k, &d)| line(i, j + k, rows) == d);
+                    penalty += 40 * (forward as usize + backward as usize);
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.get(x, y);
+                if c == self.get(x + 1, y) && c == self.get(x, y + 1) && c == self.get(x + 1, y + 1) {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.dark.iter().filter(|&&d| d).count();
+        let percent = dark * 100 / (size * size);
+        penalty + percent.abs_diff(50) / 5 * 10
+    }
+
+    pub fn render(&self) -> String {
+        let span = self.size + QUIET * 2;
+        let light = |x: usize, y: usize| {
+            let inside = (QUIET..QUIET + self.size).contains(&x) && (QUIET..QUIET + self.size).contains(&y);
+            !inside || !self.get(x - QUIET, y - QUIET)
+        };
+        let mut out = Vec::new();
+        for y in (0..span).step_by(2) {
+            let row: String = (0..span).map(|x| match (light(x, y), y + 1 < span && light(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            }).collect();
+            out.push(row);
+        }
+        out.join("\n")
+    }
+}
+
+pub fn render(text: &str) -> Option<String> {
+    Qr::encode(text.as_bytes()).map(|qr| qr.render())
+}
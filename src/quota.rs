@@ -0,0 +1,169 @@
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::command::{self, Category, Command, Context, Help, Role};
+use crate::guard;
+use crate::locale::{tr, trf};
+use crate::time::Clock;
+
+const WARN_AT: f64 = 0.8;
+const ANONYMOUS: &str = "anonymous";
+
+pub fn parse_size(raw: &str) -> Option<u64> {
+    let raw = raw.trim().to_ascii_lowercase();
+    let split = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    let (count, unit) = raw.split_at(split);
+    let unit: u64 = match unit.trim_end_matches("ib").trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => return None,
+    };
+    count.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+pub fn size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GiB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Usage {
+    day: u64,
+    bytes: u64,
+    files: u64,
+    warned: bool,
+}
+
+pub enum Charge {
+    Ok,
+    Warn(u64, u64),
+    Over(u64, u64),
+}
+
+pub struct Quota {
+    daily: u64,
+    files: u64,
+    used: DashMap<String, Usage>,
+    clock: Arc<dyn Clock>,
+}
+
+fn today(clock: &dyn Clock) -> u64 {
+    clock.unix().as_secs() / 86400
+}
+
+impl Quota {
+    pub fn new(daily: u64, files: u64, clock: Arc<dyn Clock>) -> Self {
+        Self { daily, files, used: DashMap::new(), clock }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.daily > 0 || self.files > 0
+    }
+
+    fn usage(&self, who: &str) -> Usage {
+        self.used.get(who).map(|u| *u).filter(|u| u.day == today(&*self.clock)).unwrap_or_default()
+    }
+
+    pub fn charge(&self, who: &str, bytes: u64, file: bool) -> Charge {
+        if !self.used.contains_key(who) && self.used.len().is_multiple_of(1024) {
+            self.prune();
+        }
+        let mut usage = self.used.entry(who.to_string()).or_default();
+        let day = today(&*self.clock);
+        if usage.day != day {
+            *usage = Usage { day, ..Usage::default() };
+        }
+        let bytes_after = usage.bytes + bytes;
+        let files_after = usage.files + if file { bytes } else { 0 };
+        if self.daily > 0 && bytes_after > self.daily {
+            return Charge::Over(usage.bytes, self.daily);
+        }
+        if file && self.files > 0 && files_after > self.files {
+            return Charge::Over(usage.files, self.files);
+        }
+        usage.bytes = bytes_after;
+        usage.files = files_after;
+
+        let over = |used: u64, limit: u64| limit > 0 && used as f64 >= limit as f64 * WARN_AT;
+        let warning = if over(usage.bytes, self.daily) {
+            Some((usage.bytes, self.daily))
+        } else if over(usage.files, self.files) {
+            Some((usage.files, self.files))
+        } else {
+            None
+        };
+        match warning {
+            Some((used, limit)) if !usage.warned => {
+                usage.warned = true;
+                Charge::Warn(used, limit)
+            }
+            _ => Charge::Ok,
+        }
+    }
+
+    fn prune(&self) {
+        let day = today(&*self.clock);
+        self.used.retain(|_, usage| usage.day == day);
+    }
+}
+
+pub async fn enforce(
+    state: &State,
+    actor: Option<Uuid>,
+    peer: Option<String>,
+    tags: &BTreeMap<String, String>,
+    bytes: usize,
+) -> actix_web::Result<()> {
+    if !state.quota.enabled() {
+        return Ok(());
+    }
+    if let Some(actor) = actor {
+        if guard::is_admin(&*state.store, actor).await {
+            return Ok(());
+        }
+    }
+    let who = actor.map(|a| a.to_string()).or(peer).unwrap_or_else(|| ANONYMOUS.into());
+    let file = tags.get("kind").map(String::as_str) == Some("file") || tags.contains_key("filename");
+    match state.quota.charge(&who, bytes as u64, file) {
+        Charge::Ok => Ok(()),
+        Charge::Warn(used, limit) => {
+            if let Some(actor) = actor {
+                command::ephemeral(state, actor, None, &trf("quota.warn", &[&size(used), &size(limit)])).await;
+            }
+            Ok(())
+        }
+        Charge::Over(used, limit) => Err(actix_web::error::ErrorTooManyRequests(
+            format!("daily quota exceeded: {} of {} used", size(used), size(limit)),
+        )),
+    }
+}
+
+pub struct Show;
+
+#[async_trait]
+impl Command for Show {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "", about: "help.quota" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("quota.signin"))?;
+        let quota = &ctx.state.quota;
+        if !quota.enabled() {
+            return Ok(tr("quota.off").into());
+        }
+        let usage = quota.usage(&actor.to_string());
+        let limit = |limit: u64| if limit > 0 { size(limit) } else { tr("quota.unlimited").into() };
+        Ok(trf("quota.usage", &[&size(usage.bytes), &limit(quota.daily), &size(usage.files), &limit(quota.files)]))
+    }
+}
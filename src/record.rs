@@ -0,0 +1,424 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::StreamExt;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::WriteHalf;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::client;
+use crate::layout;
+use crate::log::Level;
+use crate::task;
+use crate::time::{self, Clock};
+use crate::transport::{Tcp, Wire};
+use crate::xlog;
+
+pub const USAGE: &str = "usage: morce playback FILE [--target ADDR] [--speed N]";
+
+const SKIPPED_HEADERS: &[&str] = &[
+    "host", "content-length", "connection", "upgrade", "transfer-encoding", "accept-encoding",
+    "sec-websocket-key", "sec-websocket-version", "sec-websocket-extensions", "x-pow",
+];
+
+pub struct Recorder {
+    path: String,
+    file: Mutex<Option<std::fs::File>>,
+    started: Instant,
+    clock: Arc<dyn Clock>,
+}
+
+impl Recorder {
+    pub fn new(path: &str, clock: Arc<dyn Clock>) -> Self {
+        Self { path: path.into(), file: Mutex::new(None), started: clock.instant(), clock }
+    }
+
+    pub fn open(&self) -> std::io::Result<()> {
+        let file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&self.path)
+            .map_err(|e| std::io::Error::other(format!("--record {}: {}", self.path, e)))?;
+        *self.file.lock().unwrap() = Some(file);
+        xlog!(Level::Info, target: "record", "recording inbound traffic to {}", self.path);
+        Ok(())
+    }
+
+    fn write(&self, conn: &str, kind: &str, mut event: Map<String, Value>) {
+        event.insert("at".into(), json!(self.clock.since(self.started).as_millis() as u64));
+        event.insert("conn".into(), json!(conn));
+        event.insert("kind".into(), json!(kind));
+        let mut guard = self.file.lock().unwrap();
+        let Some(file) = guard.as_mut() else {
+            return;
+        };
+        if let Err(err) = writeln!(file, "{}", Value::Object(event)) {
+            xlog!(Level::Warn, target: "record", "{}: {}", self.path, err);
+        }
+    }
+
+    pub fn http(&self, mut event: Map<String, Value>, status: u16, created: Option<&str>, sent: &[u8]) {
+        event.insert("status".into(), json!(status));
+        if let Some(created) = created {
+            event.insert("created".into(), json!(created));
+        }
+        let ids = ids(sent);
+        if !ids.is_empty() {
+            event.insert("ids".into(), json!(ids));
+        }
+        self.write(&Uuid::new_v4().to_string(), "http", event);
+    }
+
+    pub fn open_socket(&self, conn: Uuid, headers: &HeaderMap) {
+        let mut event = Map::new();
+        event.insert("headers".into(), json!(kept(headers)));
+        self.write(&conn.to_string(), "open", event);
+    }
+
+    pub fn frame(&self, conn: Uuid, msg: &actix_ws::Message) {
+        let mut event = Map::new();
+        let kind = match msg {
+            actix_ws::Message::Text(text) => {
+                event.insert("data".into(), json!(text.to_string()));
+                "text"
+            }
+            actix_ws::Message::Binary(bytes) => {
+                event.insert("data64".into(), json!(STANDARD.encode(bytes)));
+                "binary"
+            }
+            actix_ws::Message::Ping(bytes) => {
+                event.insert("data64".into(), json!(STANDARD.encode(bytes)));
+                "ping"
+            }
+            actix_ws::Message::Pong(bytes) => {
+                event.insert("data64".into(), json!(STANDARD.encode(bytes)));
+                "pong"
+            }
+            actix_ws::Message::Close(reason) => {
+                if let Some(reason) = reason {
+                    event.insert("code".into(), json!(u16::from(reason.code)));
+                }
+                "close"
+            }
+            _ => return,
+        };
+        self.write(&conn.to_string(), kind, event);
+    }
+}
+
+fn kept(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers.iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+fn ids(body: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(body)
+        .split(|c: char| !(c.is_ascii_hexdigit() || c == '-'))
+        .filter(|token| token.len() == 36 && Uuid::parse_str(token).is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+fn payload(event: &mut Map<String, Value>, key: &str, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => event.insert(key.into(), json!(text)),
+        Err(_) => event.insert(format!("{}64", key), json!(STANDARD.encode(bytes))),
+    };
+}
+
+fn unpayload(event: &Value, key: &str) -> Result<Vec<u8>, String> {
+    if let Some(text) = event[key].as_str() {
+        return Ok(text.as_bytes().to_vec());
+    }
+    match event[format!("{}64", key)].as_str() {
+        Some(encoded) => STANDARD.decode(encoded).map_err(|e| e.to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub async fn capture(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let recorder = req.app_data::<web::Data<State>>().and_then(|state| state.recorder.clone());
+    let Some(recorder) = recorder.filter(|_| req.path() != "/ws") else {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    };
+    let mut body = web::BytesMut::new();
+    let mut stream = req.take_payload();
+    while let Some(chunk) = stream.next().await {
+        body.extend_from_slice(&chunk?);
+    }
+    let mut event = Map::new();
+    event.insert("method".into(), json!(req.method().as_str()));
+    event.insert("path".into(), json!(req.uri().path_and_query().map_or(req.path(), |p| p.as_str())));
+    event.insert("headers".into(), json!(kept(req.headers())));
+    payload(&mut event, "body", &body);
+    req.set_payload(body.freeze().into());
+    let response = match next.call(req).await {
+        Ok(response) => response,
+        Err(err) => {
+            recorder.http(event, err.as_response_error().status_code().as_u16(), None, &[]);
+            return Err(err);
+        }
+    };
+    let (req, response) = response.into_parts();
+    let (response, sent) = response.into_parts();
+    let sent = actix_web::body::to_bytes(sent).await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+    let created = response.headers().get("x-entity-id").and_then(|v| v.to_str().ok());
+    recorder.http(event, response.status().as_u16(), created, &sent);
+    Ok(ServiceResponse::new(req, response.set_body(sent)).map_into_boxed_body())
+}
+
+pub struct Options {
+    pub file: String,
+    pub target: String,
+    pub speed: f64,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options { file: String::new(), target: "127.0.0.1:8080".into(), speed: 1.0, clock: time::system() };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") && options.file.is_empty() {
+                options.file = arg;
+                continue;
+            }
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--target" => options.target = value,
+                "--speed" => options.speed = value.parse().ok().filter(|n: &f64| n.is_finite() && *n >= 0.0)
+                    .ok_or(format!("--speed expects a number, 0 for no delays, got {}", value))?,
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        if options.file.is_empty() {
+            return Err(USAGE.into());
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    events: u64,
+    statuses: BTreeMap<String, u64>,
+    opened: u64,
+    sent: u64,
+    received: Arc<AtomicU64>,
+    failures: Vec<String>,
+    elapsed: Duration,
+}
+
+impl Report {
+    fn fail(&mut self, line: usize, err: String) {
+        self.failures.push(format!("line {}: {}", line, err));
+    }
+
+    pub fn failed(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        let mut rows = vec![
+            vec!["events".into(), self.events.to_string()],
+            vec!["elapsed".into(), format!("{:.1}s", self.elapsed.as_secs_f64())],
+            vec!["ws.opened".into(), self.opened.to_string()],
+            vec!["ws.sent".into(), self.sent.to_string()],
+            vec!["ws.received".into(), self.received.load(Ordering::Relaxed).to_string()],
+            vec!["failures".into(), self.failures.len().to_string()],
+        ];
+        for (status, n) in &self.statuses {
+            rows.push(vec![status.clone(), n.to_string()]);
+        }
+        let mut out = layout::table(&["METRIC", "VALUE"], &rows);
+        for failure in &self.failures {
+            out += &format!("\n{}", failure);
+        }
+        out
+    }
+}
+
+async fn request(http: &reqwest::Client, target: &str, event: &Value) -> Result<(u16, Vec<String>), String> {
+    let method = event["method"].as_str().and_then(|m| reqwest::Method::from_bytes(m.as_bytes()).ok())
+        .ok_or("http event without a method")?;
+    let path = event["path"].as_str().ok_or("http event without a path")?;
+    let mut builder = http.request(method, format!("http://{}{}", target, path));
+    for (name, value) in event["headers"].as_object().into_iter().flatten() {
+        if let Some(value) = value.as_str() {
+            builder = builder.header(name.as_str(), value);
+        }
+    }
+    let response = builder.body(unpayload(event, "body")?).send().await.map_err(|e| e.to_string())?;
+    let status = response.status().as_u16();
+    let mut found: Vec<String> = response.headers().get("x-entity-id").and_then(|v| v.to_str().ok()).map(str::to_string).into_iter().collect();
+    found.extend(ids(&response.bytes().await.map_err(|e| e.to_string())?));
+    Ok((status, found))
+}
+
+async fn open(target: &str, conn: &str, event: &Value, received: Arc<AtomicU64>) -> Result<WriteHalf<Box<dyn Wire>>, String> {
+    let session = event["headers"]["authorization"].as_str().and_then(|v| v.strip_prefix("Bearer "));
+    let (mut read, write) = tokio::io::split(client::upgrade(&Tcp::direct(), target, session).await?);
+    task::spawn(&format!("playback.read {}", conn), async move {
+        while let Ok((_, opcode, _)) = client::read_frame(&mut read).await {
+            if opcode == 0x8 {
+                break;
+            }
+            received.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+    Ok(write)
+}
+
+fn opcode(kind: &str) -> Option<u8> {
+    match kind {
+        "text" => Some(0x1),
+        "binary" => Some(0x2),
+        "close" => Some(0x8),
+        "ping" => Some(0x9),
+        "pong" => Some(0xa),
+        _ => None,
+    }
+}
+
+pub async fn run(options: Options) -> Result<Report, String> {
+    let raw = std::fs::read_to_string(&options.file).map_err(|e| format!("{}: {}", options.file, e))?;
+    let http = reqwest::Client::new();
+    let mut sockets: HashMap<String, WriteHalf<Box<dyn Wire>>> = HashMap::new();
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut report = Report::default();
+    let started = options.clock.instant();
+    for (n, line) in raw.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let line_no = n + 1;
+        let line = ids.iter().fold(line.to_string(), |line, (old, new)| line.replace(old, new));
+        let event: Value = serde_json::from_str(&line).map_err(|e| format!("{} line {}: {}", options.file, line_no, e))?;
+        let (Some(at), Some(kind), Some(conn)) = (event["at"].as_u64(), event["kind"].as_str(), event["conn"].as_str()) else {
+            return Err(format!("{} line {}: expected at, kind, and conn", options.file, line_no));
+        };
+        if options.speed > 0.0 {
+            let due = started + Duration::from_millis(at).div_f64(options.speed);
+            tokio::time::sleep(due.saturating_duration_since(options.clock.instant())).await;
+        }
+        report.events += 1;
+        match kind {
+            "http" => match request(&http, &options.target, &event).await {
+                Ok((status, found)) => {
+                    *report.statuses.entry(format!("http.{}", status)).or_default() += 1;
+                    let recorded = event["created"].as_str().into_iter()
+                        .chain(event["ids"].as_array().into_iter().flatten().filter_map(Value::as_str));
+                    for (old, new) in recorded.zip(found) {
+                        if old != new {
+                            ids.entry(old.to_string()).or_insert(new);
+                        }
+                    }
+                    match event["status"].as_u64() {
+                        Some(recorded) if recorded != status as u64 => report.fail(line_no, format!(
+                            "{} {} returned {}, recorded {}", event["method"].as_str().unwrap_or_default(), event["path"].as_str().unwrap_or_default(), status, recorded,
+                        )),
+                        _ => {}
+                    }
+                }
+                Err(err) => report.fail(line_no, err),
+            },
+            "open" => match open(&options.target, conn, &event, report.received.clone()).await {
+                Ok(write) => {
+                    report.opened += 1;
+                    sockets.insert(conn.to_string(), write);
+                }
+                Err(err) => report.fail(line_no, err),
+            },
+            kind => {
+                let Some(op) = opcode(kind) else {
+                    report.fail(line_no, format!("unknown event kind {}", kind));
+                    continue;
+                };
+                let Some(socket) = sockets.get_mut(conn) else {
+                    report.fail(line_no, format!("{} frame on socket {} that is not open", kind, conn));
+                    continue;
+                };
+                let mut body = unpayload(&event, "data")?;
+                if let Some(code) = event["code"].as_u64() {
+                    body = (code as u16).to_be_bytes().to_vec();
+                }
+                match client::write_frame(socket, op, &body).await {
+                    Ok(()) => report.sent += 1,
+                    Err(err) => report.fail(line_no, err.to_string()),
+                }
+                if op == 0x8 {
+                    sockets.remove(conn);
+                }
+            }
+        }
+    }
+    for (_, mut socket) in sockets {
+        let _ = client::write_frame(&mut socket, 0x8, &[]).await;
+    }
+    report.elapsed = options.clock.since(started);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::predicate::Predicate;
+    use crate::server::{Config, Server};
+    use crate::store::Store;
+
+    #[actix_web::test]
+    async fn capture_plays_back_onto_a_fresh_server() {
+        let path = std::env::temp_dir().join(format!("morce-capture-{}.jsonl", Uuid::new_v4())).to_string_lossy().to_string();
+        let config = Config { record: Some(path.clone()), ..Config::default() };
+        let recorded = Server::builder().config(config).bind("127.0.0.1:0").build().await.unwrap();
+        let addr = recorded.local_addr();
+        let stop = recorded.shutdown_handle();
+        actix_web::rt::spawn(recorded.run());
+
+        let http = reqwest::Client::new();
+        let created = http.post(format!("http://{}/entities", addr)).header("x-tags", "kind=note").body("hello")
+            .send().await.unwrap();
+        assert_eq!(created.status().as_u16(), 201);
+        let id = created.headers()["x-entity-id"].to_str().unwrap().to_string();
+        let read = http.get(format!("http://{}/entities/{}", addr, id)).send().await.unwrap();
+        assert_eq!(read.status().as_u16(), 200);
+        stop.shutdown().await;
+
+        let fresh = Server::builder().bind("127.0.0.1:0").build().await.unwrap();
+        let target = fresh.local_addr().to_string();
+        let state = fresh.state().clone();
+        let stop = fresh.shutdown_handle();
+        actix_web::rt::spawn(fresh.run());
+
+        let options = Options { file: path.clone(), target, speed: 0.0, clock: time::system() };
+        let report = run(options).await.unwrap();
+        stop.shutdown().await;
+        let _ = std::fs::remove_file(&path);
+
+        assert!(!report.failed(), "{}", report.render());
+        assert_eq!(report.events, 2);
+        assert_eq!(report.statuses.get("http.201"), Some(&1));
+        assert_eq!(report.statuses.get("http.200"), Some(&1));
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "note".into());
+        let notes = state.store.query(&pred).await.unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(&notes[0].load[..], b"hello");
+        assert_ne!(notes[0].id.to_string(), id);
+    }
+}
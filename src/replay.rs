@@ -0,0 +1,105 @@
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::time::Clock;
+use crate::window::{Slot, Window};
+
+const CAPACITY: usize = 1 << 20;
+const SEQ_WINDOW: u64 = 64;
+
+struct Seen {
+    top: u64,
+    bits: u64,
+}
+
+impl Default for Seen {
+    fn default() -> Self {
+        Self { top: 0, bits: 1 }
+    }
+}
+
+pub struct Replay {
+    seqs: DashMap<Uuid, Seen>,
+    nonces: Window<String, ()>,
+}
+
+impl Replay {
+    pub fn new(window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            seqs: DashMap::new(),
+            nonces: Window::new(window, CAPACITY, clock),
+        }
+    }
+
+    pub fn accept_seq(&self, session: Uuid, seq: u64) -> bool {
+        let mut seen = self.seqs.entry(session).or_default();
+        if seq > seen.top {
+            let shift = seq - seen.top;
+            seen.bits = if shift >= SEQ_WINDOW { 0 } else { seen.bits << shift };
+            seen.bits |= 1;
+            seen.top = seq;
+            return true;
+        }
+        let offset = seen.top - seq;
+        if offset >= SEQ_WINDOW || seen.bits & (1 << offset) != 0 {
+            return false;
+        }
+        seen.bits |= 1 << offset;
+        true
+    }
+
+    pub fn release_seq(&self, session: Uuid, seq: u64) {
+        if let Some(mut seen) = self.seqs.get_mut(&session) {
+            if seq <= seen.top && seen.top - seq < SEQ_WINDOW {
+                let offset = seen.top - seq;
+                seen.bits &= !(1 << offset);
+            }
+        }
+    }
+
+    pub fn accept_nonce(&self, nonce: &str) -> bool {
+        matches!(self.nonces.claim(nonce.to_string(), ()), Slot::Claimed)
+    }
+
+    pub fn forget(&self, session: Uuid) {
+        self.seqs.remove(&session);
+    }
+
+    pub fn prune(&self) -> usize {
+        self.nonces.sweep()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+
+    #[test]
+    fn seqs_may_arrive_out_of_order_but_only_once() {
+        let replay = Replay::new(Duration::from_secs(60), time::system());
+        let session = Uuid::new_v4();
+        assert!(!replay.accept_seq(session, 0));
+        assert!(replay.accept_seq(session, 3));
+        assert!(replay.accept_seq(session, 1));
+        assert!(replay.accept_seq(session, 2));
+        assert!(!replay.accept_seq(session, 2));
+        assert!(!replay.accept_seq(session, 3));
+
+        assert!(replay.accept_seq(session, 3 + SEQ_WINDOW));
+        assert!(!replay.accept_seq(session, 3));
+        assert!(replay.accept_seq(session, 4));
+    }
+
+    #[test]
+    fn released_seqs_can_be_sent_again() {
+        let replay = Replay::new(Duration::from_secs(60), time::system());
+        let session = Uuid::new_v4();
+        assert!(replay.accept_seq(session, 5));
+        replay.release_seq(session, 5);
+        assert!(replay.accept_seq(session, 5));
+        assert!(!replay.accept_seq(session, 5));
+    }
+}
@@ -0,0 +1,214 @@
+use actix_web::web;
+use async_trait::async_trait;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::ban;
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::guard;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::room;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+const PRUNE_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    Forever,
+    Nothing,
+    Age(Duration),
+    Count(usize),
+}
+
+impl Policy {
+    pub fn parse(raw: &str) -> Option<Policy> {
+        match raw {
+            "forever" => Some(Policy::Forever),
+            "none" | "nothing" => Some(Policy::Nothing),
+            _ => match raw.parse::<usize>() {
+                Ok(n) => Some(Policy::Count(n)),
+                Err(_) => ban::parse_duration(raw).map(Policy::Age),
+            },
+        }
+    }
+
+    pub fn of(channel: &Entity) -> Policy {
+        channel.tags.get("retain").and_then(|v| Policy::parse(v)).unwrap_or(Policy::Forever)
+    }
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Forever => write!(f, "forever"),
+            Policy::Nothing => write!(f, "none"),
+            Policy::Age(age) => {
+                let secs = age.as_secs();
+                let (n, unit) = [(604800, "w"), (86400, "d"), (3600, "h"), (60, "m")].iter()
+                    .find(|(size, _)| secs >= *size && secs % size == 0)
+                    .map(|(size, unit)| (secs / size, *unit))
+                    .unwrap_or((secs, "s"));
+                write!(f, "{}{}", n, unit)
+            }
+            Policy::Count(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+async fn messages(store: &dyn Store, room: Uuid) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    pred.insert("in".into(), room.to_string());
+    let mut found = store.query(&pred).await.unwrap_or_default();
+    found.sort_by_key(|m| m.at);
+    found
+}
+
+fn expired(policy: Policy, history: &[Entity], now: u64) -> &[Entity] {
+    match policy {
+        Policy::Forever => &[],
+        Policy::Nothing => history,
+        Policy::Count(n) => &history[..history.len().saturating_sub(n)],
+        Policy::Age(age) => {
+            let cutoff = now.saturating_sub(age.as_millis() as u64);
+            &history[..history.partition_point(|m| m.at < cutoff)]
+        }
+    }
+}
+
+pub async fn prune(store: &dyn Store) -> usize {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "channel".into());
+    let channels = store.query(&pred).await.unwrap_or_default();
+    let now = store.now();
+    let mut removed = 0;
+    for channel in channels {
+        let policy = Policy::of(&channel);
+        if policy == Policy::Forever {
+            continue;
+        }
+        let history = messages(store, channel.id).await;
+        for msg in expired(policy, &history, now) {
+            if store.delete(msg.id).await.is_ok() {
+                removed += 1;
+            }
+            let mut pred = Predicate::new();
+            pred.insert("kind".into(), "preview".into());
+            pred.insert("on".into(), msg.id.to_string());
+            for preview in store.query(&pred).await.unwrap_or_default() {
+                let _ = store.delete(preview.id).await;
+            }
+        }
+    }
+    removed
+}
+
+pub async fn ephemeral(store: &dyn Store, entity: &Entity) -> bool {
+    if entity.tags.get("kind").map(String::as_str) != Some("msg") {
+        return false;
+    }
+    let Some(room) = entity.tags.get("in").and_then(|v| v.parse::<Uuid>().ok()) else {
+        return false;
+    };
+    matches!(store.read(room).await, Ok(Some(channel)) if Policy::of(&channel) == Policy::Nothing)
+}
+
+pub fn start(state: web::Data<State>) {
+    task::spawn("retention", async move {
+        let mut ticker = tokio::time::interval(PRUNE_EVERY);
+        loop {
+            ticker.tick().await;
+            let removed = prune(&*state.store).await;
+            if removed > 0 {
+                xlog!(Level::Info, target: "retention", "pruned {} messages", removed);
+            }
+        }
+    });
+}
+
+pub struct Retention;
+
+#[async_trait]
+impl Command for Retention {
+    fn help(&self) -> Help {
+        Help { category: Category::Admin, role: Role::Admin, usage: "[<room> [<age>|<count>|forever|none]]", about: "help.retention" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("retention.admin"))?;
+        let store = &*ctx.state.store;
+        if !guard::is_admin(store, actor).await {
+            return Err(tr("retention.admin").into());
+        }
+
+        let mut parts = args.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (None, _) => {
+                let mut pred = Predicate::new();
+                pred.insert("kind".into(), "channel".into());
+                let channels = store.query(&pred).await.map_err(|e| e.to_string())?;
+                let mut rows = Vec::new();
+                for channel in channels.iter().filter(|c| c.tags.contains_key("retain")) {
+                    rows.push(vec![
+                        channel.tags.get("name").cloned().unwrap_or_else(|| channel.id.to_string()),
+                        Policy::of(channel).to_string(),
+                        messages(store, channel.id).await.len().to_string(),
+                    ]);
+                }
+                if rows.is_empty() {
+                    return Ok(tr("retention.empty").into());
+                }
+                Ok(layout::table(&[tr("retention.room"), tr("retention.policy"), tr("retention.messages")], &rows))
+            }
+            (Some(room), policy) => {
+                let channel = room::find(store, room).await.ok_or_else(|| trf("retention.noroom", &[room]))?;
+                let Some(raw) = policy else {
+                    return Ok(trf("retention.current", &[room, &Policy::of(&channel).to_string()]));
+                };
+                let policy = Policy::parse(raw).ok_or(tr("retention.usage"))?;
+                let mut tags = channel.tags.clone();
+                if policy == Policy::Forever {
+                    tags.remove("retain");
+                } else {
+                    tags.insert("retain".into(), raw.to_string());
+                }
+                let channel = store.update(channel.id, channel.load.clone(), tags).await.map_err(|e| e.to_string())?;
+                ctx.state.hub.publish(&channel);
+                let removed = prune(store).await;
+                Ok(trf("retention.set", &[room, &policy.to_string(), &removed.to_string()]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use crate::time::TokioClock;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn prune_drops_messages_past_the_age() {
+        let store = Memory::new(Arc::new(TokioClock::new()));
+        let tag = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let channel = store.create(bytes::Bytes::new(), tag(&[("kind", "channel"), ("name", "lobby"), ("retain", "1h")])).await.unwrap();
+        let room = channel.id.to_string();
+        let old = store.create("old".into(), tag(&[("kind", "msg"), ("in", &room)])).await.unwrap();
+        assert_eq!(prune(&store).await, 0);
+
+        tokio::time::advance(Duration::from_secs(3600 + 60)).await;
+        let new = store.create("new".into(), tag(&[("kind", "msg"), ("in", &room)])).await.unwrap();
+        assert_eq!(prune(&store).await, 1);
+        assert!(store.read(old.id).await.unwrap().is_none());
+        assert!(store.read(new.id).await.unwrap().is_some());
+    }
+}
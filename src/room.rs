@@ -0,0 +1,372 @@
+use actix_web::{web, HttpResponse};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::command::{self, Category, Command, Context, Help, Role};
+use crate::entity::Entity;
+use crate::guard;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::marker;
+use crate::predicate::Predicate;
+use crate::store::Store;
+use crate::time;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Creation {
+    Off,
+    Users,
+    Admins,
+}
+
+impl Creation {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "off" => Some(Creation::Off),
+            "users" => Some(Creation::Users),
+            "admins" => Some(Creation::Admins),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Creation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Creation::Off => "off",
+            Creation::Users => "users",
+            Creation::Admins => "admins",
+        })
+    }
+}
+
+pub struct Rooms {
+    startup: Vec<String>,
+    default: Option<String>,
+    creation: Creation,
+}
+
+impl Rooms {
+    pub fn new(startup: Vec<String>, default: Option<String>, creation: Creation) -> Self {
+        Self { startup, default, creation }
+    }
+}
+
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.parse::<Uuid>().is_err() && !name.contains(|c: char| c.is_whitespace() || ",=&".contains(c))
+}
+
+pub async fn find(store: &dyn Store, room: &str) -> Option<Entity> {
+    if let Ok(id) = room.parse::<Uuid>() {
+        return store.read(id).await.ok()?
+            .filter(|c| c.tags.get("kind").map(String::as_str) == Some("channel"));
+    }
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "channel".into());
+    pred.insert("name".into(), room.to_string());
+    store.query(&pred).await.ok()?.into_iter().next()
+}
+
+fn name(channel: &Entity) -> String {
+    channel.tags.get("name").cloned().unwrap_or_else(|| channel.id.to_string())
+}
+
+fn cap(channel: &Entity) -> Option<usize> {
+    channel.tags.get("max").and_then(|v| v.parse().ok())
+}
+
+fn queues(channel: &Entity) -> bool {
+    channel.tags.get("full").map(String::as_str) == Some("queue")
+}
+
+pub async fn check_post(
+    state: &State,
+    actor: Option<Uuid>,
+    tags: &BTreeMap<String, String>,
+) -> actix_web::Result<()> {
+    if tags.get("kind").map(String::as_str) != Some("msg") {
+        return Ok(());
+    }
+    let Some(room) = tags.get("in").and_then(|v| v.parse::<Uuid>().ok()) else {
+        return Ok(());
+    };
+    let Some(channel) = state.store.read(room).await? else {
+        return Ok(());
+    };
+    if channel.tags.get("announce").map(String::as_str) != Some("true") {
+        return Ok(());
+    }
+    match actor {
+        Some(actor) if guard::is_moderator(&*state.store, actor, room).await => Ok(()),
+        Some(actor) => {
+            command::ephemeral(state, actor, None, &trf("announce.readonly", &[&name(&channel)])).await;
+            Err(actix_web::error::ErrorForbidden(format!("{} is announcement-only", name(&channel))))
+        }
+        None => Err(actix_web::error::ErrorForbidden(format!("{} is announcement-only", name(&channel)))),
+    }
+}
+
+async fn perms(store: &dyn Store, room: Uuid) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "perm".into());
+    pred.insert("what".into(), room.to_string());
+    store.query(&pred).await.unwrap_or_default()
+}
+
+pub async fn members(store: &dyn Store, room: Uuid) -> BTreeSet<String> {
+    perms(store, room).await.into_iter()
+        .filter_map(|perm| perm.tags.get("who").cloned())
+        .collect()
+}
+
+async fn waiting(store: &dyn Store, room: Uuid) -> Vec<Entity> {
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "waiting".into());
+    pred.insert("room".into(), room.to_string());
+    let mut line = store.query(&pred).await.unwrap_or_default();
+    line.sort_by_key(|w| w.at);
+    line
+}
+
+async fn create(state: &State, room: &str, owner: Option<Uuid>) -> Result<Entity, String> {
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "channel".into());
+    tags.insert("name".into(), room.to_string());
+    if let Some(owner) = owner {
+        tags.insert("owner".into(), owner.to_string());
+    }
+    let channel = state.store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+    state.hub.publish(&channel);
+    Ok(channel)
+}
+
+pub async fn open(state: &State) -> Result<(), String> {
+    let rooms = &state.rooms;
+    for room in rooms.startup.iter().chain(&rooms.default) {
+        if find(&*state.store, room).await.is_none() {
+            create(state, room, None).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn arrive(state: &State, user: &Entity) {
+    if user.tags.get("kind").map(String::as_str) != Some("user") {
+        return;
+    }
+    let Some(room) = &state.rooms.default else {
+        return;
+    };
+    let channel = match find(&*state.store, room).await {
+        Some(channel) => channel,
+        None => match create(state, room, None).await {
+            Ok(channel) => channel,
+            Err(_) => return,
+        },
+    };
+    let _ = enter(state, &channel, &user.id.to_string()).await;
+}
+
+pub async fn default_room(state: web::Data<State>) -> HttpResponse {
+    match &state.rooms.default {
+        Some(room) => HttpResponse::Ok().body(room.clone()),
+        None => HttpResponse::NotFound().body("no default room"),
+    }
+}
+
+async fn grant(state: &State, room: Uuid, user: &str, can: &str) -> Result<(), String> {
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "perm".into());
+    tags.insert("who".into(), user.to_string());
+    tags.insert("what".into(), room.to_string());
+    tags.insert("can".into(), can.into());
+    let perm = state.store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+    state.hub.publish(&perm);
+    Ok(())
+}
+
+pub async fn let_in(state: &State, channel: &Entity, user: &str) -> Result<bool, String> {
+    let store = &*state.store;
+    if members(store, channel.id).await.contains(user) {
+        return Ok(false);
+    }
+    for entry in waiting(store, channel.id).await {
+        if entry.tags.get("user").map(String::as_str) == Some(user) {
+            let _ = store.delete(entry.id).await;
+        }
+    }
+    grant(state, channel.id, user, "post").await?;
+    Ok(true)
+}
+
+pub async fn admit(state: &State, channel: &Entity) {
+    let store = &*state.store;
+    let Some(cap) = cap(channel) else {
+        return;
+    };
+    let mut count = members(store, channel.id).await.len();
+    for ticket in waiting(store, channel.id).await {
+        if count >= cap {
+            break;
+        }
+        let _ = store.delete(ticket.id).await;
+        let Some(user) = ticket.tags.get("user") else {
+            continue;
+        };
+        if grant(state, channel.id, user, "post").await.is_ok() {
+            count += 1;
+            if let Ok(user) = user.parse() {
+                command::direct_message(state, user, &trf("join.admitted", &[&name(channel)])).await;
+            }
+        }
+    }
+}
+
+pub async fn released(state: &State, entity: &Entity) {
+    if entity.tags.get("kind").map(String::as_str) != Some("perm") {
+        return;
+    }
+    let Some(room) = entity.tags.get("what") else {
+        return;
+    };
+    if let Some(channel) = find(&*state.store, room).await {
+        admit(state, &channel).await;
+    }
+}
+
+pub struct Join;
+
+#[async_trait]
+impl Command for Join {
+    fn help(&self) -> Help {
+        Help { category: Category::Rooms, role: Role::User, usage: "[room]", about: "help.join" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("join.signin"))?;
+        let (state, store) = (ctx.state, &*ctx.state.store);
+        let me = actor.to_string();
+        let room = match args {
+            "" => state.rooms.default.as_deref().ok_or(tr("join.usage"))?,
+            room => room,
+        };
+        let channel = match find(store, room).await {
+            Some(channel) => channel,
+            None => {
+                let allowed = match state.rooms.creation {
+                    Creation::Off => false,
+                    Creation::Users => true,
+                    Creation::Admins => guard::is_admin(store, actor).await,
+                };
+                if !allowed || !valid_name(room) {
+                    return Err(trf("join.noroom", &[room]));
+                }
+                let channel = create(state, room, Some(actor)).await?;
+                grant(state, channel.id, &me, "moderate").await?;
+                return Ok(trf("join.created", &[room]));
+            }
+        };
+        enter(state, &channel, &me).await
+    }
+}
+
+async fn enter(state: &State, channel: &Entity, me: &str) -> Result<String, String> {
+    let store = &*state.store;
+    let room = name(channel);
+    admit(state, channel).await;
+    let members = members(store, channel.id).await;
+    if members.contains(me) {
+        return Ok(trf("join.already", &[&room]));
+    }
+
+    let line = waiting(store, channel.id).await;
+    let full = cap(channel).is_some_and(|cap| members.len() >= cap);
+    if !full && line.is_empty() {
+        grant(state, channel.id, me, "post").await?;
+        return Ok(trf("join.joined", &[&room]));
+    }
+    if !queues(channel) {
+        return Err(trf("join.full", &[&room, &members.len().to_string()]));
+    }
+    if let Some(position) = line.iter().position(|w| w.tags.get("user").map(String::as_str) == Some(me)) {
+        return Ok(trf("join.waiting", &[&room, &(position + 1).to_string()]));
+    }
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "waiting".into());
+    tags.insert("room".into(), channel.id.to_string());
+    tags.insert("user".into(), me.to_string());
+    store.create(bytes::Bytes::new(), tags).await.map_err(|e| e.to_string())?;
+    Ok(trf("join.queued", &[&room, &(line.len() + 1).to_string()]))
+}
+
+pub struct Leave;
+
+#[async_trait]
+impl Command for Leave {
+    fn help(&self) -> Help {
+        Help { category: Category::Rooms, role: Role::User, usage: "<room>", about: "help.leave" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("join.signin"))?;
+        if args.is_empty() {
+            return Err(tr("leave.usage").into());
+        }
+        let store = &*ctx.state.store;
+        let channel = find(store, args).await.ok_or_else(|| trf("join.noroom", &[args]))?;
+        let me = actor.to_string();
+        let mut left = false;
+        for perm in perms(store, channel.id).await.iter().filter(|p| p.tags.get("who") == Some(&me)) {
+            left |= store.delete(perm.id).await.is_ok();
+        }
+        for ticket in waiting(store, channel.id).await.iter().filter(|w| w.tags.get("user") == Some(&me)) {
+            left |= store.delete(ticket.id).await.is_ok();
+        }
+        if !left {
+            return Err(trf("leave.notin", &[&name(&channel)]));
+        }
+        admit(ctx.state, &channel).await;
+        Ok(trf("leave.left", &[&name(&channel)]))
+    }
+}
+
+pub struct List;
+
+#[async_trait]
+impl Command for List {
+    fn help(&self) -> Help {
+        Help { category: Category::Rooms, role: Role::Anyone, usage: "", about: "help.roomlist" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        let store = &*ctx.state.store;
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "channel".into());
+        let channels = store.query(&pred).await.map_err(|e| e.to_string())?;
+        let mut rows = Vec::new();
+        for channel in &channels {
+            let members = members(store, channel.id).await;
+            let history = marker::history(store, channel.id).await;
+            let unread = match ctx.actor.filter(|me| members.contains(&me.to_string())) {
+                Some(me) => marker::unread(&history, me, marker::position(store, me, channel.id).await).len().to_string(),
+                None => "-".into(),
+            };
+            let active = history.last()
+                .map(|m| time::iso_millis(m.at).get(..16).unwrap_or_default().replace('T', " "))
+                .unwrap_or_else(|| "-".into());
+            rows.push(vec![name(channel), members.len().to_string(), unread, active]);
+        }
+        if rows.is_empty() {
+            return Ok(tr("rooms.empty").into());
+        }
+        rows.sort();
+        Ok(layout::table(&[tr("rooms.room"), tr("rooms.members"), tr("rooms.unread"), tr("rooms.active")], &rows))
+    }
+}
@@ -0,0 +1,538 @@
+use actix_web::middleware::from_fn;
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpServer};
+use jsonwebtoken::Algorithm;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::account;
+use crate::api::{self, State};
+use crate::backup;
+use crate::ban::{self, Bans};
+use crate::command::Commands;
+use crate::console;
+use crate::daemon;
+use crate::dedup::Dedup;
+use crate::echo;
+use crate::federation::{self, Federation};
+use crate::feed::{self, Feeds};
+use crate::handover;
+use crate::invite;
+use crate::marker;
+use crate::health;
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::hooks::Hooks;
+use crate::hub::Hub;
+use crate::log::Level;
+use crate::mail::{self, Mail, Security, Smtp};
+use crate::memory::Memory;
+use crate::mqtt::{self, Broker};
+use crate::names::Policy;
+use crate::oidc::{self, Oidc};
+use crate::pow::{self, Pow};
+use crate::presence::{self, Online};
+use crate::push::{self, Relay};
+use crate::quota::{self, Quota};
+use crate::record::{self, Recorder};
+use crate::replay::Replay;
+use crate::retention;
+use crate::room::{self, Creation, Rooms};
+use crate::stats::Stats;
+use crate::store::Store;
+use crate::task;
+use crate::time::{self, Clock};
+use crate::tor;
+use crate::translate::{Api, Backend, Translator};
+use crate::unfurl::{self, Unfurler};
+use crate::webhook;
+use crate::welcome::Welcome;
+use crate::ws::{self, Deadlines};
+use crate::xlog;
+
+const TOR_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const SWEEP_EVERY: Duration = Duration::from_secs(60);
+
+pub const USAGE: &str = "usage: morce [--bind ADDR] [--workers N] [--threads N] [--current-thread] [--request-timeout SECS] [--max-connections N] [--echo] [--name NAME] [--duplicate-names reject|suffix|kick] [--pow BITS] [--admin NAME]... [--quota SIZE] [--file-quota SIZE] [--welcome [--rules PATH]] [--room NAME]... [--default-room NAME] [--join-creates off|users|admins] [--daemon [--pid-file PATH] [--log-file PATH]] [--handover PATH] [--record PATH] [--grpc ADDR] [--mqtt ADDR [--mqtt-user NAME] [--mqtt-password-file PATH]] [--smtp ADDR --smtp-from ADDRESS [--smtp-tls plain|starttls|tls] [--smtp-user NAME] [--smtp-password-file PATH] [--email-after MINUTES]] [--push [--push-host HOST]...] [--feed-interval MINUTES] [--translate URL [--translate-api libre|deepl] [--translate-key-file PATH]] [--unfurl] [--tor] [--onion [--tor-control ADDR] [--onion-key PATH]] [--oidc-issuer URL --oidc-audience ID [--oidc-alg ALG] [--oidc-admin-role ROLE]]";
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub bind: String,
+    pub name: String,
+    pub duplicate_names: Policy,
+    pub workers: Option<usize>,
+    pub threads: Option<usize>,
+    pub current_thread: bool,
+    pub request_timeout: Duration,
+    pub max_connections: usize,
+    pub echo: bool,
+    pub pow_bits: u32,
+    pub admins: Vec<String>,
+    pub oidc_issuer: Option<String>,
+    pub oidc_audience: Option<String>,
+    pub oidc_alg: Algorithm,
+    pub oidc_admin_role: Option<String>,
+    pub tor: bool,
+    pub onion: bool,
+    pub tor_control: String,
+    pub onion_key: String,
+    pub handover: Option<String>,
+    pub record: Option<String>,
+    pub grpc: Option<String>,
+    pub mqtt: Option<Broker>,
+    pub smtp: Option<Smtp>,
+    pub email_after: Duration,
+    pub push: bool,
+    pub push_hosts: Vec<String>,
+    pub feed_interval: Duration,
+    pub translate: Option<Backend>,
+    pub unfurl: bool,
+    pub quota: u64,
+    pub file_quota: u64,
+    pub welcome: bool,
+    pub rules: Option<String>,
+    pub rooms: Vec<String>,
+    pub default_room: Option<String>,
+    pub join_creates: Creation,
+    pub daemon: bool,
+    pub pid_file: String,
+    pub log_file: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8080".into(),
+            name: "morce".into(),
+            duplicate_names: Policy::Reject,
+            workers: None,
+            threads: None,
+            current_thread: false,
+            request_timeout: Duration::from_secs(5),
+            max_connections: 25_000,
+            echo: false,
+            pow_bits: 0,
+            admins: Vec::new(),
+            oidc_issuer: None,
+            oidc_audience: None,
+            oidc_alg: Algorithm::RS256,
+            oidc_admin_role: None,
+            tor: false,
+            onion: false,
+            tor_control: "127.0.0.1:9051".into(),
+            onion_key: "morce.onion.key".into(),
+            handover: None,
+            record: None,
+            grpc: None,
+            mqtt: None,
+            smtp: None,
+            email_after: Duration::from_secs(15 * 60),
+            push: false,
+            push_hosts: Vec::new(),
+            feed_interval: Duration::from_secs(15 * 60),
+            translate: None,
+            unfurl: false,
+            quota: 0,
+            file_quota: 0,
+            welcome: false,
+            rules: None,
+            rooms: Vec::new(),
+            default_room: None,
+            join_creates: Creation::Users,
+            daemon: false,
+            pid_file: "morce.pid".into(),
+            log_file: "morce.log".into(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut mqtt = Broker { addr: String::new(), user: None, password: None };
+        let mut translate = Backend { url: String::new(), api: Api::Libre, key: None };
+        let mut smtp = Smtp { addr: String::new(), security: Security::StartTls, user: None, password: None, from: String::new() };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || inline.clone().or_else(|| args.next()).ok_or(format!("{} needs a value", flag));
+            match flag.as_str() {
+                "--bind" => config.bind = value()?,
+                "--name" => config.name = value()?,
+                "--duplicate-names" => config.duplicate_names = Policy::parse(&value()?)
+                    .ok_or("--duplicate-names expects reject, suffix, or kick")?,
+                "--workers" => config.workers = Some(count(&flag, &value()?)?),
+                "--threads" => config.threads = Some(count(&flag, &value()?)?),
+                "--current-thread" => config.current_thread = true,
+                "--echo" => config.echo = true,
+                "--request-timeout" => config.request_timeout = Duration::from_secs(count(&flag, &value()?)? as u64),
+                "--max-connections" => config.max_connections = count(&flag, &value()?)?,
+                "--pow" => config.pow_bits = match value()?.parse() {
+                    Ok(bits) if bits <= 32 => bits,
+                    _ => return Err("--pow expects 0 to 32 bits".into()),
+                },
+                "--admin" => config.admins.push(value()?),
+                "--oidc-issuer" => config.oidc_issuer = Some(value()?),
+                "--oidc-audience" => config.oidc_audience = Some(value()?),
+                "--oidc-alg" => config.oidc_alg = oidc::algorithm(&value()?)
+                    .ok_or("--oidc-alg expects an asymmetric algorithm such as RS256, ES256, or EdDSA")?,
+                "--oidc-admin-role" => config.oidc_admin_role = Some(value()?),
+                "--tor" => config.tor = true,
+                "--onion" => config.onion = true,
+                "--tor-control" => config.tor_control = value()?,
+                "--onion-key" => config.onion_key = value()?,
+                "--handover" => config.handover = Some(value()?),
+                "--record" => config.record = Some(value()?),
+                "--grpc" if cfg!(feature = "grpc") => config.grpc = Some(value()?),
+                "--grpc" => return Err("--grpc needs a build with --features grpc".into()),
+                "--mqtt" => mqtt.addr = value()?,
+                "--mqtt-user" => mqtt.user = Some(value()?),
+                "--mqtt-password-file" => {
+                    let path = value()?;
+                    let password = std::fs::read_to_string(&path).map_err(|e| format!("--mqtt-password-file {}: {}", path, e))?;
+                    mqtt.password = Some(password.trim().to_string());
+                }
+                "--smtp" => smtp.addr = value()?,
+                "--smtp-from" => smtp.from = value()?,
+                "--smtp-tls" => smtp.security = Security::parse(&value()?).ok_or("--smtp-tls expects plain, starttls, or tls")?,
+                "--smtp-user" => smtp.user = Some(value()?),
+                "--smtp-password-file" => {
+                    let path = value()?;
+                    let password = std::fs::read_to_string(&path).map_err(|e| format!("--smtp-password-file {}: {}", path, e))?;
+                    smtp.password = Some(password.trim().to_string());
+                }
+                "--email-after" => config.email_after = Duration::from_secs(count(&flag, &value()?)? as u64 * 60),
+                "--push" => config.push = true,
+                "--push-host" => {
+                    config.push_hosts.push(value()?);
+                    config.push = true;
+                }
+                "--feed-interval" => config.feed_interval = Duration::from_secs(count(&flag, &value()?)? as u64 * 60),
+                "--translate" => translate.url = value()?,
+                "--translate-api" => translate.api = Api::parse(&value()?).ok_or("--translate-api expects libre or deepl")?,
+                "--translate-key-file" => {
+                    let path = value()?;
+                    let key = std::fs::read_to_string(&path).map_err(|e| format!("--translate-key-file {}: {}", path, e))?;
+                    translate.key = Some(key.trim().to_string());
+                }
+                "--unfurl" => config.unfurl = true,
+                "--quota" => config.quota = bytes(&flag, &value()?)?,
+                "--file-quota" => config.file_quota = bytes(&flag, &value()?)?,
+                "--welcome" => config.welcome = true,
+                "--rules" => {
+                    let path = value()?;
+                    let rules = std::fs::read_to_string(&path).map_err(|e| format!("--rules {}: {}", path, e))?;
+                    config.rules = Some(rules.trim().to_string());
+                    config.welcome = true;
+                }
+                "--room" => config.rooms.push(room_name(&flag, value()?)?),
+                "--default-room" => config.default_room = Some(room_name(&flag, value()?)?),
+                "--join-creates" => config.join_creates = Creation::parse(&value()?)
+                    .ok_or("--join-creates expects off, users, or admins")?,
+                "--daemon" => config.daemon = true,
+                "--pid-file" => config.pid_file = value()?,
+                "--log-file" => config.log_file = value()?,
+                "-h" | "--help" => return Err(USAGE.into()),
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        match mqtt.addr.is_empty() {
+            true if mqtt.user.is_some() || mqtt.password.is_some() => return Err("--mqtt-user and --mqtt-password-file need --mqtt".into()),
+            true => {}
+            false => config.mqtt = Some(mqtt),
+        }
+        match smtp.addr.is_empty() {
+            true if !smtp.from.is_empty() || smtp.user.is_some() || smtp.password.is_some() => return Err("--smtp-from, --smtp-user, and --smtp-password-file need --smtp".into()),
+            true => {}
+            false if !mail::valid_address(&smtp.from) => return Err("--smtp needs --smtp-from with the sender address".into()),
+            false => config.smtp = Some(smtp),
+        }
+        match translate.url.is_empty() {
+            true if translate.api != Api::Libre || translate.key.is_some() => return Err("--translate-api and --translate-key-file need --translate".into()),
+            true => {}
+            false if reqwest::Url::parse(&translate.url).map_or(true, |u| !matches!(u.scheme(), "http" | "https")) => return Err("--translate expects an http or https URL".into()),
+            false => config.translate = Some(translate),
+        }
+        if config.oidc_issuer.is_some() != config.oidc_audience.is_some() {
+            return Err("--oidc-issuer and --oidc-audience go together".into());
+        }
+        if config.onion {
+            config.tor = true;
+        }
+        if config.tor {
+            config.request_timeout = config.request_timeout.max(TOR_REQUEST_TIMEOUT);
+        }
+        Ok(config)
+    }
+}
+
+fn bytes(flag: &str, raw: &str) -> Result<u64, String> {
+    quota::parse_size(raw).ok_or(format!("{} expects a size like 512k, 20m, or 1g, got {}", flag, raw))
+}
+
+fn room_name(flag: &str, raw: String) -> Result<String, String> {
+    match room::valid_name(&raw) {
+        true => Ok(raw),
+        false => Err(format!("{} expects a room name without spaces, commas, = or &, got {}", flag, raw)),
+    }
+}
+
+fn count(flag: &str, raw: &str) -> Result<usize, String> {
+    match raw.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} expects a positive number, got {}", flag, raw)),
+    }
+}
+
+pub fn state(config: &Config, clock: Arc<dyn Clock>) -> web::Data<State> {
+    web::Data::new(State {
+        store: Arc::new(Memory::new(clock.clone())),
+        hub: Arc::new(Hub::new()),
+        commands: Arc::new(Commands::builtin()),
+        dedup: Arc::new(Dedup::new(Duration::from_secs(600), clock.clone())),
+        stats: Arc::new(Stats::new()),
+        pow: Arc::new(Pow::new(config.pow_bits, Duration::from_secs(120), clock.clone())),
+        oidc: config.oidc_issuer.as_ref().zip(config.oidc_audience.as_ref())
+            .map(|(issuer, audience)| Arc::new(Oidc::new(issuer, audience, config.oidc_alg, config.oidc_admin_role.as_deref(), clock.clone()))),
+        replay: Arc::new(Replay::new(Duration::from_secs(86400), clock.clone())),
+        federation: Arc::new(Federation::new(&config.name)),
+        draining: Arc::new(AtomicBool::new(false)),
+        quota: Arc::new(Quota::new(config.quota, config.file_quota, clock.clone())),
+        welcome: config.welcome.then(|| Arc::new(Welcome::new(config.rules.clone()))),
+        hooks: Arc::new(Hooks::new()),
+        online: Arc::new(Online::new(clock.clone())),
+        feeds: Arc::new(Feeds::new(config.feed_interval)),
+        translator: config.translate.clone().map(|backend| Arc::new(Translator::new(backend))),
+        unfurl: config.unfurl.then(|| Arc::new(Unfurler::new())),
+        push: config.push.then(|| Arc::new(Relay::new(config.push_hosts.clone()))),
+        mail: config.smtp.clone().map(|smtp| Arc::new(Mail::new(smtp, config.email_after, clock.clone()))),
+        rooms: Arc::new(Rooms::new(config.rooms.clone(), config.default_room.clone(), config.join_creates)),
+        recorder: config.record.as_deref().map(|path| Arc::new(Recorder::new(path, clock.clone()))),
+        bans: Arc::new(Bans::new(clock.clone())),
+        names: config.duplicate_names,
+        admins: config.admins.clone(),
+        deadlines: if config.tor { Deadlines::TOR } else { Deadlines::DIRECT },
+        clock,
+    })
+}
+
+async fn start(config: &Config, state: &web::Data<State>) -> std::io::Result<()> {
+    room::open(state).await.map_err(std::io::Error::other)?;
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = &config.grpc {
+        let addr = addr.parse().map_err(|e| std::io::Error::other(format!("--grpc {}: {}", addr, e)))?;
+        let state = state.clone();
+        task::spawn("grpc", async move {
+            if let Err(err) = grpc::serve(addr, state).await {
+                xlog!(Level::Error, target: "grpc", "gRPC server stopped: {}", err);
+            }
+        });
+    }
+    federation::start(state.clone());
+    if let Some(broker) = &config.mqtt {
+        mqtt::start(state.clone(), broker.clone());
+    }
+    mail::start(state.clone());
+    presence::start(state.clone());
+    push::start(state.clone());
+    feed::start(state.clone());
+    unfurl::start(state.clone());
+    retention::start(state.clone());
+    ban::start(state.clone());
+    Ok(())
+}
+
+fn sweep(state: web::Data<State>) {
+    task::spawn("sweep", async move {
+        let mut ticker = tokio::time::interval(SWEEP_EVERY);
+        loop {
+            ticker.tick().await;
+            let swept = state.pow.prune() + state.dedup.prune() + state.replay.prune();
+            if swept > 0 {
+                xlog!(Level::Debug, target: "sweep", "dropped {} expired challenges, request ids, and nonces", swept);
+            }
+        }
+    });
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route("/entities", web::post().to(api::create))
+        .route("/entities", web::get().to(api::query))
+        .route("/entities/{id}", web::get().to(api::read))
+        .route("/entities/{id}", web::put().to(api::update))
+        .route("/entities/{id}", web::delete().to(api::delete))
+        .route("/eval/{id}", web::get().to(api::eval))
+        .route("/commands", web::get().to(api::commands))
+        .route("/help", web::get().to(api::help))
+        .route("/help/{name}", web::get().to(api::help))
+        .route("/rooms/default", web::get().to(room::default_room))
+        .route("/rooms/{room}/read", web::get().to(marker::show))
+        .route("/rooms/{room}/read", web::put().to(marker::mark))
+        .route("/stats", web::get().to(api::stats))
+        .route("/healthz", web::get().to(health::healthz))
+        .route("/challenge", web::get().to(pow::challenge))
+        .route("/login", web::post().to(account::login))
+        .route("/invites", web::post().to(invite::create))
+        .route("/invites/{token}", web::post().to(invite::redeem))
+        .route("/backup", web::get().to(backup::export))
+        .service(web::resource("/restore")
+            .app_data(web::PayloadConfig::new(backup::MAX_SNAPSHOT))
+            .route(web::post().to(backup::import)))
+        .route("/federation/inbox", web::post().to(federation::inbox))
+        .route("/hooks/{room}", web::post().to(webhook::ingest))
+        .route("/ws", web::get().to(ws::handler))
+        .route("/console", web::get().to(console::page));
+}
+
+pub struct Builder {
+    config: Config,
+    state: Option<web::Data<State>>,
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl Builder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.config.bind = addr.into();
+        self
+    }
+
+    pub fn state(mut self, state: web::Data<State>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub async fn build(self) -> std::io::Result<Server> {
+        let config = self.config;
+        let state = self.state.unwrap_or_else(|| state(&config, self.clock.unwrap_or_else(time::system)));
+        let inherited = match config.handover.as_deref() {
+            Some(path) => handover::take_over(path)?,
+            None => None,
+        };
+        if let Some(recorder) = &state.recorder {
+            recorder.open()?;
+        }
+        let listener = match inherited {
+            Some((listener, entities)) => {
+                xlog!(Level::Info, target: "handover", "took over {} with {} entities", config.bind, entities.len());
+                state.store.restore(entities).await.map_err(|e| std::io::Error::other(e.to_string()))?;
+                listener
+            }
+            None => TcpListener::bind(&config.bind)?,
+        };
+        let local_addr = listener.local_addr()?;
+        xlog!(Level::Info, "morce server starting on http://{}", local_addr);
+        sweep(state.clone());
+        if config.echo {
+            xlog!(Level::Info, target: "echo", "echo mode: frames sent to /ws go back to their sender only");
+        } else {
+            xlog!(Level::Info, "open http://{}/console for the terminal", local_addr);
+            start(&config, &state).await?;
+        }
+        if config.onion {
+            let address = tor::publish(&config.tor_control, &config.onion_key, &local_addr.to_string()).await?;
+            xlog!(Level::Info, target: "tor", "onion service at http://{}/console", address);
+        }
+
+        let routes: fn(&mut web::ServiceConfig) = if config.echo { echo::routes } else { routes };
+        let app_state = state.clone();
+        let mut server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .wrap(from_fn(record::capture))
+                .wrap(from_fn(ban::reject))
+                .configure(routes)
+        })
+            .client_request_timeout(config.request_timeout)
+            .client_disconnect_timeout(Duration::from_secs(if config.tor { 5 } else { 1 }))
+            .keep_alive(Duration::from_secs(if config.tor { 60 } else { 15 }))
+            .max_connections(config.max_connections);
+        if config.current_thread {
+            server = server.workers(1);
+        } else if let Some(workers) = config.workers {
+            server = server.workers(workers);
+        }
+        let running = server.listen(listener.try_clone()?)?.run();
+        if let Some(path) = &config.handover {
+            handover::listen(path, listener, state.clone(), running.handle())?;
+        }
+        Ok(Server { local_addr, state, running })
+    }
+}
+
+#[derive(Clone)]
+pub struct ShutdownHandle(ServerHandle);
+
+impl ShutdownHandle {
+    pub async fn shutdown(&self) {
+        self.0.stop(false).await
+    }
+}
+
+pub struct Server {
+    local_addr: SocketAddr,
+    state: web::Data<State>,
+    running: actix_web::dev::Server,
+}
+
+impl Server {
+    pub fn builder() -> Builder {
+        Builder { config: Config::default(), state: None, clock: None }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn state(&self) -> &web::Data<State> {
+        &self.state
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.running.handle())
+    }
+
+    pub async fn run(self) -> std::io::Result<()> {
+        self.running.await
+    }
+}
+
+pub async fn serve(config: &Config, state: web::Data<State>) -> std::io::Result<()> {
+    Server::builder().config(config.clone()).state(state).build().await?.run().await
+}
+
+pub fn run(config: Config) -> std::io::Result<()> {
+    let _pid = if config.daemon {
+        Some(daemon::detach(&config.pid_file, &config.log_file)?)
+    } else {
+        None
+    };
+    let system = if config.current_thread {
+        actix_web::rt::System::new()
+    } else {
+        let threads = config.threads;
+        actix_web::rt::System::with_tokio_rt(move || {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            if let Some(threads) = threads {
+                builder.worker_threads(threads);
+            }
+            builder.enable_all().build().expect("failed to build tokio runtime")
+        })
+    };
+    system.block_on(serve(&config, state(&config, time::system())))
+}
@@ -0,0 +1,87 @@
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::entity::Entity;
+
+pub struct Stats {
+    pub received: AtomicU64,
+    pub pushed: AtomicU64,
+    pub bytes_in: AtomicU64,
+    pub bytes_out: AtomicU64,
+    pub connections: AtomicI64,
+    pub replayed: AtomicU64,
+    kinds: DashMap<String, AtomicU64>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            received: AtomicU64::new(0),
+            pushed: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            connections: AtomicI64::new(0),
+            replayed: AtomicU64::new(0),
+            kinds: DashMap::new(),
+        }
+    }
+
+    pub fn record_in(&self, entity: &Entity) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(entity.load.len() as u64, Ordering::Relaxed);
+        let kind = entity.tags.get("kind").map(String::as_str).unwrap_or("-");
+        self.kinds.entry(kind.to_string()).or_default().fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_push(&self, bytes: usize) {
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_out(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_replay(&self) {
+        self.replayed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connect(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn disconnect(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let mut rows = vec![
+            vec!["connections".into(), self.connections.load(Ordering::Relaxed).to_string()],
+            vec!["received".into(), self.received.load(Ordering::Relaxed).to_string()],
+            vec!["pushed".into(), self.pushed.load(Ordering::Relaxed).to_string()],
+            vec!["bytes_in".into(), self.bytes_in.load(Ordering::Relaxed).to_string()],
+            vec!["bytes_out".into(), self.bytes_out.load(Ordering::Relaxed).to_string()],
+            vec!["replayed".into(), self.replayed.load(Ordering::Relaxed).to_string()],
+        ];
+        let mut kinds: Vec<Vec<String>> = self.kinds.iter()
+            .map(|e| vec![format!("kind.{}", e.key()), e.value().load(Ordering::Relaxed).to_string()])
+            .collect();
+        kinds.sort();
+        rows.extend(kinds);
+        rows
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for row in self.rows() {
+            out.push_str(&format!("{} {}\n", row[0], row[1]));
+        }
+        out
+    }
+}
@@ -34,9 +34,12 @@ impl actix_web::ResponseError for Error {
 #[async_trait]
 pub trait Store: Send + Sync {
     async fn create(&self, load: bytes::Bytes, tags: BTreeMap<String, String>) -> Result<Entity, Error>;
+    fn now(&self) -> u64;
     async fn read(&self, id: Uuid) -> Result<Option<Entity>, Error>;
     async fn update(&self, id: Uuid, load: bytes::Bytes, tags: BTreeMap<String, String>) -> Result<Entity, Error>;
     async fn delete(&self, id: Uuid) -> Result<(), Error>;
     async fn query(&self, predicate: &Predicate) -> Result<Vec<Entity>, Error>;
     async fn query_prefix(&self, key: &str, value: &str) -> Result<Vec<Entity>, Error>;
+    async fn snapshot(&self) -> Result<Vec<Entity>, Error>;
+    async fn restore(&self, entities: Vec<Entity>) -> Result<(), Error>;
 }
\ No newline at end of file
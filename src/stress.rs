@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::client;
+use crate::layout;
+use crate::task;
+use crate::time::{self, Clock};
+use crate::transport::{Faults, Faulty, Rng, Tcp, Transport};
+
+pub const USAGE: &str = "usage: morce stress [--target ADDR] [--clients N] [--rate N] [--duration SECS] [--disconnect P] [--oversize P] [--latency MS] [--jitter MS] [--partial P] [--drop P] [--socks ADDR]";
+
+const OVERSIZE: usize = 1024 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SOCKS_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+pub struct Options {
+    pub target: String,
+    pub clients: usize,
+    pub rate: f64,
+    pub duration: Duration,
+    pub disconnect: f64,
+    pub oversize: f64,
+    pub faults: Faults,
+    pub socks: Option<String>,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            target: "127.0.0.1:8080".into(),
+            clients: 10,
+            rate: 5.0,
+            duration: Duration::from_secs(10),
+            disconnect: 0.0,
+            oversize: 0.0,
+            faults: Faults::default(),
+            socks: None,
+            clock: time::system(),
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let value = inline.or_else(|| args.next()).ok_or(format!("{} needs a value", flag))?;
+            let number = |raw: &str| raw.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+                .ok_or(format!("{} expects a number, got {}", flag, raw));
+            match flag.as_str() {
+                "--target" => options.target = value,
+                "--clients" => options.clients = number(&value)?.max(1.0) as usize,
+                "--rate" => options.rate = number(&value)?,
+                "--duration" => options.duration = Duration::from_secs_f64(number(&value)?),
+                "--disconnect" => options.disconnect = number(&value)?.min(1.0),
+                "--oversize" => options.oversize = number(&value)?.min(1.0),
+                "--latency" => options.faults.latency = Duration::from_secs_f64(number(&value)? / 1000.0),
+                "--jitter" => options.faults.jitter = Duration::from_secs_f64(number(&value)? / 1000.0),
+                "--partial" => options.faults.partial = number(&value)?.min(1.0),
+                "--drop" => options.faults.drop = number(&value)?.min(1.0),
+                "--socks" => options.socks = Some(value),
+                _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+            }
+        }
+        if options.rate <= 0.0 {
+            return Err("--rate must be above zero".into());
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub latencies: Vec<Duration>,
+    pub statuses: BTreeMap<String, u64>,
+    pub received: u64,
+    pub reconnects: u64,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    fn merge(&mut self, other: Report) {
+        self.latencies.extend(other.latencies);
+        for (status, n) in other.statuses {
+            *self.statuses.entry(status).or_default() += n;
+        }
+        self.received += other.received;
+        self.reconnects += other.reconnects;
+    }
+
+    fn percentile(&self, p: f64) -> String {
+        if self.latencies.is_empty() {
+            return "-".into();
+        }
+        let idx = ((self.latencies.len() - 1) as f64 * p).round() as usize;
+        format!("{:.1}ms", self.latencies[idx].as_secs_f64() * 1000.0)
+    }
+
+    pub fn render(&mut self) -> String {
+        self.latencies.sort();
+        let sent: u64 = self.statuses.values().sum();
+        let secs = self.elapsed.as_secs_f64().max(0.001);
+        let mut rows = vec![
+            vec!["sent".into(), sent.to_string()],
+            vec!["rate".into(), format!("{:.1}/s", sent as f64 / secs)],
+            vec!["p50".into(), self.percentile(0.50)],
+            vec!["p90".into(), self.percentile(0.90)],
+            vec!["p99".into(), self.percentile(0.99)],
+            vec!["max".into(), self.percentile(1.0)],
+            vec!["pushed_bytes".into(), self.received.to_string()],
+            vec!["reconnects".into(), self.reconnects.to_string()],
+        ];
+        for (status, n) in &self.statuses {
+            rows.push(vec![format!("status.{}", status), n.to_string()]);
+        }
+        layout::table(&["METRIC", "VALUE"], &rows)
+    }
+}
+
+pub async fn run(options: Options) -> Report {
+    let tcp = Tcp::new(options.socks.clone());
+    let transport: Arc<dyn Transport> = match options.faults.any() {
+        true => Arc::new(Faulty::new(tcp, options.faults)),
+        false => Arc::new(tcp),
+    };
+    run_over(options, transport).await
+}
+
+pub async fn run_over(options: Options, transport: Arc<dyn Transport>) -> Report {
+    let started = options.clock.instant();
+    let run = Uuid::new_v4().to_string();
+    let tasks: Vec<_> = (0..options.clients)
+        .map(|n| task::spawn(&format!("stress.client {}", n), client(options.clone(), transport.clone(), run.clone(), n)))
+        .collect();
+    let mut report = Report::default();
+    for task in tasks {
+        if let Ok(result) = task.await {
+            report.merge(result);
+        }
+    }
+    report.elapsed = options.clock.since(started);
+    report
+}
+
+async fn client(options: Options, transport: Arc<dyn Transport>, run: String, n: usize) -> Report {
+    let mut report = Report::default();
+    let mut rng = Rng::new();
+    let filter = format!("kind=stress&run={}", run);
+    let received = Arc::new(AtomicU64::new(0));
+    let mut watcher = task::spawn(&format!("stress.watch {}", n), watch(transport.clone(), options.target.clone(), filter.clone(), received.clone()));
+    let deadline = options.clock.instant() + options.duration;
+    let timeout = if options.socks.is_some() { SOCKS_REQUEST_TIMEOUT } else { REQUEST_TIMEOUT };
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / options.rate));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    while options.clock.instant() < deadline {
+        ticker.tick().await;
+        let body = if rng.chance(options.oversize) {
+            vec![b'x'; OVERSIZE]
+        } else {
+            format!("client {} at {:?}", n, options.clock.instant()).into_bytes()
+        };
+        let tags = format!("kind=stress,run={},client={}", run, n);
+        let started = options.clock.instant();
+        let status = match tokio::time::timeout(timeout, post(&*transport, &options.target, &tags, &body)).await {
+            Ok(Ok(status)) => status,
+            Ok(Err(err)) => format!("io:{:?}", err.kind()),
+            Err(_) => "timeout".into(),
+        };
+        if status.starts_with('2') {
+            report.latencies.push(options.clock.since(started));
+        }
+        *report.statuses.entry(status).or_default() += 1;
+
+        if watcher.is_finished() || rng.chance(options.disconnect) {
+            watcher.abort();
+            report.reconnects += 1;
+            watcher = task::spawn(&format!("stress.watch {}", n), watch(transport.clone(), options.target.clone(), filter.clone(), received.clone()));
+        }
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    watcher.abort();
+    report.received = received.load(Ordering::Relaxed);
+    report
+}
+
+async fn post(transport: &dyn Transport, target: &str, tags: &str, body: &[u8]) -> std::io::Result<String> {
+    let mut stream = transport.connect(target).await?;
+    let head = format!(
+        "POST /entities HTTP/1.1\r\nHost: {}\r\nx-tags: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        target, tags, body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    let _ = stream.write_all(body).await;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let line = String::from_utf8_lossy(&response);
+    Ok(line.split(' ').nth(1).unwrap_or("bad").to_string())
+}
+
+async fn watch(transport: Arc<dyn Transport>, target: String, filter: String, received: Arc<AtomicU64>) {
+    let path = match client::challenge(&*transport, &target).await {
+        Ok(Some(solution)) => format!("/ws?pow={}", solution),
+        Ok(None) => "/ws".into(),
+        Err(_) => return,
+    };
+    let Ok(mut stream) = transport.connect(&target).await else {
+        return;
+    };
+    let key = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, Uuid::new_v4().as_bytes());
+    let head = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path, target, key,
+    );
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    let mask = *Uuid::new_v4().as_bytes();
+    let mut frame = vec![0x81, 0x80 | filter.len() as u8];
+    frame.extend_from_slice(&mask[..4]);
+    frame.extend(filter.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    if stream.write_all(&frame).await.is_err() {
+        return;
+    }
+    let mut buf = [0u8; 8192];
+    while let Ok(n) = stream.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        received.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
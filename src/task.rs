@@ -0,0 +1,46 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+#[cfg(all(feature = "tokio-console", not(tokio_unstable)))]
+compile_error!("the tokio-console feature needs RUSTFLAGS=\"--cfg tokio_unstable\"");
+
+#[cfg(feature = "tokio-console")]
+pub fn spawn<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::task::Builder::new().name(name).spawn(future).expect("failed to spawn task")
+}
+
+#[cfg(not(feature = "tokio-console"))]
+pub fn spawn<F>(_name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future)
+}
+
+#[cfg(feature = "tokio-console")]
+pub fn spawn_local<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    tokio::task::Builder::new().name(name).spawn_local(future).expect("failed to spawn task")
+}
+
+#[cfg(not(feature = "tokio-console"))]
+pub fn spawn_local<F>(_name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    actix_web::rt::spawn(future)
+}
+
+pub fn init() {
+    #[cfg(feature = "tokio-console")]
+    console_subscriber::init();
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Jalali {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+const JALALI_MONTHS: [&str; 12] = [
+    "فروردین", "اردیبهشت", "خرداد", "تیر", "مرداد", "شهریور",
+    "مهر", "آبان", "آذر", "دی", "بهمن", "اسفند",
+];
+
+const PERSIAN_WEEKDAYS: [&str; 7] = ["شنبه", "یکشنبه", "دوشنبه", "سه‌شنبه", "چهارشنبه", "پنجشنبه", "جمعه"];
+
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+pub trait Clock: Send + Sync {
+    fn unix(&self) -> Duration;
+    fn instant(&self) -> Instant;
+
+    fn since(&self, at: Instant) -> Duration {
+        self.instant().saturating_duration_since(at)
+    }
+
+    fn millis(&self) -> u64 {
+        self.unix().as_millis() as u64
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn unix(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub struct TokioClock {
+    wall: Duration,
+    start: tokio::time::Instant,
+}
+
+impl TokioClock {
+    pub fn new() -> Self {
+        Self { wall: SystemClock.unix(), start: tokio::time::Instant::now() }
+    }
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TokioClock {
+    fn unix(&self) -> Duration {
+        self.wall + self.start.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+pub fn unix_now() -> Duration {
+    SystemClock.unix()
+}
+
+pub fn is_leap(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+impl Date {
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Self { year, month, day }
+    }
+
+    pub fn to_days(&self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if self.month > 2 { self.month - 3 } else { self.month + 9 } as i64;
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    pub fn today(clock: &dyn Clock) -> Self {
+        Self::from_days(clock.unix().as_secs() as i64 / 86400)
+    }
+
+    pub fn weekday(&self) -> usize {
+        (self.to_days() + 3).rem_euclid(7) as usize
+    }
+
+    pub fn format(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    pub fn format_full(&self) -> String {
+        format!("{}, {}", WEEKDAYS[self.weekday()], self.format())
+    }
+}
+
+impl Jalali {
+    pub fn from_gregorian(date: Date) -> Self {
+        const CUMULATIVE: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let gy = date.year;
+        let gy2 = if date.month > 2 { gy + 1 } else { gy };
+        let mut days = 355666 + 365 * gy + (gy2 + 3) / 4 - (gy2 + 99) / 100 + (gy2 + 399) / 400
+            + date.day as i64 + CUMULATIVE[date.month as usize - 1];
+
+        let mut year = -1595 + 33 * (days / 12053);
+        days %= 12053;
+        year += 4 * (days / 1461);
+        days %= 1461;
+        if days > 365 {
+            year += (days - 1) / 365;
+            days = (days - 1) % 365;
+        }
+        let (month, day) = if days < 186 {
+            (1 + days / 31, 1 + days % 31)
+        } else {
+            (7 + (days - 186) / 30, 1 + (days - 186) % 30)
+        };
+        Self { year, month: month as u32, day: day as u32 }
+    }
+
+    pub fn to_gregorian(&self) -> Date {
+        let jy = self.year + 1595;
+        let month_days = if self.month < 7 {
+            (self.month as i64 - 1) * 31
+        } else {
+            (self.month as i64 - 7) * 30 + 186
+        };
+        let mut days = -355668 + 365 * jy + (jy / 33) * 8 + (jy % 33 + 3) / 4 + self.day as i64 + month_days;
+
+        let mut year = 400 * (days / 146097);
+        days %= 146097;
+        if days > 36524 {
+            days -= 1;
+            year += 100 * (days / 36524);
+            days %= 36524;
+            if days >= 365 {
+                days += 1;
+            }
+        }
+        year += 4 * (days / 1461);
+        days %= 1461;
+        if days > 365 {
+            year += (days - 1) / 365;
+            days = (days - 1) % 365;
+        }
+
+        let lengths = [31, if is_leap(year) { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        let mut day = days + 1;
+        let mut month = 1;
+        for length in lengths {
+            if day <= length {
+                break;
+            }
+            day -= length;
+            month += 1;
+        }
+        Date { year, month, day: day as u32 }
+    }
+
+    pub fn today(clock: &dyn Clock) -> Self {
+        Self::from_gregorian(Date::today(clock))
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        JALALI_MONTHS[(self.month as usize).clamp(1, 12) - 1]
+    }
+
+    pub fn format(&self) -> String {
+        persian_digits(&format!("{} {} {}", self.year, self.month_name(), self.day))
+    }
+
+    pub fn format_full(&self) -> String {
+        let weekday = (self.to_gregorian().weekday() + 2) % 7;
+        persian_digits(&format!("{} {} {} {}", PERSIAN_WEEKDAYS[weekday], self.day, self.month_name(), self.year))
+    }
+}
+
+pub fn persian_digits(text: &str) -> String {
+    text.chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) => char::from_u32(0x06F0 + d).unwrap_or(c),
+            None => c,
+        })
+        .collect()
+}
+
+pub fn timestamp() -> String {
+    iso(unix_now())
+}
+
+pub fn iso_millis(millis: u64) -> String {
+    iso(std::time::Duration::from_millis(millis))
+}
+
+pub fn iso(now: std::time::Duration) -> String {
+    let secs = now.as_secs() as i64;
+    let date = Date::from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        date.format(), rem / 3600, rem % 3600 / 60, rem % 60, now.subsec_millis()
+    )
+}
+
+pub fn rfc2822(now: std::time::Duration) -> String {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let secs = now.as_secs() as i64;
+    let date = Date::from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        &WEEKDAYS[date.weekday()][..3], date.day, MONTHS[date.month as usize - 1], date.year, rem / 3600, rem % 3600 / 60, rem % 60
+    )
+}
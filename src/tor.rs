@@ -0,0 +1,131 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::log::Level;
+use crate::task;
+use crate::xlog;
+
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub async fn connect(target: &str, socks: Option<&str>) -> std::io::Result<TcpStream> {
+    let Some(proxy) = socks else {
+        return TcpStream::connect(target).await;
+    };
+    let (host, port) = target.rsplit_once(':')
+        .and_then(|(host, port)| Some((host.trim_matches(['[', ']']), port.parse::<u16>().ok()?)))
+        .ok_or(Error::new(ErrorKind::InvalidInput, "target must be host:port"))?;
+    if host.len() > 255 {
+        return Err(Error::new(ErrorKind::InvalidInput, "host name too long"));
+    }
+
+    let mut stream = TcpStream::connect(proxy).await?;
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [5, 0] {
+        return Err(Error::other("socks proxy wants authentication"));
+    }
+
+    let mut request = vec![5, 1, 0, 3, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        return Err(Error::new(ErrorKind::ConnectionRefused, format!("socks connect failed with code {}", head[1])));
+    }
+    let skip = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        _ => return Err(Error::other("socks reply has unknown address type")),
+    };
+    let mut bound = vec![0u8; skip + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(stream)
+}
+
+struct Control {
+    reader: BufReader<TcpStream>,
+}
+
+impl Control {
+    async fn send(&mut self, command: &str) -> std::io::Result<Vec<String>> {
+        self.reader.get_mut().write_all(format!("{}\r\n", command).as_bytes()).await?;
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).await? == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "tor closed the control connection"));
+            }
+            let line = line.trim_end().to_string();
+            let done = line.as_bytes().get(3) == Some(&b' ');
+            lines.push(line);
+            if done {
+                break;
+            }
+        }
+        match lines.last() {
+            Some(last) if last.starts_with("250") => Ok(lines),
+            Some(last) => Err(Error::other(format!("tor refused {}: {}", command.split(' ').next().unwrap_or(""), last))),
+            None => Err(Error::other("empty reply from tor")),
+        }
+    }
+
+    async fn authenticate(&mut self) -> std::io::Result<()> {
+        let info = self.send("PROTOCOLINFO 1").await?;
+        let auth = info.iter().find_map(|l| l.strip_prefix("250-AUTH ")).unwrap_or("");
+        let methods = auth.split_whitespace().next().and_then(|m| m.strip_prefix("METHODS=")).unwrap_or("");
+        if methods.split(',').any(|m| m == "NULL") {
+            self.send("AUTHENTICATE").await?;
+            return Ok(());
+        }
+        let Some(path) = auth.split("COOKIEFILE=\"").nth(1).and_then(|rest| rest.split('"').next()) else {
+            return Err(Error::new(ErrorKind::PermissionDenied, format!("unsupported tor auth methods {}", methods)));
+        };
+        let cookie = tokio::fs::read(path.replace("\\\\", "\\")).await?;
+        let hex: String = cookie.iter().map(|b| format!("{:02x}", b)).collect();
+        self.send(&format!("AUTHENTICATE {}", hex)).await?;
+        Ok(())
+    }
+}
+
+pub async fn publish(control: &str, key_path: &str, bind: &str) -> std::io::Result<String> {
+    tokio::time::timeout(CONTROL_TIMEOUT, add_onion(control, key_path, bind)).await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "tor control port did not answer"))?
+}
+
+async fn add_onion(control: &str, key_path: &str, bind: &str) -> std::io::Result<String> {
+    let stream = TcpStream::connect(control).await?;
+    let mut control = Control { reader: BufReader::new(stream) };
+    control.authenticate().await?;
+
+    let stored = tokio::fs::read_to_string(key_path).await.ok().map(|k| k.trim().to_string());
+    let key = stored.clone().unwrap_or_else(|| "NEW:ED25519-V3".into());
+    let reply = control.send(&format!("ADD_ONION {} Port=80,{}", key, bind)).await?;
+    let field = |name: &str| reply.iter().find_map(|l| l.strip_prefix(&format!("250-{}=", name)).map(str::to_string));
+    let service = field("ServiceID").ok_or(Error::other("tor did not return a service id"))?;
+
+    if stored.is_none() {
+        if let Some(private) = field("PrivateKey") {
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            options.open(key_path).await?.write_all(private.as_bytes()).await?;
+            xlog!(Level::Info, target: "tor", "saved onion key to {}", key_path);
+        }
+    }
+
+    task::spawn("tor.control", async move {
+        let mut sink = String::new();
+        while matches!(control.reader.read_line(&mut sink).await, Ok(n) if n > 0) {
+            sink.clear();
+        }
+        xlog!(Level::Warn, target: "tor", "control connection closed, onion service is gone");
+    });
+    Ok(format!("{}.onion", service))
+}
@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::account;
+use crate::command::{Category, Command, Context, Help, Role};
+use crate::locale::{tr, trf};
+use crate::store::Store;
+
+const TRANSLATE_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_TEXT: usize = 4000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Api {
+    Libre,
+    DeepL,
+}
+
+impl Api {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "libre" | "libretranslate" => Some(Api::Libre),
+            "deepl" => Some(Api::DeepL),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Api {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Api::Libre => "libre",
+            Api::DeepL => "deepl",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Backend {
+    pub url: String,
+    pub api: Api,
+    pub key: Option<String>,
+}
+
+pub struct Translator {
+    client: reqwest::Client,
+    backend: Backend,
+}
+
+impl Translator {
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(TRANSLATE_TIMEOUT)
+                .user_agent(concat!("morce/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap_or_default(),
+            backend,
+        }
+    }
+
+    pub async fn translate(&self, text: &str, lang: &str) -> Result<(String, Option<String>), String> {
+        let base = self.backend.url.trim_end_matches('/');
+        let request = match self.backend.api {
+            Api::Libre => self.client.post(format!("{}/translate", base)).json(&json!({
+                "q": text,
+                "source": "auto",
+                "target": lang,
+                "format": "text",
+                "api_key": self.backend.key.as_deref().unwrap_or_default(),
+            })),
+            Api::DeepL => self.client.post(format!("{}/v2/translate", base))
+                .header("Authorization", format!("DeepL-Auth-Key {}", self.backend.key.as_deref().unwrap_or_default()))
+                .json(&json!({ "text": [text], "target_lang": lang.to_uppercase() })),
+        };
+        let response = request.send().await.map_err(|e| e.without_url().to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("{} answered {}", self.backend.api, response.status()));
+        }
+        let body: Value = response.json().await.map_err(|e| e.without_url().to_string())?;
+        let (text, from) = match self.backend.api {
+            Api::Libre => (body.get("translatedText"), body.pointer("/detectedLanguage/language")),
+            Api::DeepL => (body.pointer("/translations/0/text"), body.pointer("/translations/0/detected_source_language")),
+        };
+        let text = text.and_then(Value::as_str).ok_or(format!("{} sent no translation", self.backend.api))?;
+        Ok((text.to_string(), from.and_then(Value::as_str).map(str::to_lowercase)))
+    }
+}
+
+pub fn valid_lang(lang: &str) -> bool {
+    (2..=8).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+pub struct Translate;
+
+#[async_trait]
+impl Command for Translate {
+    fn help(&self) -> Help {
+        Help { category: Category::Chat, role: Role::Anyone, usage: "<lang> <message_id|text>", about: "help.translate" }
+    }
+
+    fn private(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        let Some(translator) = &ctx.state.translator else {
+            return Err(tr("translate.disabled").into());
+        };
+        let mut split = args.splitn(2, char::is_whitespace);
+        let (lang, rest) = (split.next().unwrap_or_default(), split.next().unwrap_or_default().trim());
+        if !valid_lang(lang) || rest.is_empty() {
+            return Err(tr("translate.usage").into());
+        }
+        let text = match rest.parse::<Uuid>() {
+            Ok(id) => {
+                let message = ctx.state.store.read(id).await.map_err(|e| e.to_string())?
+                    .filter(|e| !account::secret(e) && e.tags.get("kind").map(String::as_str) == Some("msg"))
+                    .ok_or(trf("translate.missing", &[rest]))?;
+                String::from_utf8_lossy(&message.load).into_owned()
+            }
+            Err(_) => rest.to_string(),
+        };
+        if text.trim().is_empty() {
+            return Err(tr("translate.empty").into());
+        }
+        if text.len() > MAX_TEXT {
+            return Err(trf("translate.long", &[&MAX_TEXT.to_string()]));
+        }
+        let (translated, from) = translator.translate(&text, lang).await.map_err(|e| trf("translate.failed", &[&e]))?;
+        let lang = lang.to_lowercase();
+        Ok(match from {
+            Some(from) if from != lang => trf("translate.from", &[&from, &lang, &translated]),
+            _ => trf("translate.into", &[&lang, &translated]),
+        })
+    }
+}
+
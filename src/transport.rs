@@ -0,0 +1,281 @@
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use tokio::sync::mpsc;
+use tokio::time::Sleep;
+use uuid::Uuid;
+
+use crate::tor;
+
+const DUPLEX_BUFFER: usize = 64 * 1024;
+const READ_CHUNK: usize = 8192;
+
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4().as_u128() as u64 | 1)
+    }
+
+    pub fn fraction(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.fraction() < p
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub trait Wire: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Wire for T {}
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn connect(&self, target: &str) -> std::io::Result<Box<dyn Wire>>;
+}
+
+pub struct Tcp {
+    socks: Option<String>,
+}
+
+impl Tcp {
+    pub fn direct() -> Self {
+        Self { socks: None }
+    }
+
+    pub fn new(socks: Option<String>) -> Self {
+        Self { socks }
+    }
+}
+
+#[async_trait]
+impl Transport for Tcp {
+    async fn connect(&self, target: &str) -> std::io::Result<Box<dyn Wire>> {
+        Ok(Box::new(tor::connect(target, self.socks.as_deref()).await?))
+    }
+}
+
+pub struct Listener {
+    rx: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl Listener {
+    pub async fn accept(&mut self) -> Option<DuplexStream> {
+        self.rx.recv().await
+    }
+}
+
+#[derive(Default)]
+pub struct Duplex {
+    listeners: DashMap<String, mpsc::UnboundedSender<DuplexStream>>,
+}
+
+impl Duplex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn listen(&self, target: &str) -> Listener {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.listeners.insert(target.to_string(), tx);
+        Listener { rx }
+    }
+}
+
+#[async_trait]
+impl Transport for Duplex {
+    async fn connect(&self, target: &str) -> std::io::Result<Box<dyn Wire>> {
+        let refused = || Error::new(ErrorKind::ConnectionRefused, format!("nothing listens on {}", target));
+        let listener = self.listeners.get(target).ok_or_else(refused)?;
+        let (near, far) = tokio::io::duplex(DUPLEX_BUFFER);
+        listener.send(far).map_err(|_| refused())?;
+        Ok(Box::new(near))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Faults {
+    pub latency: Duration,
+    pub jitter: Duration,
+    pub partial: f64,
+    pub drop: f64,
+}
+
+impl Faults {
+    pub fn any(&self) -> bool {
+        !self.latency.is_zero() || !self.jitter.is_zero() || self.partial > 0.0 || self.drop > 0.0
+    }
+}
+
+pub struct Faulty<T> {
+    inner: T,
+    faults: Faults,
+}
+
+impl<T: Transport> Faulty<T> {
+    pub fn new(inner: T, faults: Faults) -> Self {
+        Self { inner, faults }
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for Faulty<T> {
+    async fn connect(&self, target: &str) -> std::io::Result<Box<dyn Wire>> {
+        let inner = self.inner.connect(target).await?;
+        Ok(Box::new(Impaired { inner, faults: self.faults, rng: Rng::new(), held: None, dead: false }))
+    }
+}
+
+struct Held {
+    until: Option<Pin<Box<Sleep>>>,
+    data: Vec<u8>,
+    at: usize,
+}
+
+struct Impaired {
+    inner: Box<dyn Wire>,
+    faults: Faults,
+    rng: Rng,
+    held: Option<Held>,
+    dead: bool,
+}
+
+impl Impaired {
+    fn cut(&mut self) -> Error {
+        self.dead = true;
+        Error::new(ErrorKind::ConnectionReset, "connection dropped by fault injection")
+    }
+}
+
+impl AsyncRead for Impaired {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        if this.dead {
+            return Poll::Ready(Err(this.cut()));
+        }
+        let held = match &mut this.held {
+            Some(held) => held,
+            None => {
+                let mut data = vec![0u8; READ_CHUNK];
+                let mut chunk = ReadBuf::new(&mut data);
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+                let n = chunk.filled().len();
+                if n == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                if this.rng.chance(this.faults.drop) {
+                    return Poll::Ready(Err(this.cut()));
+                }
+                data.truncate(n);
+                let delay = this.faults.latency + this.faults.jitter.mul_f64(this.rng.fraction());
+                let until = (!delay.is_zero()).then(|| Box::pin(tokio::time::sleep(delay)));
+                this.held.insert(Held { until, data, at: 0 })
+            }
+        };
+        if let Some(until) = &mut held.until {
+            ready!(until.as_mut().poll(cx));
+            held.until = None;
+        }
+        let n = buf.remaining().min(held.data.len() - held.at);
+        buf.put_slice(&held.data[held.at..held.at + n]);
+        held.at += n;
+        if held.at == held.data.len() {
+            this.held = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Impaired {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        if this.dead || this.rng.chance(this.faults.drop) {
+            return Poll::Ready(Err(this.cut()));
+        }
+        let len = match buf.len() > 1 && this.rng.chance(this.faults.partial) {
+            true => 1 + (this.rng.fraction() * (buf.len() - 1) as f64) as usize,
+            false => buf.len(),
+        };
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn duplex_round_trip() {
+        let duplex = Duplex::new();
+        let mut listener = duplex.listen("server");
+        let mut client = duplex.connect("server").await.unwrap();
+        let mut server = listener.accept().await.unwrap();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        server.write_all(b"pong").await.unwrap();
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        let refused = duplex.connect("elsewhere").await.err().unwrap();
+        assert_eq!(refused.kind(), ErrorKind::ConnectionRefused);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn faulty_delays_reads() {
+        let duplex = Duplex::new();
+        let mut listener = duplex.listen("server");
+        let latency = Duration::from_secs(3);
+        let faulty = Faulty::new(duplex, Faults { latency, ..Faults::default() });
+        let mut client = faulty.connect("server").await.unwrap();
+        let mut server = listener.accept().await.unwrap();
+
+        server.write_all(b"late").await.unwrap();
+        let started = tokio::time::Instant::now();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"late");
+        assert!(started.elapsed() >= latency);
+    }
+
+    #[tokio::test]
+    async fn faulty_drops_connections() {
+        let duplex = Duplex::new();
+        let _listener = duplex.listen("server");
+        let faulty = Faulty::new(duplex, Faults { drop: 1.0, ..Faults::default() });
+        let mut client = faulty.connect("server").await.unwrap();
+
+        let err = client.write_all(b"lost").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        let mut buf = [0u8; 4];
+        let err = client.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    }
+}
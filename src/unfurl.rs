@@ -0,0 +1,279 @@
+use actix_web::web;
+use async_trait::async_trait;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION};
+use reqwest::Url;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::command::{Category, Command, Commands, Context, Help, Role};
+use crate::entity::Entity;
+use crate::feed;
+use crate::guard;
+use crate::layout;
+use crate::locale::{tr, trf};
+use crate::log::Level;
+use crate::predicate::Predicate;
+use crate::retention;
+use crate::room;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_PAGE: usize = 256 << 10;
+const MAX_REDIRECTS: usize = 3;
+const MAX_FETCHES: usize = 8;
+const MAX_TITLE: usize = 200;
+const MAX_DESCRIPTION: usize = 400;
+
+pub struct Unfurler {
+    slots: Arc<Semaphore>,
+}
+
+impl Default for Unfurler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Preview {
+    url: String,
+    site: String,
+    title: String,
+    description: String,
+}
+
+impl Unfurler {
+    pub fn new() -> Self {
+        Self { slots: Arc::new(Semaphore::new(MAX_FETCHES)) }
+    }
+
+    async fn fetch(&self, raw: &str) -> Result<Preview, String> {
+        let mut url = Url::parse(raw).map_err(|e| e.to_string())?;
+        for _ in 0..=MAX_REDIRECTS {
+            let addr = check(&url).await?;
+            let mut client = reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none())
+                .no_proxy()
+                .user_agent(concat!("morce/", env!("CARGO_PKG_VERSION")));
+            if let Some(host) = url.domain() {
+                client = client.resolve(host, addr);
+            }
+            let client = client.build().map_err(|e| e.to_string())?;
+            let mut response = client.get(url.clone()).header(ACCEPT, "text/html").send().await
+                .map_err(|e| e.without_url().to_string())?;
+            if response.status().is_redirection() {
+                let location = response.headers().get(LOCATION).and_then(|v| v.to_str().ok())
+                    .ok_or("redirect without a location")?;
+                url = url.join(location).map_err(|e| e.to_string())?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(format!("answered {}", response.status()));
+            }
+            let html = response.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok())
+                .is_some_and(|kind| kind.starts_with("text/html") || kind.starts_with("application/xhtml"));
+            if !html {
+                return Err("not an HTML page".into());
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(|e| e.without_url().to_string())? {
+                body.extend_from_slice(&chunk);
+                if body.len() >= MAX_PAGE {
+                    body.truncate(MAX_PAGE);
+                    break;
+                }
+            }
+            return parse(&String::from_utf8_lossy(&body), &url).ok_or("no title".into());
+        }
+        Err("too many redirects".into())
+    }
+}
+
+pub(crate) fn public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_broadcast()
+                || v4.is_documentation() || v4.is_unspecified() || v4.is_multicast()
+                || a == 0 || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return public(IpAddr::V4(v4));
+            }
+            let s = v6.segments();
+            !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+                || (s[0] & 0xfe00) == 0xfc00
+                || (s[0] & 0xffc0) == 0xfe80
+                || (s[0] == 0x2001 && s[1] == 0x0db8)
+                || (s[0] == 0x64 && s[1] == 0xff9b))
+        }
+    }
+}
+
+pub(crate) async fn check(url: &Url) -> Result<SocketAddr, String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("not an http or https URL".into());
+    }
+    let port = url.port_or_known_default().unwrap_or_default();
+    if port != 80 && port != 443 {
+        return Err(format!("port {} is not allowed", port));
+    }
+    let host = url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = match (url.domain(), host.parse::<IpAddr>()) {
+        (Some(domain), _) => tokio::net::lookup_host((domain, port)).await.map_err(|e| e.to_string())?.collect(),
+        (None, Ok(ip)) => vec![SocketAddr::new(ip, port)],
+        (None, Err(_)) => Vec::new(),
+    };
+    match addrs.first() {
+        None => Err("host has no address".into()),
+        Some(_) if addrs.iter().any(|addr| !public(addr.ip())) => Err("host resolves to a private address".into()),
+        Some(addr) => Ok(*addr),
+    }
+}
+
+pub fn links(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .filter_map(|word| word.find("http://").or_else(|| word.find("https://")).map(|at| &word[at..]))
+        .map(|link| link.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\'']))
+        .filter(|link| Url::parse(link).is_ok_and(|url| url.host().is_some()))
+        .collect()
+}
+
+fn parse(doc: &str, url: &Url) -> Option<Preview> {
+    let head = doc.find("</head>").map_or(doc, |end| &doc[..end]);
+    let mut meta = BTreeMap::new();
+    let mut at = 0;
+    while let Some((start, end)) = feed::opening(head, "meta", at) {
+        let tag = &head[start..end];
+        let key = feed::attribute(tag, "property").or_else(|| feed::attribute(tag, "name"));
+        if let Some((key, content)) = key.zip(feed::attribute(tag, "content")) {
+            meta.entry(key.to_lowercase()).or_insert_with(|| feed::text(&content));
+        }
+        at = end;
+    }
+    let pick = |keys: &[&str]| keys.iter().filter_map(|key| meta.get(*key)).find(|v| !v.is_empty()).cloned();
+    let title = pick(&["og:title", "twitter:title"])
+        .or_else(|| feed::element(head, "title").map(feed::text))
+        .filter(|title| !title.is_empty())?;
+    Some(Preview {
+        url: url.to_string(),
+        site: pick(&["og:site_name"]).unwrap_or_else(|| url.host_str().unwrap_or_default().to_string()),
+        title: layout::truncate(&title, MAX_TITLE),
+        description: layout::truncate(&pick(&["og:description", "twitter:description", "description"]).unwrap_or_default(), MAX_DESCRIPTION),
+    })
+}
+
+fn enabled(channel: &Entity) -> bool {
+    channel.tags.get("unfurl").map(String::as_str) != Some("off")
+}
+
+async fn unfurl(state: &State, unfurler: &Unfurler, entity: &Entity) {
+    if entity.tags.get("from").is_none_or(|from| from == "system") || Commands::parse(&entity.load).is_some() {
+        return;
+    }
+    let Some(room) = entity.tags.get("in").and_then(|v| v.parse::<Uuid>().ok()) else {
+        return;
+    };
+    let text = String::from_utf8_lossy(&entity.load);
+    let Some(link) = links(&text).first().map(|l| l.to_string()) else {
+        return;
+    };
+    match state.store.read(room).await {
+        Ok(Some(channel)) if enabled(&channel) => {}
+        _ => return,
+    }
+    if retention::ephemeral(&*state.store, entity).await {
+        return;
+    }
+    let Ok(_slot) = unfurler.slots.clone().try_acquire_owned() else {
+        xlog!(Level::Debug, target: "unfurl", "busy, skipping {}", link);
+        return;
+    };
+    let preview = match unfurler.fetch(&link).await {
+        Ok(preview) => preview,
+        Err(err) => {
+            xlog!(Level::Debug, target: "unfurl", "{}: {}", link, err);
+            return;
+        }
+    };
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "preview".into());
+    tags.insert("from".into(), "system".into());
+    tags.insert("on".into(), entity.id.to_string());
+    tags.insert("in".into(), room.to_string());
+    let load = json!({
+        "url": preview.url,
+        "site": preview.site,
+        "title": preview.title,
+        "description": preview.description,
+    });
+    if let Ok(created) = state.store.create(load.to_string().into(), tags).await {
+        state.hub.publish(&created);
+    }
+}
+
+pub fn start(state: web::Data<State>) {
+    let Some(unfurler) = state.unfurl.clone() else {
+        return;
+    };
+    let mut pred = Predicate::new();
+    pred.insert("kind".into(), "msg".into());
+    let (_, mut rx) = state.hub.subscribe(pred);
+    task::spawn("unfurl", async move {
+        while let Some(entity) = rx.recv().await {
+            let (state, unfurler) = (state.clone(), unfurler.clone());
+            task::spawn("unfurl.fetch", async move {
+                unfurl(&state, &unfurler, &entity).await;
+            });
+        }
+    });
+}
+
+pub struct Unfurl;
+
+#[async_trait]
+impl Command for Unfurl {
+    fn help(&self) -> Help {
+        Help { category: Category::Rooms, role: Role::User, usage: "<room> [on|off]", about: "help.unfurl" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<String, String> {
+        if ctx.state.unfurl.is_none() {
+            return Err(tr("unfurl.disabled").into());
+        }
+        let actor = ctx.actor.ok_or(tr("unfurl.moderator"))?;
+        let mut parts = args.split_whitespace();
+        let room = parts.next().ok_or(tr("unfurl.usage"))?;
+        let store = &*ctx.state.store;
+        let channel = room::find(store, room).await.ok_or_else(|| trf("join.noroom", &[room]))?;
+        if !guard::is_moderator(store, actor, channel.id).await {
+            return Err(tr("unfurl.moderator").into());
+        }
+        let on = match (parts.next(), parts.next()) {
+            (None, _) => return Ok(trf(if enabled(&channel) { "unfurl.on" } else { "unfurl.off" }, &[room])),
+            (Some("on"), None) => true,
+            (Some("off"), None) => false,
+            _ => return Err(tr("unfurl.usage").into()),
+        };
+        let mut tags = channel.tags.clone();
+        match on {
+            true => tags.remove("unfurl"),
+            false => tags.insert("unfurl".into(), "off".into()),
+        };
+        let channel = store.update(channel.id, channel.load.clone(), tags).await.map_err(|e| e.to_string())?;
+        ctx.state.hub.publish(&channel);
+        Ok(trf(if on { "unfurl.on" } else { "unfurl.off" }, &[room]))
+    }
+}
@@ -0,0 +1,77 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::api::{self, State};
+use crate::forge::{self, Source};
+use crate::store::Store;
+
+fn extract_token(req: &HttpRequest) -> Option<Uuid> {
+    let header = req.headers().get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if let Some(token) = header {
+        return token.parse().ok();
+    }
+    req.query_string().split('&')
+        .find_map(|part| part.strip_prefix("token="))
+        .and_then(|v| v.parse().ok())
+}
+
+pub fn render(payload: &Value) -> String {
+    for key in ["text", "message", "content", "body"] {
+        if let Some(Value::String(text)) = payload.get(key) {
+            return text.clone();
+        }
+    }
+    payload.to_string()
+}
+
+pub async fn ingest(
+    state: web::Data<State>,
+    path: web::Path<Uuid>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let room = path.into_inner();
+    let token = extract_token(&req).ok_or(actix_web::error::ErrorUnauthorized("missing token"))?;
+    let hook = state.store.read(token).await?
+        .filter(|h| h.tags.get("kind").map(String::as_str) == Some("hook"))
+        .filter(|h| h.tags.get("in") == Some(&room.to_string()))
+        .ok_or(actix_web::error::ErrorUnauthorized("invalid token"))?;
+
+    let payload: Value = serde_json::from_slice(&body)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid json"))?;
+
+    let mut tags = BTreeMap::new();
+    tags.insert("kind".into(), "msg".into());
+    tags.insert("in".into(), room.to_string());
+    tags.insert("from".into(), hook.tags.get("as").cloned().unwrap_or_else(|| hook.id.to_string()));
+    tags.insert("hook".into(), hook.id.to_string());
+
+    let source = match hook.tags.get("format").map(String::as_str) {
+        Some("raw") => None,
+        Some(format) => Source::parse(format),
+        None => Source::detect(&req),
+    };
+    let text = match source {
+        Some(source) => {
+            let event = source.event(&req, &payload);
+            let (text, tone) = forge::format(source, &event, &payload);
+            tags.insert("source".into(), source.to_string());
+            tags.insert("event".into(), event.replace([',', '=', '&'], "_"));
+            tags.insert("tone".into(), tone.to_string());
+            text
+        }
+        None => render(&payload),
+    };
+
+    let actor = hook.tags.get("as").and_then(|v| v.parse::<Uuid>().ok());
+    api::check_post(&state, actor, &tags, text.as_bytes()).await?;
+    let peer = req.peer_addr().map(|addr| addr.ip().to_string());
+    let entity = api::accept(&state, actor, None, peer, tags, text.into()).await?;
+    Ok(HttpResponse::Created()
+        .insert_header(("x-entity-id", entity.id.to_string()))
+        .finish())
+}
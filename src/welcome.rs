@@ -0,0 +1,101 @@
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::api::State;
+use crate::command::{self, Category, Command, Commands, Context, Help, Role};
+use crate::entity::Entity;
+use crate::locale::{tr, trf};
+use crate::predicate::Predicate;
+use crate::store::Store;
+
+const ALLOWED: [&str; 2] = ["agree", "help"];
+
+pub struct Welcome {
+    rules: Option<String>,
+}
+
+impl Welcome {
+    pub fn new(rules: Option<String>) -> Self {
+        Self { rules }
+    }
+
+    fn rules(&self) -> String {
+        self.rules.clone().unwrap_or_else(|| tr("welcome.rules").into())
+    }
+}
+
+fn pending(user: &Entity) -> bool {
+    user.tags.get("onboarding").map(String::as_str) == Some("pending")
+}
+
+pub async fn prepare(state: &State, tags: &mut BTreeMap<String, String>) -> bool {
+    if state.welcome.is_none() || tags.get("kind").map(String::as_str) != Some("user") {
+        return false;
+    }
+    if let Some(name) = tags.get("name") {
+        let mut pred = Predicate::new();
+        pred.insert("kind".into(), "user".into());
+        pred.insert("name".into(), name.clone());
+        let known = state.store.query(&pred).await.unwrap_or_default();
+        if known.iter().any(|user| !pending(user)) {
+            return false;
+        }
+    }
+    tags.insert("onboarding".into(), "pending".into());
+    true
+}
+
+pub async fn greet(state: &State, user: &Entity) {
+    let Some(welcome) = &state.welcome else {
+        return;
+    };
+    let name = user.tags.get("name").cloned().unwrap_or_else(|| user.id.to_string());
+    command::direct_message(state, user.id, &trf("welcome.greeting", &[&name, &welcome.rules()])).await;
+}
+
+pub async fn check_post(
+    state: &State,
+    actor: Option<Uuid>,
+    tags: &BTreeMap<String, String>,
+    load: &[u8],
+) -> actix_web::Result<()> {
+    if state.welcome.is_none() || tags.get("kind").map(String::as_str) != Some("msg") {
+        return Ok(());
+    }
+    let Some(actor) = actor else {
+        return Ok(());
+    };
+    if !state.store.read(actor).await?.is_some_and(|user| pending(&user)) {
+        return Ok(());
+    }
+    if Commands::parse(load).is_some_and(|(name, _)| ALLOWED.contains(&name)) {
+        return Ok(());
+    }
+    command::ephemeral(state, actor, None, tr("welcome.pending")).await;
+    Err(actix_web::error::ErrorForbidden("accept the rules with /agree first"))
+}
+
+pub struct Agree;
+
+#[async_trait]
+impl Command for Agree {
+    fn help(&self) -> Help {
+        Help { category: Category::Account, role: Role::User, usage: "", about: "help.agree" }
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<String, String> {
+        let actor = ctx.actor.ok_or(tr("welcome.signin"))?;
+        let store = &*ctx.state.store;
+        let user = store.read(actor).await.map_err(|e| e.to_string())?
+            .ok_or(tr("status.nouser"))?;
+        if !pending(&user) {
+            return Ok(tr("welcome.nothing").into());
+        }
+        let mut tags = user.tags.clone();
+        tags.remove("onboarding");
+        let user = store.update(user.id, user.load.clone(), tags).await.map_err(|e| e.to_string())?;
+        ctx.state.hub.publish(&user);
+        Ok(tr("welcome.agreed").into())
+    }
+}
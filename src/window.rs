@@ -0,0 +1,103 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::time::Clock;
+
+pub enum Slot<V> {
+    Claimed,
+    Taken(V),
+    Full,
+}
+
+pub struct Window<K, V> {
+    items: DashMap<K, (V, Instant)>,
+    ttl: Duration,
+    cap: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Window<K, V> {
+    pub fn new(ttl: Duration, cap: usize, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            items: DashMap::new(),
+            ttl,
+            cap,
+            clock,
+        }
+    }
+
+    fn room_for(&self, key: &K) -> bool {
+        if self.items.len() < self.cap || self.items.contains_key(key) {
+            return true;
+        }
+        self.sweep();
+        self.items.len() < self.cap
+    }
+
+    pub fn claim(&self, key: K, value: V) -> Slot<V> {
+        if !self.room_for(&key) {
+            return Slot::Full;
+        }
+        match self.items.entry(key) {
+            Entry::Occupied(entry) if self.clock.since(entry.get().1) < self.ttl => Slot::Taken(entry.get().0.clone()),
+            Entry::Occupied(mut entry) => {
+                entry.insert((value, self.clock.instant()));
+                Slot::Claimed
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((value, self.clock.instant()));
+                Slot::Claimed
+            }
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) -> bool {
+        if !self.room_for(&key) {
+            return false;
+        }
+        self.items.insert(key, (value, self.clock.instant()));
+        true
+    }
+
+    pub fn take(&self, key: &K) -> Option<V> {
+        self.items.remove(key)
+            .filter(|(_, (_, at))| self.clock.since(*at) < self.ttl)
+            .map(|(_, (value, _))| value)
+    }
+
+    pub fn remove_if(&self, key: &K, f: impl FnOnce(&V) -> bool) {
+        self.items.remove_if(key, |_, (value, _)| f(value));
+    }
+
+    pub fn sweep(&self) -> usize {
+        let before = self.items.len();
+        self.items.retain(|_, (_, at)| self.clock.since(*at) < self.ttl);
+        before.saturating_sub(self.items.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::TokioClock;
+
+    #[tokio::test(start_paused = true)]
+    async fn entries_expire_after_ttl() {
+        let window = Window::new(Duration::from_secs(60), 2, Arc::new(TokioClock::new()));
+        assert!(matches!(window.claim("a", 1), Slot::Claimed));
+        assert!(matches!(window.claim("a", 2), Slot::Taken(1)));
+        assert!(window.insert("b", 3));
+        assert!(!window.insert("c", 4));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(matches!(window.claim("a", 5), Slot::Claimed));
+        assert_eq!(window.take(&"b"), None);
+        assert_eq!(window.sweep(), 0);
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(window.sweep(), 1);
+    }
+}
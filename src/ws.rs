@@ -1,22 +1,222 @@
 use actix_web::{web, HttpRequest, HttpResponse};
-use actix_ws;
+use actix_ws::{CloseCode, CloseReason};
 use futures_util::StreamExt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
 
+use crate::account;
 use crate::api::State;
 use crate::format;
+use crate::guard;
+use crate::hooks::Connection;
+use crate::log::Level;
 use crate::parse;
+use crate::pow;
+use crate::presence::Socket;
+use crate::store::Store;
+use crate::task;
+use crate::xlog;
+
+const HEARTBEAT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Deadlines {
+    pub subscribe: Duration,
+    pub client: Duration,
+    pub probe: Duration,
+}
+
+impl Deadlines {
+    pub const DIRECT: Deadlines = Deadlines { subscribe: Duration::from_secs(10), client: Duration::from_secs(30), probe: Duration::from_secs(2) };
+    pub const TOR: Deadlines = Deadlines { subscribe: Duration::from_secs(60), client: Duration::from_secs(120), probe: Duration::from_secs(10) };
+}
+const MAX_PENDING: usize = 256;
+
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+struct Pending;
+
+impl Pending {
+    fn acquire() -> Option<Pending> {
+        PENDING.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_PENDING).then_some(n + 1))
+            .ok()
+            .map(|_| Pending)
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        PENDING.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Beat {
+    Idle,
+    Ping,
+    Unsubscribed,
+    TimedOut,
+}
+
+struct Pulse {
+    opened: u64,
+    read: u64,
+    write: u64,
+    subscribed: bool,
+}
+
+impl Pulse {
+    fn check(&self, now: u64, deadlines: Deadlines) -> Beat {
+        if !self.subscribed && now.saturating_sub(self.opened) > deadlines.subscribe.as_millis() as u64 {
+            return Beat::Unsubscribed;
+        }
+        if now.saturating_sub(self.read) > deadlines.client.as_millis() as u64 {
+            return Beat::TimedOut;
+        }
+        if now.saturating_sub(self.write) < HEARTBEAT.as_millis() as u64 {
+            return Beat::Idle;
+        }
+        Beat::Ping
+    }
+}
 
 pub async fn handler(
     req: HttpRequest,
     stream: web::Payload,
     state: web::Data<State>,
 ) -> actix_web::Result<HttpResponse> {
+    if state.pow.enabled() && !pow::solution(&req).is_some_and(|s| state.pow.verify(&s)) {
+        return Ok(HttpResponse::Forbidden().body("proof of work required, see /challenge"));
+    }
+    let Some(pending) = Pending::acquire() else {
+        xlog!(Level::Warn, target: "ws", "too many unsubscribed sockets, refusing upgrade");
+        return Ok(HttpResponse::ServiceUnavailable().body("too many pending connections"));
+    };
+    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+    let actor = guard::check(&*state.store, auth_header, "subscribe", None).await?;
+    let session_id = guard::session_id(auth_header).and_then(Result::ok);
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let peer = req.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|| "-".into());
+    let conn = Connection { id: Uuid::new_v4(), peer: peer.clone(), session: session_id, actor };
+    state.hooks.connect(&state, &conn).await.map_err(actix_web::error::ErrorForbidden)?;
+    if let Some(recorder) = &state.recorder {
+        recorder.open_socket(conn.id, req.headers());
+    }
+    let recording = state.recorder.clone().map(|recorder| (recorder, conn.id));
+    let store = state.store.clone();
     let (response, session, mut msg_stream) = actix_ws::handle(&req, stream)?;
 
+    let clock = state.clock.clone();
+    let opened = clock.millis();
+    let last_write = Arc::new(AtomicU64::new(opened));
+    let last_read = Arc::new(AtomicU64::new(opened));
+    let subscribed = Arc::new(AtomicBool::new(false));
+    let deadlines = state.deadlines;
+
+    let mut beat = session.clone();
+    let (beat_write, beat_read, beat_subscribed) = (last_write.clone(), last_read.clone(), subscribed.clone());
+    let (draining, beat_clock) = (state.draining.clone(), clock.clone());
+    task::spawn_local(&format!("ws.heartbeat {}", peer), async move {
+        let mut interval = actix_web::rt::time::interval(HEARTBEAT);
+        loop {
+            interval.tick().await;
+            let now = beat_clock.millis();
+            if draining.load(Ordering::Relaxed) {
+                let reason = CloseReason { code: CloseCode::Restart, description: Some("server upgrading".into()) };
+                let _ = beat.close(Some(reason)).await;
+                break;
+            }
+            let pulse = Pulse {
+                opened,
+                read: beat_read.load(Ordering::Relaxed),
+                write: beat_write.load(Ordering::Relaxed),
+                subscribed: beat_subscribed.load(Ordering::Relaxed),
+            };
+            let verdict = pulse.check(now, deadlines);
+            if verdict == Beat::Unsubscribed {
+                xlog!(Level::Debug, target: "ws", "no subscription before deadline");
+                let _ = beat.close(None).await;
+                break;
+            }
+            if verdict == Beat::TimedOut {
+                xlog!(Level::Debug, target: "ws", "client timed out");
+                let _ = beat.close(None).await;
+                break;
+            }
+            if let Some(id) = session_id {
+                if !matches!(store.read(id).await, Ok(Some(_))) {
+                    xlog!(Level::Debug, target: "ws", "session {} revoked", id);
+                    let _ = beat.close(None).await;
+                    break;
+                }
+            }
+            if verdict == Beat::Idle {
+                continue;
+            }
+            if beat.ping(b"").await.is_err() {
+                break;
+            }
+            beat_write.store(now, Ordering::Relaxed);
+        }
+    });
+
     let hub = state.hub.clone();
-    actix_web::rt::spawn(async move {
+    let stats = state.stats.clone();
+    let state = state.clone();
+    stats.connect();
+    let mut link = None;
+    let reaped = Arc::new(Notify::new());
+    if let Some(actor) = actor {
+        let socket = Socket { user: actor, login: session_id, ip, last_read: last_read.clone(), session: Mutex::new(session.clone()), reaped: reaped.clone() };
+        state.online.join(conn.id, socket);
+        if let Some(ip) = ip {
+            let (state, id) = (state.clone(), conn.id);
+            task::spawn_local(&format!("ws.reap {}", peer), async move {
+                let reaped = state.online.reap(actor, ip, state.deadlines.probe, Some(id)).await;
+                if reaped > 0 {
+                    xlog!(Level::Info, target: "ws", "{} reconnected from {}, closed {} dead socket(s)", actor, ip, reaped);
+                }
+            });
+        }
+        if let Some(mail) = &state.mail {
+            mail.forget(actor);
+        }
+        let (id, mut rx) = hub.attach(actor);
+        link = Some(id);
+        let mut sender = session.clone();
+        let (last_write, stats, clock) = (last_write.clone(), stats.clone(), clock.clone());
+        task::spawn_local(&format!("ws.direct {}", peer), async move {
+            while let Some(entity) = rx.recv().await {
+                let text = format::entity(&entity);
+                stats.record_push(text.len());
+                let _ = sender.text(text).await;
+                last_write.store(clock.millis(), Ordering::Relaxed);
+            }
+        });
+    }
+    task::spawn_local(&format!("ws.read {}", peer), async move {
         let mut sub_id = None;
-        while let Some(Ok(msg)) = msg_stream.next().await {
+        let mut session = session;
+        let mut pending = Some(pending);
+        loop {
+            let msg = tokio::select! {
+                msg = msg_stream.next() => msg,
+                _ = reaped.notified() => {
+                    let reason = CloseReason { code: CloseCode::Policy, description: Some("replaced by a newer connection".into()) };
+                    let _ = actix_web::rt::time::timeout(HEARTBEAT, session.clone().close(Some(reason))).await;
+                    break;
+                }
+            };
+            let Some(Ok(msg)) = msg else {
+                break;
+            };
+            last_read.store(clock.millis(), Ordering::Relaxed);
+            if let Some((recorder, id)) = &recording {
+                recorder.frame(*id, &msg);
+            }
             match msg {
                 actix_ws::Message::Text(text) => {
                     let predicate = parse::predicate(&text);
@@ -26,24 +226,75 @@ pub async fn handler(
                     if let Some(id) = sub_id {
                         hub.unsubscribe(id);
                     }
+                    pending.take();
+                    subscribed.store(true, Ordering::Relaxed);
                     let (id, mut rx) = hub.subscribe(predicate);
+                    xlog!(Level::Debug, target: "ws", "subscription {} on {}", id, text);
                     sub_id = Some(id);
                     let mut sender = session.clone();
-                    actix_web::rt::spawn(async move {
+                    let last_write = last_write.clone();
+                    let stats = stats.clone();
+                    let clock = clock.clone();
+                    task::spawn_local(&format!("ws.push {}", id), async move {
                         while let Some(entity) = rx.recv().await {
+                            if account::secret(&entity) {
+                                continue;
+                            }
                             let text = format::entity(&entity);
+                            stats.record_push(text.len());
                             let _ = sender.text(text).await;
+                            last_write.store(clock.millis(), Ordering::Relaxed);
                         }
                     });
                 }
+                actix_ws::Message::Ping(bytes) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                    last_write.store(clock.millis(), Ordering::Relaxed);
+                }
                 actix_ws::Message::Close(_) => break,
                 _ => {}
             }
         }
         if let Some(id) = sub_id {
             hub.unsubscribe(id);
+            xlog!(Level::Debug, target: "ws", "subscription {} closed", id);
         }
+        stats.disconnect();
+        state.online.leave(conn.id);
+        if let Some((actor, id)) = actor.zip(link) {
+            hub.detach(actor, id);
+        }
+        state.hooks.disconnect(&state, &conn).await;
     });
 
     Ok(response)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::{Clock, TokioClock};
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_follows_the_clock() {
+        let clock = TokioClock::new();
+        let opened = clock.millis();
+        let mut pulse = Pulse { opened, read: opened, write: opened, subscribed: false };
+        assert_eq!(pulse.check(clock.millis(), Deadlines::DIRECT), Beat::Idle);
+
+        tokio::time::advance(HEARTBEAT).await;
+        assert_eq!(pulse.check(clock.millis(), Deadlines::DIRECT), Beat::Ping);
+
+        tokio::time::advance(Deadlines::DIRECT.subscribe).await;
+        assert_eq!(pulse.check(clock.millis(), Deadlines::DIRECT), Beat::Unsubscribed);
+
+        pulse.subscribed = true;
+        pulse.read = clock.millis();
+        pulse.write = clock.millis();
+        assert_eq!(pulse.check(clock.millis(), Deadlines::DIRECT), Beat::Idle);
+
+        tokio::time::advance(Deadlines::DIRECT.client + Duration::from_millis(1)).await;
+        assert_eq!(pulse.check(clock.millis(), Deadlines::DIRECT), Beat::TimedOut);
+    }
+}